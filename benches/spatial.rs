@@ -0,0 +1,131 @@
+use std::{
+  hint::black_box,
+  time::{Duration, Instant},
+};
+
+use boom::{EntityId, SpatialGrid};
+use glam::{DVec2, IVec2};
+use rand::Rng;
+
+///
+/// Times the grid against checking every entity, for a few crowd sizes.
+///
+/// Run it with cargo bench. It doesn't need a window.
+///
+fn main() {
+  // cargo test runs benches too, but only cargo bench passes --bench.
+  if !std::env::args().any(|arg| arg == "--bench") {
+    return;
+  }
+
+  let size = IVec2::new(64, 64);
+  let queries = 2000;
+  let mut rng = rand::thread_rng();
+
+  println!(
+    "{:>8} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10}",
+    "entities", "update", "radius", "radius all", "ray", "ray all", "nearest"
+  );
+
+  for count in [1000, 4000, 16000] {
+    let mut grid = SpatialGrid::new(size);
+    let mut entities: Vec<(EntityId, DVec2)> = (0..count)
+      .map(|index| {
+        let id = EntityId {
+          index,
+          generation: 0,
+        };
+        let position = DVec2::new(rng.gen_range(0.0..64.0), rng.gen_range(0.0..64.0));
+        grid.insert(id, position);
+        (id, position)
+      })
+      .collect();
+
+    let update = time_each(count as usize, || {
+      for (id, position) in &mut entities {
+        *position = (*position + DVec2::new(0.05, -0.03)).rem_euclid(DVec2::splat(64.0));
+        grid.insert(*id, *position);
+      }
+    });
+
+    let points: Vec<DVec2> = (0..queries)
+      .map(|_| DVec2::new(rng.gen_range(0.0..64.0), rng.gen_range(0.0..64.0)))
+      .collect();
+
+    let radius = time_each(queries, || {
+      for point in &points {
+        black_box(grid.query_radius(*point, 1.5));
+      }
+    });
+
+    // What every query would cost without the grid.
+    let radius_all = time_each(queries, || {
+      for point in &points {
+        black_box(
+          entities
+            .iter()
+            .filter(|(_, position)| position.distance_squared(*point) <= 1.5 * 1.5)
+            .count(),
+        );
+      }
+    });
+
+    // Same thing the bullets do, a thin circle around every point.
+    let hit = |from: DVec2, direction: DVec2, position: DVec2| {
+      let along = (position - from).dot(direction);
+      let miss = (position - from).length_squared() - along * along;
+      (along >= 0.0 && miss <= 0.09).then_some(along)
+    };
+
+    let ray = time_each(queries, || {
+      for (index, point) in points.iter().enumerate() {
+        let direction = DVec2::from_angle(index as f64);
+        black_box(grid.query_ray(*point, direction, 64.0, |_, position| {
+          hit(*point, direction, position)
+        }));
+      }
+    });
+
+    let ray_all = time_each(queries, || {
+      for (index, point) in points.iter().enumerate() {
+        let direction = DVec2::from_angle(index as f64);
+        black_box(
+          entities
+            .iter()
+            .filter_map(|(id, position)| Some((*id, hit(*point, direction, *position)?)))
+            .min_by(|a, b| a.1.total_cmp(&b.1)),
+        );
+      }
+    });
+
+    let nearest = time_each(queries, || {
+      for point in &points {
+        black_box(grid.nearest(*point, 64.0, |_| true));
+      }
+    });
+
+    println!(
+      "{:>8} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10}",
+      count,
+      format_time(update),
+      format_time(radius),
+      format_time(radius_all),
+      format_time(ray),
+      format_time(ray_all),
+      format_time(nearest)
+    );
+  }
+}
+
+///
+/// Runs the work once and returns how long it took per item.
+///
+fn time_each(items: usize, mut work: impl FnMut()) -> Duration {
+  let started = Instant::now();
+  work();
+  started.elapsed() / items.max(1) as u32
+}
+
+fn format_time(time: Duration) -> String {
+  format!("{} ns", time.as_nanos())
+}
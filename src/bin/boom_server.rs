@@ -0,0 +1,13 @@
+use boom::dedicated::{DedicatedServer, ServerOptions};
+
+fn main() {
+  // Nothing in here touches SDL, so it runs fine on a box without a screen or a sound card.
+  // Built with --no-default-features it doesn't even link it.
+  match DedicatedServer::new(ServerOptions::from_args()) {
+    Ok(server) => server.run(),
+    Err(e) => {
+      eprintln!("boom_server: {}", e);
+      std::process::exit(1);
+    }
+  }
+}
@@ -0,0 +1,689 @@
+use std::{
+  f64::consts::FRAC_PI_4,
+  fmt,
+  fs::File,
+  io::{self, BufReader},
+  path::{Path, PathBuf},
+  sync::Arc,
+  thread,
+  time::Duration,
+};
+
+use glam::DVec2;
+use sdl2::{
+  audio::{AudioCallback, AudioDevice, AudioSpecDesired},
+  Sdl,
+};
+
+use super::{
+  music::MusicTrack,
+  settings::AudioSettings,
+  sound::{music_path, SoundEvent, SOUND_NAMES},
+  world::{Map, World},
+};
+
+// Everything gets resampled to this when it loads so the mixer never has to.
+pub const MIX_RATE: i32 = 44100;
+// Past this many voices the oldest one gets cut off.
+const MAX_VOICES: usize = 32;
+// Cells away before something can't be heard at all.
+const HEARING_DISTANCE: f64 = 24.0;
+// How much of a sound gets through a wall.
+const OCCLUDED_GAIN: f64 = 0.35;
+// How much quieter something right behind you is than something right in front.
+const BEHIND_GAIN: f64 = 0.8;
+
+///
+/// Something went wrong loading a sound.
+///
+#[derive(Debug)]
+pub enum AudioError {
+  Io(PathBuf, io::Error),
+  Wav(PathBuf, hound::Error),
+  Ogg(PathBuf, lewton::VorbisError),
+  Invalid(PathBuf, String),
+}
+
+impl fmt::Display for AudioError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      AudioError::Io(path, e) => write!(f, "could not access {}: {}", path.display(), e),
+      AudioError::Wav(path, e) => write!(f, "could not decode {}: {}", path.display(), e),
+      AudioError::Ogg(path, e) => write!(f, "could not decode {}: {}", path.display(), e),
+      AudioError::Invalid(path, reason) => {
+        write!(f, "invalid sound {}: {}", path.display(), reason)
+      }
+    }
+  }
+}
+
+///
+/// A whole sound decoded into memory. Always mono and always at the mix rate,
+/// the panning is what makes it stereo.
+///
+pub struct Clip {
+  pub samples: Vec<f32>,
+}
+
+impl Clip {
+  ///
+  /// Loads a .wav or .ogg, going by the extension.
+  ///
+  pub fn load(path: &Path) -> Result<Self, AudioError> {
+    match path.extension().and_then(|extension| extension.to_str()) {
+      Some("wav") => Clip::load_wav(path),
+      Some("ogg") => Clip::load_ogg(path),
+      _ => Err(AudioError::Invalid(
+        path.to_path_buf(),
+        "only .wav and .ogg are supported".to_string(),
+      )),
+    }
+  }
+
+  fn load_wav(path: &Path) -> Result<Self, AudioError> {
+    let reader =
+      hound::WavReader::open(path).map_err(|e| AudioError::Wav(path.to_path_buf(), e))?;
+    let spec = reader.spec();
+
+    let samples = match spec.sample_format {
+      hound::SampleFormat::Float => reader.into_samples::<f32>().collect::<Result<Vec<_>, _>>(),
+      hound::SampleFormat::Int => {
+        let scale = 1.0 / (1i64 << (spec.bits_per_sample - 1)) as f32;
+        reader
+          .into_samples::<i32>()
+          .map(|sample| sample.map(|sample| sample as f32 * scale))
+          .collect()
+      }
+    }
+    .map_err(|e| AudioError::Wav(path.to_path_buf(), e))?;
+
+    Clip::from_interleaved(path, &samples, spec.channels as usize, spec.sample_rate)
+  }
+
+  fn load_ogg(path: &Path) -> Result<Self, AudioError> {
+    let file = File::open(path).map_err(|e| AudioError::Io(path.to_path_buf(), e))?;
+    let mut reader = lewton::inside_ogg::OggStreamReader::new(BufReader::new(file))
+      .map_err(|e| AudioError::Ogg(path.to_path_buf(), e))?;
+    let channels = reader.ident_hdr.audio_channels as usize;
+    let rate = reader.ident_hdr.audio_sample_rate;
+
+    let mut samples = Vec::new();
+    while let Some(packet) = reader
+      .read_dec_packet_itl()
+      .map_err(|e| AudioError::Ogg(path.to_path_buf(), e))?
+    {
+      samples.extend(packet.iter().map(|sample| *sample as f32 / 32768.0));
+    }
+
+    Clip::from_interleaved(path, &samples, channels, rate)
+  }
+
+  ///
+  /// Squashes every channel down into one and stretches it to the mix rate.
+  ///
+  fn from_interleaved(
+    path: &Path,
+    samples: &[f32],
+    channels: usize,
+    rate: u32,
+  ) -> Result<Self, AudioError> {
+    if channels == 0 || rate == 0 {
+      return Err(AudioError::Invalid(
+        path.to_path_buf(),
+        format!("{} channels at {} Hz", channels, rate),
+      ));
+    }
+
+    let mono: Vec<f32> = samples
+      .chunks_exact(channels)
+      .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+      .collect();
+
+    Ok(Clip {
+      samples: resample(&mono, rate, MIX_RATE as u32),
+    })
+  }
+
+  ///
+  /// Seconds of silence. Stands in for sounds that didn't load.
+  ///
+  pub fn silence(seconds: f64) -> Self {
+    Clip {
+      samples: vec![0.0; (seconds * MIX_RATE as f64) as usize],
+    }
+  }
+}
+
+///
+/// Linear interpolation. Not audiophile grade but good enough for gunshots.
+///
+fn resample(samples: &[f32], from: u32, to: u32) -> Vec<f32> {
+  if from == to || samples.is_empty() {
+    return samples.to_vec();
+  }
+
+  let step = from as f64 / to as f64;
+  let length = (samples.len() as f64 / step) as usize;
+  (0..length)
+    .map(|index| {
+      let position = index as f64 * step;
+      let before = position.floor() as usize;
+      let after = (before + 1).min(samples.len() - 1);
+      let blend = (position - before as f64) as f32;
+      samples[before] * (1.0 - blend) + samples[after] * blend
+    })
+    .collect()
+}
+
+///
+/// How loud a sound at source is in each ear, for someone at listener looking along direction.
+///
+/// Quieter the further away it is, a bit quieter behind you, and muffled if there's a wall
+/// in the way. Panning is equal power so things don't dip in the middle.
+///
+pub fn spatialize(listener: DVec2, direction: DVec2, source: DVec2, map: &Map) -> [f32; 2] {
+  let to_source = source - listener;
+  let distance = to_source.length();
+  if distance >= HEARING_DISTANCE {
+    return [0.0, 0.0];
+  }
+
+  let mut gain = (1.0 - distance / HEARING_DISTANCE).powi(2);
+  let mut pan = 0.0;
+
+  if distance > 0.01 {
+    let heading = to_source / distance;
+    let direction = direction.normalize_or_zero();
+    // Same way the camera plane points, so right on screen is right in your ears.
+    let right = DVec2::new(direction.y, -direction.x);
+    pan = heading.dot(right);
+
+    let behind = (-heading.dot(direction)).max(0.0);
+    gain *= 1.0 - (1.0 - BEHIND_GAIN) * behind;
+
+    if !map.line_of_sight(listener, source) {
+      gain *= OCCLUDED_GAIN;
+    }
+  }
+
+  let angle = (pan + 1.0) * FRAC_PI_4;
+  [(gain * angle.cos()) as f32, (gain * angle.sin()) as f32]
+}
+
+///
+/// One clip playing.
+///
+struct Voice {
+  clip: Arc<Clip>,
+  cursor: usize,
+  position: DVec2,
+  /// What the gains were at the end of the last buffer.
+  gains: [f32; 2],
+  /// What they should be by the end of the next one. They slide over instead of jumping
+  /// so turning around doesn't click.
+  target: [f32; 2],
+}
+
+///
+/// Lives on SDL's audio thread and adds every voice and music track together.
+///
+/// Boom only touches it through AudioDevice::lock, which holds the audio thread off.
+///
+pub struct Mixer {
+  voices: Vec<Voice>,
+  /// Usually just one, two while crossfading.
+  music: Vec<MusicTrack>,
+  channels: usize,
+  pub sfx_volume: f32,
+  pub music_volume: f32,
+  /// Frames mixed so far, so a headless run can tell the driver is actually pulling audio.
+  pub frames_mixed: u64,
+  /// The loudest sample so far.
+  pub peak: f32,
+}
+
+impl Mixer {
+  pub fn new(channels: usize, settings: &AudioSettings) -> Self {
+    Mixer {
+      voices: Vec::new(),
+      music: Vec::new(),
+      channels,
+      sfx_volume: settings.sfx_volume as f32,
+      music_volume: settings.music_volume as f32,
+      frames_mixed: 0,
+      peak: 0.0,
+    }
+  }
+}
+
+impl AudioCallback for Mixer {
+  type Channel = f32;
+
+  fn callback(&mut self, out: &mut [f32]) {
+    out.fill(0.0);
+    let channels = self.channels.max(1);
+    let frames = out.len() / channels;
+    if frames == 0 {
+      return;
+    }
+
+    for voice in &mut self.voices {
+      let remaining = voice.clip.samples.len().saturating_sub(voice.cursor);
+      let count = remaining.min(frames);
+
+      for (index, frame) in out.chunks_exact_mut(channels).take(count).enumerate() {
+        let sample = voice.clip.samples[voice.cursor + index] * self.sfx_volume;
+        let blend = index as f32 / frames as f32;
+        let left = voice.gains[0] + (voice.target[0] - voice.gains[0]) * blend;
+        let right = voice.gains[1] + (voice.target[1] - voice.gains[1]) * blend;
+
+        if channels == 1 {
+          frame[0] += sample * (left + right) * 0.5;
+        } else {
+          frame[0] += sample * left;
+          frame[1] += sample * right;
+        }
+      }
+
+      voice.cursor += count;
+      voice.gains = voice.target;
+    }
+    self
+      .voices
+      .retain(|voice| voice.cursor < voice.clip.samples.len());
+
+    for track in &mut self.music {
+      track.mix(out, channels, self.music_volume);
+    }
+    self.music.retain(|track| !track.finished());
+
+    for sample in out.iter_mut() {
+      self.peak = self.peak.max(sample.abs());
+      *sample = sample.clamp(-1.0, 1.0);
+    }
+    self.frames_mixed += frames as u64;
+  }
+}
+
+///
+/// Sound effects and music. Owns the SDL audio device and every loaded clip.
+///
+/// If there's no audio device the game just runs silent, nothing else has to care.
+///
+pub struct Audio {
+  device: Option<AudioDevice<Mixer>>,
+  clips: Vec<Arc<Clip>>,
+}
+
+impl Audio {
+  pub fn new(sdl: &Sdl, settings: &AudioSettings) -> Self {
+    let clips = load_sounds();
+
+    let device = sdl
+      .audio()
+      .and_then(|subsystem| {
+        let desired = AudioSpecDesired {
+          freq: Some(MIX_RATE),
+          channels: Some(2),
+          samples: Some(1024),
+        };
+        let device = subsystem.open_playback(None, &desired, |spec| {
+          if spec.freq != MIX_RATE {
+            eprintln!(
+              "boom: audio device runs at {} Hz instead of {}, sounds will be off pitch",
+              spec.freq, MIX_RATE
+            );
+          }
+          Mixer::new(spec.channels as usize, settings)
+        })?;
+        Ok(device)
+      })
+      .map_err(|e| eprintln!("boom: no audio, {}", e))
+      .ok();
+
+    if let Some(device) = &device {
+      device.resume();
+    }
+
+    Audio { device, clips }
+  }
+
+  pub fn set_volumes(&mut self, settings: &AudioSettings) {
+    if let Some(device) = &mut self.device {
+      let mut mixer = device.lock();
+      mixer.sfx_volume = settings.sfx_volume as f32;
+      mixer.music_volume = settings.music_volume as f32;
+    }
+  }
+
+  ///
+  /// Fades over to a different song, or to nothing. Asking for the song that's already
+  /// playing leaves it alone, so restarting a level doesn't restart the music.
+  ///
+  pub fn play_music(&mut self, path: Option<&Path>) {
+    let Some(device) = &mut self.device else {
+      return;
+    };
+
+    let playing = device
+      .lock()
+      .music
+      .iter()
+      .any(|track| Some(track.path.as_path()) == path && track.fade_target > 0.0);
+    if playing {
+      return;
+    }
+
+    // The decoder thread gets going before the lock, the audio thread shouldn't wait on it.
+    let track = path.map(MusicTrack::start);
+    let mut mixer = device.lock();
+    for track in &mut mixer.music {
+      track.fade_target = 0.0;
+    }
+    mixer.music.extend(track);
+  }
+
+  ///
+  /// Starts a sound. Gains come from spatialize and get kept up to date by update.
+  ///
+  pub fn play(&mut self, event: SoundEvent, gains: [f32; 2]) {
+    let Some(device) = &mut self.device else {
+      return;
+    };
+    let clip = self.clips[event.sound].clone();
+
+    let mut mixer = device.lock();
+    if mixer.voices.len() >= MAX_VOICES {
+      mixer.voices.remove(0);
+    }
+    mixer.voices.push(Voice {
+      clip,
+      cursor: 0,
+      position: event.position,
+      gains,
+      target: gains,
+    });
+  }
+
+  ///
+  /// Plays whatever the world made noise about this tick and re-aims everything already
+  /// playing at where the player is now.
+  ///
+  /// There's only one pair of speakers, so in split screen each sound is heard by whichever
+  /// player at the screen is closest to it.
+  ///
+  pub fn update(&mut self, world: &mut World) {
+    let listeners = &world.players[..world.local_player_count()];
+    let heard_by = |position: DVec2| {
+      let listener = listeners
+        .iter()
+        .min_by(|a, b| {
+          a.position
+            .distance_squared(position)
+            .total_cmp(&b.position.distance_squared(position))
+        })
+        .unwrap();
+      (listener.position, listener.direction)
+    };
+
+    for event in std::mem::take(&mut world.sounds) {
+      let (listener, direction) = heard_by(event.position);
+      let gains = spatialize(listener, direction, event.position, &world.map);
+      self.play(event, gains);
+    }
+
+    if let Some(device) = &mut self.device {
+      let mut mixer = device.lock();
+      for voice in &mut mixer.voices {
+        let (listener, direction) = heard_by(voice.position);
+        voice.target = spatialize(listener, direction, voice.position, &world.map);
+      }
+    }
+  }
+
+  ///
+  /// Cuts off every sound effect, for when the world gets swapped out from under them.
+  /// Music carries on, play_music is what changes that.
+  ///
+  pub fn stop_all(&mut self) {
+    if let Some(device) = &mut self.device {
+      device.lock().voices.clear();
+    }
+  }
+}
+
+///
+/// Loads everything in SOUND_NAMES, in order. A sound that won't load gets reported
+/// and stays quiet instead of taking the game down with it.
+///
+pub fn load_sounds() -> Vec<Arc<Clip>> {
+  SOUND_NAMES
+    .iter()
+    .map(|name| {
+      let wav = PathBuf::from(format!("sounds/{}.wav", name));
+      let path = if wav.exists() {
+        wav
+      } else {
+        PathBuf::from(format!("sounds/{}.ogg", name))
+      };
+      let clip = Clip::load(&path).unwrap_or_else(|e| {
+        eprintln!("boom: {}", e);
+        Clip::silence(0.0)
+      });
+      Arc::new(clip)
+    })
+    .collect()
+}
+
+///
+/// Plays every sound from a few places around a listener in a little room with a pillar
+/// in it, printing how loud each ear gets it. Then crossfades between two songs and
+/// reports what the mixer did.
+///
+/// Run it with --audio-test. It doesn't open a window, so with --audio-driver dummy or
+/// disk it works on a machine with no screen and no sound card.
+///
+pub fn self_test() {
+  let sdl = sdl2::init().unwrap_or_else(|e| panic!("{}", e));
+  let mut audio = Audio::new(
+    &sdl,
+    &AudioSettings {
+      sfx_volume: 1.0,
+      music_volume: 1.0,
+    },
+  );
+
+  let data = (0..9)
+    .map(|x| {
+      (0..9)
+        .map(|y| {
+          let edge = x == 0 || y == 0 || x == 8 || y == 8;
+          // The pillar, right in front of the listener.
+          let pillar = (x, y) == (6, 4);
+          (edge || pillar) as i32
+        })
+        .collect()
+    })
+    .collect();
+  let map = Map::new(data, Vec::new(), Vec::new());
+
+  let listener = DVec2::new(4.5, 4.5);
+  let direction = DVec2::new(1.0, 0.0);
+  let places = [
+    ("ahead, behind the pillar", DVec2::new(7.5, 4.5)),
+    ("right", DVec2::new(4.5, 3.5)),
+    ("left", DVec2::new(4.5, 5.5)),
+    ("behind", DVec2::new(2.5, 4.5)),
+    ("far ahead left", DVec2::new(7.5, 7.5)),
+    ("on top", listener),
+  ];
+
+  for (sound, name) in SOUND_NAMES.iter().enumerate() {
+    for (label, position) in places {
+      let gains = spatialize(listener, direction, position, &map);
+      println!(
+        "{:>10} {:<24} left {:.3} right {:.3}",
+        name, label, gains[0], gains[1]
+      );
+      audio.play(SoundEvent { sound, position }, gains);
+    }
+    thread::sleep(Duration::from_millis(200));
+  }
+
+  audio.play_music(Some(&music_path("hangar.wav")));
+  thread::sleep(Duration::from_millis(1000));
+  audio.play_music(Some(&music_path("warehouse.wav")));
+  thread::sleep(Duration::from_millis(1000));
+
+  match &mut audio.device {
+    Some(device) => {
+      let mixer = device.lock();
+      println!(
+        "mixed {} frames, peak {:.3}, {} voices and {} songs still playing",
+        mixer.frames_mixed,
+        mixer.peak,
+        mixer.voices.len(),
+        mixer.music.len()
+      );
+    }
+    None => println!("no audio device, nothing was mixed"),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  ///
+  /// An empty room, 12 cells square, with a wall down the middle from y 6 to 11.
+  ///
+  fn room() -> Map {
+    let mut data = vec![vec![0; 12]; 12];
+    for (x, row) in data.iter_mut().enumerate() {
+      for (y, cell) in row.iter_mut().enumerate() {
+        let edge = x == 0 || y == 0 || x == 11 || y == 11;
+        let divider = x == 6 && y >= 6;
+        if edge || divider {
+          *cell = 1;
+        }
+      }
+    }
+    Map::new(data, Vec::new(), Vec::new())
+  }
+
+  fn loudness(gains: [f32; 2]) -> f32 {
+    (gains[0] * gains[0] + gains[1] * gains[1]).sqrt()
+  }
+
+  const EAST: DVec2 = DVec2::X;
+
+  #[test]
+  fn pans_towards_the_side_its_on() {
+    let map = room();
+    let listener = DVec2::new(3.5, 3.5);
+    // Facing east, the camera plane and so the right ear point along -y.
+    let right = spatialize(listener, EAST, DVec2::new(3.5, 1.5), &map);
+    let left = spatialize(listener, EAST, DVec2::new(3.5, 5.5), &map);
+    assert!(right[1] > right[0], "{:?}", right);
+    assert!(left[0] > left[1], "{:?}", left);
+
+    let ahead = spatialize(listener, EAST, DVec2::new(5.5, 3.5), &map);
+    assert!((ahead[0] - ahead[1]).abs() < 1e-6, "{:?}", ahead);
+  }
+
+  #[test]
+  fn behind_is_quieter_than_ahead() {
+    let map = room();
+    let listener = DVec2::new(3.5, 3.5);
+    let ahead = spatialize(listener, EAST, DVec2::new(5.5, 3.5), &map);
+    let behind = spatialize(listener, EAST, DVec2::new(1.5, 3.5), &map);
+    let ratio = loudness(behind) / loudness(ahead);
+    assert!((ratio - BEHIND_GAIN as f32).abs() < 1e-5, "{}", ratio);
+  }
+
+  #[test]
+  fn walls_muffle_and_distance_silences() {
+    let map = room();
+    let listener = DVec2::new(4.5, 8.5);
+    let open = spatialize(listener, EAST, DVec2::new(4.5, 4.5), &map);
+    // Same distance, but through the divider, and facing the same way relative to it.
+    let walled = spatialize(listener, DVec2::Y, DVec2::new(8.5, 8.5), &map);
+    let open_facing = spatialize(listener, DVec2::NEG_Y, DVec2::new(4.5, 4.5), &map);
+    assert!(loudness(open) > 0.0);
+    let ratio = loudness(walled) / loudness(open_facing);
+    assert!((ratio - OCCLUDED_GAIN as f32).abs() < 1e-5, "{}", ratio);
+
+    let far = spatialize(
+      listener,
+      EAST,
+      listener + DVec2::new(HEARING_DISTANCE, 0.0),
+      &map,
+    );
+    assert_eq!(far, [0.0, 0.0]);
+  }
+
+  fn voice(samples: Vec<f32>, gains: [f32; 2]) -> Voice {
+    Voice {
+      clip: Arc::new(Clip { samples }),
+      cursor: 0,
+      position: DVec2::ZERO,
+      gains,
+      target: gains,
+    }
+  }
+
+  fn mixer(channels: usize) -> Mixer {
+    let settings = AudioSettings {
+      sfx_volume: 1.0,
+      music_volume: 1.0,
+    };
+    Mixer::new(channels, &settings)
+  }
+
+  #[test]
+  fn mixes_voices_into_each_ear() {
+    let mut mixer = mixer(2);
+    mixer.voices.push(voice(vec![0.25; 100], [1.0, 0.0]));
+    mixer.voices.push(voice(vec![0.5; 100], [0.0, 0.5]));
+
+    let mut out = vec![9.0; 64 * 2];
+    mixer.callback(&mut out);
+    for frame in out.chunks_exact(2) {
+      assert_eq!(frame, [0.25, 0.25]);
+    }
+    assert_eq!(mixer.frames_mixed, 64);
+    assert_eq!(mixer.voices.len(), 2);
+
+    // The rest of them, then silence once they've run out.
+    mixer.callback(&mut out);
+    assert_eq!(out[(36 - 1) * 2], 0.25);
+    assert_eq!(out[36 * 2], 0.0);
+    assert!(mixer.voices.is_empty());
+  }
+
+  #[test]
+  fn mono_gets_both_ears_averaged() {
+    let mut mixer = mixer(1);
+    mixer.voices.push(voice(vec![0.5; 10], [1.0, 0.0]));
+    let mut out = vec![0.0; 10];
+    mixer.callback(&mut out);
+    assert!(out.iter().all(|sample| *sample == 0.25), "{:?}", out);
+  }
+
+  #[test]
+  fn loud_mixes_get_clamped() {
+    let mut mixer = mixer(2);
+    mixer.sfx_volume = 0.5;
+    for _ in 0..4 {
+      mixer.voices.push(voice(vec![1.0; 16], [1.0, 1.0]));
+    }
+    mixer.voices.push(voice(vec![-1.0; 16], [0.0, 1.0]));
+
+    let mut out = vec![0.0; 16 * 2];
+    mixer.callback(&mut out);
+    for frame in out.chunks_exact(2) {
+      assert_eq!(frame, [1.0, 1.0]);
+    }
+    assert_eq!(mixer.peak, 2.0);
+  }
+}
@@ -0,0 +1,223 @@
+use glam::{DVec2, IVec2};
+use sdl2::keyboard::Keycode;
+
+use super::{
+  framebuffer::FrameBuffer, renderer::wall_color, win_handler::WinHandler, world::World,
+};
+
+const MIN_VIEW_CELLS: f64 = 4.0;
+const MAX_VIEW_CELLS: f64 = 96.0;
+// How many cells across the minimap is.
+const MINIMAP_CELLS: f64 = 12.0;
+
+///
+/// The map overlay. Either a little minimap in the corner or the whole screen.
+///
+/// M toggles the minimap, Tab toggles the full screen map, F toggles the fog of war.
+/// In the full screen map the arrow keys pan, Page Up and Page Down zoom and Home recenters.
+///
+pub struct Automap {
+  pub minimap: bool,
+  pub full: bool,
+  /// Only show walls that the raycaster has actually hit.
+  pub fog: bool,
+  pan: DVec2,
+  // How many cells fit top to bottom in the full screen map.
+  view_cells: f64,
+}
+
+impl Automap {
+  pub fn new() -> Self {
+    Automap {
+      minimap: false,
+      full: false,
+      fog: true,
+      pan: DVec2::ZERO,
+      view_cells: 24.0,
+    }
+  }
+
+  pub fn handle_input(&mut self, window: &WinHandler, delta: f64) {
+    if window.key_pressed(Keycode::M) {
+      self.minimap = !self.minimap;
+    }
+    if window.key_pressed(Keycode::Tab) {
+      self.full = !self.full;
+      self.pan = DVec2::ZERO;
+    }
+    if window.key_pressed(Keycode::F) {
+      self.fog = !self.fog;
+    }
+
+    if !self.full {
+      return;
+    }
+
+    let pan_speed = self.view_cells * 0.5 * delta;
+    if window.key_down(Keycode::Left) {
+      self.pan.x -= pan_speed;
+    }
+    if window.key_down(Keycode::Right) {
+      self.pan.x += pan_speed;
+    }
+    if window.key_down(Keycode::Up) {
+      self.pan.y -= pan_speed;
+    }
+    if window.key_down(Keycode::Down) {
+      self.pan.y += pan_speed;
+    }
+    if window.key_down(Keycode::PageUp) {
+      self.view_cells = (self.view_cells / (1.0 + delta * 2.0)).max(MIN_VIEW_CELLS);
+    }
+    if window.key_down(Keycode::PageDown) {
+      self.view_cells = (self.view_cells * (1.0 + delta * 2.0)).min(MAX_VIEW_CELLS);
+    }
+    if window.key_pressed(Keycode::Home) {
+      self.pan = DVec2::ZERO;
+    }
+  }
+
+  ///
+  /// Draws the map the way players[view] sees it. Split screen gets one each.
+  ///
+  pub fn draw(&self, frame: &mut FrameBuffer, world: &World, view: usize) {
+    let size = frame.size;
+
+    if self.full {
+      frame.darken_rect(IVec2::ZERO, size);
+      let pixels_per_cell = size.y as f64 / self.view_cells;
+      self.draw_map(frame, world, view, IVec2::ZERO, size, pixels_per_cell);
+    } else if self.minimap {
+      let side = size.x.min(size.y) / 3;
+      let margin = (size.y / 100).max(2);
+      let position = IVec2::new(size.x - side - margin, margin);
+      let area = IVec2::splat(side);
+
+      frame.darken_rect(position, area);
+      let pixels_per_cell = side as f64 / MINIMAP_CELLS;
+      self.draw_map(frame, world, view, position, area, pixels_per_cell);
+    }
+  }
+
+  ///
+  /// Draws the map into a rectangle of the screen, centered on the player unless the full
+  /// map has been panned somewhere else.
+  ///
+  fn draw_map(
+    &self,
+    frame: &mut FrameBuffer,
+    world: &World,
+    view: usize,
+    position: IVec2,
+    size: IVec2,
+    pixels_per_cell: f64,
+  ) {
+    frame.set_clip(position, size);
+
+    let viewer = &world.players[view];
+    let center = if self.full {
+      viewer.position + self.pan
+    } else {
+      viewer.position
+    };
+
+    let middle = position.as_dvec2() + size.as_dvec2() / 2.0;
+    let to_screen = |point: DVec2| {
+      (middle + (point - center) * pixels_per_cell)
+        .floor()
+        .as_ivec2()
+    };
+
+    let map = &world.map;
+    for x in map.min.x..map.max.x {
+      for y in map.min.y..map.max.y {
+        let cell = map.data[x as usize][y as usize];
+        if cell == 0 || (self.fog && !map.revealed[x as usize][y as usize]) {
+          continue;
+        }
+
+        let top_left = to_screen(DVec2::new(x as f64, y as f64));
+        let bottom_right = to_screen(DVec2::new(x as f64 + 1.0, y as f64 + 1.0));
+        let [r, g, b] = wall_color(cell);
+        frame.fill_rect(top_left, bottom_right - top_left, [r / 2, g / 2, b / 2]);
+      }
+    }
+
+    // Locked doors show their key color so you know which key to go looking for.
+    for door in &map.doors {
+      let cell = door.cell;
+      let closed = map.data[cell.x as usize][cell.y as usize] != 0;
+      let seen = !self.fog || map.revealed[cell.x as usize][cell.y as usize];
+      if let (Some(key), true, true) = (door.key, closed, seen) {
+        let top_left = to_screen(cell.as_dvec2());
+        let bottom_right = to_screen(cell.as_dvec2() + DVec2::ONE);
+        frame.fill_rect(top_left, bottom_right - top_left, key.color());
+      }
+    }
+
+    for exit in &map.exits {
+      if self.fog && !map.revealed[exit.x as usize][exit.y as usize] {
+        continue;
+      }
+      let top_left = to_screen(exit.as_dvec2());
+      let bottom_right = to_screen(exit.as_dvec2() + DVec2::ONE);
+      frame.fill_rect(top_left, bottom_right - top_left, [40, 160, 60]);
+    }
+
+    let dot_size = (pixels_per_cell / 4.0).max(2.0) as i32;
+    let draw_dot = |frame: &mut FrameBuffer, point: DVec2, color: [u8; 3]| {
+      frame.fill_rect(
+        to_screen(point) - IVec2::splat(dot_size / 2),
+        IVec2::splat(dot_size),
+        color,
+      );
+    };
+
+    // With the fog on, mobs only show up while you can see them. Revealed cells stay
+    // revealed, so going by those would give away whatever wandered in later.
+    let entities = &world.entities;
+    for position in entities.mobs().filter_map(|id| entities.position(id)) {
+      if !self.fog || map.line_of_sight(viewer.position, position) {
+        draw_dot(frame, position, [255, 60, 60]);
+      }
+    }
+    for (id, _) in entities.bullets.iter() {
+      if let Some(position) = entities.position(id) {
+        draw_dot(frame, position, [255, 255, 80]);
+      }
+    }
+    for (id, _) in entities.pickups.iter() {
+      let Some(position) = entities.position(id) else {
+        continue;
+      };
+      let cell = position.floor().as_ivec2();
+      if !self.fog || map.revealed[cell.x as usize][cell.y as usize] {
+        draw_dot(frame, position, [80, 220, 220]);
+      }
+    }
+
+    // The view cone comes straight from the camera plane, so it matches the FOV.
+    let player = viewer.position;
+    let direction = viewer.direction;
+    let plane = viewer.plane;
+    let cone_length = 3.0;
+    frame.draw_line(
+      to_screen(player),
+      to_screen(player + (direction - plane) * cone_length),
+      [90, 90, 140],
+    );
+    frame.draw_line(
+      to_screen(player),
+      to_screen(player + (direction + plane) * cone_length),
+      [90, 90, 140],
+    );
+    frame.draw_line(
+      to_screen(player),
+      to_screen(player + direction),
+      [80, 255, 120],
+    );
+    draw_dot(frame, player, [80, 255, 120]);
+
+    frame.clear_clip();
+  }
+}
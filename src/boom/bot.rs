@@ -0,0 +1,586 @@
+use glam::{DVec2, IVec2};
+use rand::Rng;
+
+use super::{
+  player_input::PlayerInput,
+  world::{ItemKind, Player, World, MAX_HEALTH},
+};
+
+// More than this and the spawn points run out anyway.
+pub const MAX_BOTS: usize = 16;
+// How far a bot will walk for something, in steps. Doors cost more than this, so bots
+// keep to the open parts of a level.
+const MAX_PATH_STEPS: u32 = 96;
+// Seconds between making up its mind about where to go.
+const PLAN_TIME: f64 = 1.0;
+// How close to the middle of a cell counts as there.
+const WAYPOINT_REACH: f64 = 0.25;
+// How far away somebody can be and still get shot at, in cells.
+const SIGHT_RANGE: f64 = 24.0;
+// Radians off target it's happy to shoot at.
+const FIRE_CONE: f64 = 0.08;
+// Worst aim there is, in radians either side, for a bot with no skill at all.
+const MAX_AIM_ERROR: f64 = 0.3;
+// Seconds before the aim wanders off somewhere else.
+const AIM_WANDER_TIME: f64 = 0.4;
+// How much chasing somebody down is worth next to picking up a weapon it hasn't got.
+const HUNT_WANT: f64 = 1.0;
+// Seconds of pushing against something before trying another way.
+const STUCK_TIME: f64 = 0.5;
+
+///
+/// One computer controlled deathmatch player. It doesn't touch the world, it just
+/// decides what a human would have pressed this tick.
+///
+/// Skill goes from 0 to 1. Low skill bots take longer to start shooting, turn slowly,
+/// aim all over the place and hardly ever dodge.
+///
+pub struct Bot {
+  /// The id of the player it's driving.
+  pub player: u32,
+  pub name: String,
+  pub skill: f64,
+  /// Cells left to walk through, the next one last.
+  path: Vec<IVec2>,
+  /// Counts down to planning a new path.
+  plan_time: f64,
+  /// Who it's shooting at, and how long it's been able to see them for.
+  target: Option<u32>,
+  seen_for: f64,
+  /// Radians it's aiming off by. It wanders around every AIM_WANDER_TIME.
+  aim_error: f64,
+  aim_time: f64,
+  /// -1 strafes left, 1 right, 0 not at all, for strafe_time seconds.
+  strafe: f64,
+  strafe_time: f64,
+  last_position: DVec2,
+  stuck_time: f64,
+}
+
+impl Bot {
+  pub fn new(player: u32, name: &str, skill: f64) -> Self {
+    Bot {
+      player,
+      name: name.to_string(),
+      skill: skill.clamp(0.0, 1.0),
+      path: Vec::new(),
+      plan_time: 0.0,
+      target: None,
+      seen_for: 0.0,
+      aim_error: 0.0,
+      aim_time: 0.0,
+      strafe: 0.0,
+      strafe_time: 0.0,
+      last_position: DVec2::ZERO,
+      stuck_time: 0.0,
+    }
+  }
+
+  /// Seconds somebody has to be in sight before it starts shooting.
+  fn reaction_time(&self) -> f64 {
+    0.7 - 0.6 * self.skill
+  }
+
+  /// Radians per second it can turn.
+  fn turn_speed(&self) -> f64 {
+    2.0 + 8.0 * self.skill
+  }
+
+  ///
+  /// What to press this tick, for the player at index.
+  ///
+  pub fn think(&mut self, world: &World, index: usize, delta: f64) -> PlayerInput {
+    let me = &world.players[index];
+    let mut input = PlayerInput::default();
+    if !me.alive {
+      // Respawning puts it somewhere else entirely.
+      self.path.clear();
+      self.plan_time = 0.0;
+      self.target = None;
+      self.stuck_time = 0.0;
+      return input;
+    }
+
+    self.plan_time -= delta;
+    if self.plan_time <= 0.0 || self.path.is_empty() {
+      self.plan_time = PLAN_TIME;
+      self.plan(world, me);
+    }
+    while let Some(next) = self.path.last() {
+      if cell_center(*next).distance(me.position) > WAYPOINT_REACH {
+        break;
+      }
+      self.path.pop();
+    }
+    let heading = self
+      .path
+      .last()
+      .map(|next| (cell_center(*next) - me.position).normalize_or_zero())
+      .unwrap_or(DVec2::ZERO);
+
+    let target = find_target(world, me);
+    if target.map(|enemy| enemy.id) != self.target {
+      self.target = target.map(|enemy| enemy.id);
+      self.seen_for = 0.0;
+    }
+
+    let mut wanted_move = heading;
+    match target {
+      Some(enemy) => {
+        self.seen_for += delta;
+        self.wander_aim(delta);
+        let aim = DVec2::from_angle(self.aim_error)
+          .rotate((enemy.position - me.position).normalize_or_zero());
+        let off_by = self.turn_towards(&mut input, me, aim, delta);
+        input.fire = self.seen_for >= self.reaction_time() && off_by < FIRE_CONE;
+
+        self.do_strafing(delta);
+        wanted_move += me.plane.normalize_or_zero() * self.strafe;
+      }
+      None => {
+        self.turn_towards(&mut input, me, heading, delta);
+      }
+    }
+    steer(&mut input, me, wanted_move);
+
+    // Walls and other players get in the way sometimes. Hop and try again.
+    let moving = input.forward || input.backward || input.left || input.right;
+    if moving && me.position.distance(self.last_position) < 0.01 {
+      self.stuck_time += delta;
+    } else {
+      self.stuck_time = 0.0;
+    }
+    if self.stuck_time > STUCK_TIME {
+      self.stuck_time = 0.0;
+      self.path.clear();
+      self.plan_time = 0.0;
+      input.jump = true;
+    }
+    self.last_position = me.position;
+
+    input.wheel = pick_weapon(world, me);
+    input
+  }
+
+  ///
+  /// Picks whatever's most worth walking to and finds the way there.
+  ///
+  fn plan(&mut self, world: &World, me: &Player) {
+    let from = me.position.floor().as_ivec2();
+    let steps = world
+      .map
+      .flood_fill(from, MAX_PATH_STEPS, MAX_PATH_STEPS + 1);
+
+    // Near things are worth more than far ones. The 4 stops it from dithering between
+    // something right here and something good just over there.
+    let mut best: Option<(f64, IVec2)> = None;
+    let mut consider = |cell: IVec2, want: f64| {
+      let Some(distance) = steps_at(&steps, cell) else {
+        return;
+      };
+      let score = want / (distance as f64 + 4.0);
+      if want > 0.0 && best.map_or(true, |(best_score, _)| score > best_score) {
+        best = Some((score, cell));
+      }
+    };
+
+    let entities = &world.entities;
+    for (id, pickup) in entities.pickups.iter() {
+      if let Some(position) = entities.position(id) {
+        consider(
+          position.floor().as_ivec2(),
+          item_want(world, me, &pickup.kind),
+        );
+      }
+    }
+    for enemy in world.players.iter() {
+      if enemy.alive && enemy.id != me.id {
+        consider(enemy.position.floor().as_ivec2(), HUNT_WANT);
+      }
+    }
+
+    // Nothing to do, so go for a wander.
+    let goal = best.map(|(_, cell)| cell).or_else(|| {
+      let spawn = world
+        .spawns
+        .get(rand::thread_rng().gen_range(0..world.spawns.len().max(1)))?;
+      Some(spawn.floor().as_ivec2())
+    });
+
+    self.path.clear();
+    let Some(mut cell) = goal.filter(|goal| steps_at(&steps, *goal).is_some()) else {
+      return;
+    };
+    // Walks back downhill from the goal, which leaves the path next step last.
+    self.path.push(cell);
+    while let Some(distance @ 1..) = steps_at(&steps, cell) {
+      let Some(back) = [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y]
+        .into_iter()
+        .map(|offset| cell + offset)
+        .find(|next| steps_at(&steps, *next) == Some(distance - 1))
+      else {
+        break;
+      };
+      cell = back;
+      if distance > 1 {
+        self.path.push(cell);
+      }
+    }
+  }
+
+  ///
+  /// Turns as far towards a direction as it can this tick. Returns how many radians
+  /// are left to go.
+  ///
+  fn turn_towards(&self, input: &mut PlayerInput, me: &Player, wanted: DVec2, delta: f64) -> f64 {
+    if wanted == DVec2::ZERO || me.direction == DVec2::ZERO {
+      return f64::INFINITY;
+    }
+    // Angles go anticlockwise, turning right goes clockwise.
+    let angle = me.direction.angle_between(wanted);
+    let max_turn = self.turn_speed() * delta;
+    input.turn = (-angle).clamp(-max_turn, max_turn);
+    angle.abs() - input.turn.abs()
+  }
+
+  fn wander_aim(&mut self, delta: f64) {
+    self.aim_time -= delta;
+    if self.aim_time > 0.0 {
+      return;
+    }
+    self.aim_time = AIM_WANDER_TIME;
+    let max_error = MAX_AIM_ERROR * (1.0 - self.skill);
+    self.aim_error = rand::thread_rng().gen_range(-max_error..=max_error);
+  }
+
+  fn do_strafing(&mut self, delta: f64) {
+    self.strafe_time -= delta;
+    if self.strafe_time > 0.0 {
+      return;
+    }
+    let mut rng = rand::thread_rng();
+    self.strafe_time = rng.gen_range(0.3..1.2);
+    self.strafe = if rng.gen_bool(self.skill) {
+      if rng.gen_bool(0.5) {
+        1.0
+      } else {
+        -1.0
+      }
+    } else {
+      0.0
+    };
+  }
+}
+
+///
+/// All the bots in a game. Each one is a player in the world like anybody else, this
+/// just does their thinking.
+///
+#[derive(Default)]
+pub struct Bots {
+  pub bots: Vec<Bot>,
+  // Keeps names unique even after some have been removed.
+  next_number: u32,
+}
+
+impl Bots {
+  ///
+  /// Puts a new bot into the game. Returns its name.
+  ///
+  pub fn add(&mut self, world: &mut World, skill: f64) -> String {
+    let name = loop {
+      self.next_number += 1;
+      let name = format!("bot {}", self.next_number);
+      if !world.players.iter().any(|player| player.name == name) {
+        break name;
+      }
+    };
+    let index = world.add_player(&name);
+    self
+      .bots
+      .push(Bot::new(world.players[index].id, &name, skill));
+    name
+  }
+
+  ///
+  /// Takes the newest bot out of the game. Returns its name, None if there weren't any.
+  ///
+  pub fn remove(&mut self, world: &mut World) -> Option<String> {
+    let bot = self.bots.pop()?;
+    world.remove_player(bot.player);
+    Some(bot.name)
+  }
+
+  ///
+  /// Moves every bot over to a new world, for a level change. They start from scratch.
+  ///
+  pub fn rejoin(&mut self, world: &mut World) {
+    for bot in &mut self.bots {
+      let index = world.add_player(&bot.name);
+      *bot = Bot::new(world.players[index].id, &bot.name, bot.skill);
+    }
+  }
+
+  ///
+  /// What every bot presses this tick, as (player index, input). Bots whose player
+  /// isn't in the world any more are skipped.
+  ///
+  pub fn think(&mut self, world: &World, delta: f64) -> Vec<(usize, PlayerInput)> {
+    self
+      .bots
+      .iter_mut()
+      .filter_map(|bot| {
+        let index = world.player_index(bot.player)?;
+        Some((index, bot.think(world, index, delta)))
+      })
+      .collect()
+  }
+}
+
+///
+/// How much a bot wants an item. 0 is not at all, an unowned weapon is 3.
+///
+fn item_want(world: &World, me: &Player, kind: &ItemKind) -> f64 {
+  match kind {
+    ItemKind::Health(_) => (MAX_HEALTH - me.health).max(0) as f64 / 25.0,
+    ItemKind::Ammo(ammo, _) => 1.0 - me.ammo_of(*ammo) as f64 / ammo.max() as f64,
+    ItemKind::Weapon(id) if !me.weapons.contains(id) && world.weapons.get(id).is_some() => 3.0,
+    ItemKind::Weapon(id) => match world.weapons.get(id) {
+      Some(weapon) if me.ammo_of(weapon.ammo) < weapon.ammo.max() => 0.3,
+      _ => 0.0,
+    },
+    ItemKind::Key(_) => 0.0,
+  }
+}
+
+///
+/// The nearest living player it can see, if they're close enough to shoot at.
+///
+fn find_target<'a>(world: &'a World, me: &Player) -> Option<&'a Player> {
+  world
+    .players
+    .iter()
+    .filter(|enemy| enemy.alive && enemy.id != me.id)
+    .map(|enemy| (enemy, enemy.position.distance(me.position)))
+    .filter(|(enemy, distance)| {
+      *distance <= SIGHT_RANGE && world.map.line_of_sight(me.position, enemy.position)
+    })
+    .min_by(|(_, a), (_, b)| a.total_cmp(b))
+    .map(|(enemy, _)| enemy)
+}
+
+///
+/// The mouse wheel clicks that get it to the hardest hitting weapon it has ammo for.
+///
+fn pick_weapon(world: &World, me: &Player) -> i32 {
+  let owned = world.weapons.sorted(&me.weapons);
+  let firepower = |id: &String| {
+    let weapon = world.weapons.get(id)?;
+    if me.ammo_of(weapon.ammo) < weapon.ammo_per_shot {
+      return None;
+    }
+    Some(weapon.damage as f64 * weapon.pellets as f64 * weapon.fire_rate)
+  };
+
+  let best = owned
+    .iter()
+    .enumerate()
+    .filter_map(|(index, id)| Some((index, firepower(id)?)))
+    .max_by(|(_, a), (_, b)| a.total_cmp(b));
+  let current = owned.iter().position(|id| **id == me.weapon);
+  match (best, current) {
+    // The wheel goes back through the list when it's positive.
+    (Some((best, _)), Some(current)) => current as i32 - best as i32,
+    _ => 0,
+  }
+}
+
+///
+/// Presses whichever movement keys head the player the wanted way, given which way
+/// they're facing.
+///
+fn steer(input: &mut PlayerInput, me: &Player, wanted: DVec2) {
+  let wanted = wanted.normalize_or_zero();
+  if wanted == DVec2::ZERO {
+    return;
+  }
+  let ahead = me.direction.normalize_or_zero().dot(wanted);
+  let right = me.plane.normalize_or_zero().dot(wanted);
+  // About 67 degrees either side, so diagonals press two keys.
+  input.forward = ahead > 0.38;
+  input.backward = ahead < -0.38;
+  input.right = right > 0.38;
+  input.left = right < -0.38;
+}
+
+fn cell_center(cell: IVec2) -> DVec2 {
+  cell.as_dvec2() + 0.5
+}
+
+///
+/// Looks a cell up in what Map::flood_fill came back with.
+///
+fn steps_at(steps: &[Vec<Option<u32>>], cell: IVec2) -> Option<u32> {
+  let x = usize::try_from(cell.x).ok()?;
+  let y = usize::try_from(cell.y).ok()?;
+  *steps.get(x)?.get(y)?
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::boom::{
+    level::{Level, Rotation},
+    settings::Settings,
+    weapon::{AmmoType, Weapons},
+  };
+
+  const DELTA: f64 = 1.0 / 30.0;
+
+  ///
+  /// A deathmatch on the first level of the shipped rotation, with nobody in it.
+  ///
+  fn deathmatch() -> World {
+    let rotation = Rotation::load(&Rotation::default_path()).unwrap();
+    let level = Level::load(&rotation.levels[0]).unwrap();
+    let weapons = Weapons::load(&Weapons::default_path()).unwrap();
+    World::new_deathmatch(&level, &Settings::default(), &weapons)
+  }
+
+  #[test]
+  fn bots_soak() {
+    let mut world = deathmatch();
+    let mut bots = Bots::default();
+    for skill in [0.0, 0.3, 0.7, 1.0] {
+      bots.add(&mut world, skill);
+    }
+
+    // Where each bot's current life started, and whether it's got anywhere from there.
+    let mut life_start: Vec<Option<DVec2>> = vec![None; world.players.len()];
+    let mut moved = vec![false; world.players.len()];
+
+    // Three and a bit minutes of game.
+    for _ in 0..6000 {
+      for (index, input) in bots.think(&world, DELTA) {
+        world.run_input(index, &input, DELTA);
+      }
+      world.update(DELTA);
+
+      for (index, player) in world.players.iter().enumerate() {
+        assert!(
+          player.position.is_finite(),
+          "{} went off to NaN",
+          player.name
+        );
+        if !player.alive {
+          life_start[index] = None;
+          continue;
+        }
+        let start = *life_start[index].get_or_insert(player.position);
+        moved[index] |= start.distance(player.position) > 1.0;
+      }
+
+      // A death is a frag for somebody else, or a frag off for dying on your own.
+      let frags: i32 = world.players.iter().map(|player| player.frags).sum();
+      let deaths: i32 = world.players.iter().map(|player| player.deaths).sum();
+      assert!(frags <= deaths, "{} frags from {} deaths", frags, deaths);
+      assert_eq!(
+        (deaths - frags) % 2,
+        0,
+        "{} frags from {} deaths",
+        frags,
+        deaths
+      );
+    }
+
+    for (player, moved) in world.players.iter().zip(moved) {
+      assert!(moved, "{} never left its spawn", player.name);
+    }
+    let deaths: i32 = world.players.iter().map(|player| player.deaths).sum();
+    assert!(deaths > 0, "nobody died in three minutes");
+  }
+
+  #[test]
+  fn paths_lead_back_to_the_bot() {
+    let mut world = deathmatch();
+    let mut bots = Bots::default();
+    bots.add(&mut world, 1.0);
+    bots.add(&mut world, 1.0);
+
+    // Nothing to pick up, so the only thing worth going to is the other bot.
+    let items: Vec<_> = world.entities.pickups.iter().map(|(id, _)| id).collect();
+    for id in items {
+      world.entities.despawn(id);
+    }
+    let me = world.players[0].position.floor().as_ivec2();
+    let steps = world.map.flood_fill(me, MAX_PATH_STEPS, MAX_PATH_STEPS + 1);
+    let far = world
+      .spawns
+      .iter()
+      .map(|spawn| spawn.floor().as_ivec2())
+      .filter(|cell| steps_at(&steps, *cell).is_some_and(|distance| distance > 3))
+      .max_by_key(|cell| steps_at(&steps, *cell))
+      .expect("a spawn some way off");
+    world.players[1].position = cell_center(far);
+
+    let bot = &mut bots.bots[0];
+    bot.plan(&world, &world.players[0]);
+    let path = &bot.path;
+    assert_eq!(path.first(), Some(&far));
+    assert_eq!(path.len() as u32, steps_at(&steps, far).unwrap());
+    for pair in path.windows(2) {
+      let step = pair[0] - pair[1];
+      assert_eq!(step.x.abs() + step.y.abs(), 1, "{:?}", pair);
+      assert!(!world.map.is_wall(cell_center(pair[1])));
+    }
+    let next = *path.last().unwrap() - me;
+    assert_eq!(next.x.abs() + next.y.abs(), 1);
+  }
+
+  #[test]
+  fn steps_off_the_fill_are_none() {
+    let steps = vec![vec![Some(0), None], vec![Some(1), Some(2)]];
+    assert_eq!(steps_at(&steps, IVec2::new(1, 1)), Some(2));
+    assert_eq!(steps_at(&steps, IVec2::new(0, 1)), None);
+    assert_eq!(steps_at(&steps, IVec2::new(-1, 0)), None);
+    assert_eq!(steps_at(&steps, IVec2::new(0, 2)), None);
+  }
+
+  #[test]
+  fn the_wheel_goes_where_the_bot_wants() {
+    let mut world = deathmatch();
+    let mut bots = Bots::default();
+    bots.add(&mut world, 1.0);
+
+    let mut owned: Vec<String> = world.weapons.defs.keys().cloned().collect();
+    owned.sort();
+    assert!(owned.len() > 2, "needs a few weapons to pick from");
+    {
+      let me = &mut world.players[0];
+      me.weapons = owned.clone();
+      for ammo in AmmoType::ALL {
+        me.ammo.insert(ammo, ammo.max());
+      }
+    }
+
+    let mut picked = Vec::new();
+    for start in &owned {
+      world.players[0].weapon = start.clone();
+      let input = PlayerInput {
+        wheel: pick_weapon(&world, &world.players[0]),
+        ..PlayerInput::default()
+      };
+      world.predict(0, &input, DELTA);
+      assert_eq!(
+        pick_weapon(&world, &world.players[0]),
+        0,
+        "from {} the wheel went to {}",
+        start,
+        world.players[0].weapon
+      );
+      picked.push(world.players[0].weapon.clone());
+    }
+    picked.dedup();
+    assert_eq!(picked.len(), 1, "{:?}", picked);
+  }
+}
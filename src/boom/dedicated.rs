@@ -0,0 +1,535 @@
+use std::{
+  fmt,
+  io::{self, BufRead},
+  path::PathBuf,
+  sync::mpsc::{self, Receiver, Sender},
+  thread,
+  time::Instant,
+};
+
+use spin_sleep::LoopHelper;
+
+use super::{
+  bot::MAX_BOTS,
+  level::{Level, LevelError, Rotation},
+  net::{log, NetError, NetServer, DEFAULT_PORT, TICK_RATE},
+  settings::Settings,
+  weapon::{WeaponError, Weapons},
+  world::World,
+};
+
+// Seconds the final scores stay up before the next level starts.
+const INTERMISSION_TIME: f64 = 5.0;
+
+///
+/// Things you can pass the dedicated server on the command line.
+///
+/// --port <port>      listens here instead of 7777.
+/// --rotation <path>  plays a different rotation file than levels/rotation.toml.
+/// --level <name>     starts at this level of the rotation instead of the first.
+/// --bots <count>     fills the server up with bots.
+/// --soak <minutes>   plays that many minutes of game time as fast as it can, prints the
+///                    scores and quits. Add some bots and it's a test that needs nobody.
+///
+#[derive(Default, Debug)]
+pub struct ServerOptions {
+  pub port: Option<u16>,
+  pub rotation: Option<PathBuf>,
+  pub level: Option<String>,
+  pub bots: usize,
+  pub soak: Option<f64>,
+}
+
+impl ServerOptions {
+  pub fn from_args() -> Self {
+    ServerOptions::parse(std::env::args().skip(1))
+  }
+
+  pub fn parse(mut args: impl Iterator<Item = String>) -> Self {
+    let mut options = ServerOptions::default();
+
+    while let Some(arg) = args.next() {
+      match arg.as_str() {
+        "--port" => match args.next().map(|port| port.parse::<u16>()) {
+          Some(Ok(port)) => options.port = Some(port),
+          _ => eprintln!("boom_server: --port needs a port number"),
+        },
+        "--rotation" => match args.next() {
+          Some(path) => options.rotation = Some(PathBuf::from(path)),
+          None => eprintln!("boom_server: --rotation needs a path"),
+        },
+        "--level" => match args.next() {
+          Some(level) => options.level = Some(level),
+          None => eprintln!("boom_server: --level needs a level name"),
+        },
+        "--bots" => match args.next().map(|count| count.parse::<usize>()) {
+          Some(Ok(count)) if count <= MAX_BOTS => options.bots = count,
+          _ => eprintln!(
+            "boom_server: --bots needs a number of bots from 0 to {}",
+            MAX_BOTS
+          ),
+        },
+        "--soak" => match args.next().map(|minutes| minutes.parse::<f64>()) {
+          Some(Ok(minutes)) if minutes > 0.0 && minutes.is_finite() => options.soak = Some(minutes),
+          _ => eprintln!("boom_server: --soak needs a number of minutes"),
+        },
+        _ => eprintln!("boom_server: ignoring unknown argument {}", arg),
+      }
+    }
+
+    options
+  }
+}
+
+///
+/// Something that stops the dedicated server from starting.
+///
+#[derive(Debug)]
+pub enum ServerError {
+  Level(LevelError),
+  Weapons(WeaponError),
+  Net(NetError),
+}
+
+impl fmt::Display for ServerError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      ServerError::Level(e) => write!(f, "{}", e),
+      ServerError::Weapons(e) => write!(f, "{}", e),
+      ServerError::Net(e) => write!(f, "could not listen: {}", e),
+    }
+  }
+}
+
+///
+/// A deathmatch server with nobody sitting at it. No window, no sound, no SDL, just the
+/// world ticking over and a socket.
+///
+/// It plays through the levels of a rotation, moving on when the time or frag limit gets
+/// hit, and takes commands typed into stdin. Type help for the list.
+///
+pub struct DedicatedServer {
+  server: NetServer,
+  rotation: Rotation,
+  level_index: usize,
+  settings: Settings,
+  weapons: Weapons,
+  /// Counts down once a level is over. None while it's still being played.
+  intermission: Option<f64>,
+  console: Receiver<String>,
+  running: bool,
+  /// Minutes of game time to run flat out for before quitting. None runs in real time.
+  soak: Option<f64>,
+}
+
+impl DedicatedServer {
+  pub fn new(options: ServerOptions) -> Result<Self, ServerError> {
+    let rotation_path = options.rotation.unwrap_or_else(Rotation::default_path);
+    let rotation = Rotation::load(&rotation_path).map_err(ServerError::Level)?;
+    let level_index = match &options.level {
+      Some(name) => rotation
+        .levels
+        .iter()
+        .position(|level| level == name)
+        .ok_or_else(|| {
+          let reason = format!("{} isn't in the rotation", name);
+          ServerError::Level(LevelError::Invalid(rotation_path.clone(), reason))
+        })?,
+      None => 0,
+    };
+
+    let weapons = Weapons::load(&Weapons::default_path()).map_err(ServerError::Weapons)?;
+    let settings = Settings::load();
+    let level = Level::load(&rotation.levels[level_index]).map_err(ServerError::Level)?;
+    let world = World::new_deathmatch(&level, &settings, &weapons);
+    let mut server =
+      NetServer::bind(options.port.unwrap_or(DEFAULT_PORT), world).map_err(ServerError::Net)?;
+    for _ in 0..options.bots {
+      server
+        .bots
+        .add(&mut server.world, settings.gameplay.bot_skill);
+    }
+
+    log(
+      format!(
+        "serving {} on port {}, type help for commands",
+        level.id,
+        server.port()
+      )
+      .as_str(),
+    );
+
+    Ok(DedicatedServer {
+      server,
+      rotation,
+      level_index,
+      settings,
+      weapons,
+      intermission: None,
+      console: read_console(),
+      running: true,
+      soak: options.soak,
+    })
+  }
+
+  ///
+  /// Ticks the world at TICK_RATE until somebody types quit, or the soak test is done.
+  ///
+  pub fn run(mut self) {
+    let mut loop_helper = LoopHelper::builder().build_with_target_rate(TICK_RATE);
+    let started = Instant::now();
+    let mut game_time = 0.0;
+
+    while self.running {
+      // A hitch shouldn't turn into everybody teleporting. Soak tests don't wait around.
+      let delta = match self.soak {
+        Some(_) => 1.0 / TICK_RATE,
+        None => loop_helper.loop_start_s().min(0.25),
+      };
+      self.server.tick(delta);
+      self.check_limits();
+      self.do_intermission(delta);
+      game_time += delta;
+
+      while let Ok(line) = self.console.try_recv() {
+        self.do_command(line.trim());
+      }
+
+      match self.soak {
+        Some(minutes) if game_time >= minutes * 60.0 => self.running = false,
+        Some(_) => (),
+        None => loop_helper.loop_sleep(),
+      }
+    }
+
+    if self.soak.is_some() {
+      self.print_status();
+      log(
+        format!(
+          "soaked {:.1} minutes of play in {:.1} seconds",
+          game_time / 60.0,
+          started.elapsed().as_secs_f64()
+        )
+        .as_str(),
+      );
+    }
+    self.server.shut_down("server shut down");
+    log("shut down");
+  }
+
+  ///
+  /// Ends the level once the clock runs out or somebody gets enough frags.
+  ///
+  fn check_limits(&mut self) {
+    if self.intermission.is_some() {
+      return;
+    }
+
+    let world = &self.server.world;
+    let time_up =
+      self.rotation.time_limit > 0.0 && world.level_time >= self.rotation.time_limit * 60.0;
+    let frags_up = self.rotation.frag_limit > 0
+      && world
+        .players
+        .iter()
+        .any(|player| player.frags >= self.rotation.frag_limit);
+    if !time_up && !frags_up {
+      return;
+    }
+
+    let winner = world
+      .players
+      .iter()
+      .max_by_key(|player| player.frags)
+      .map(|player| format!("{} wins with {} frags.", player.name, player.frags));
+    let text = winner.unwrap_or_else(|| "Time's up.".to_string());
+    self.server.world.show_message(&text);
+    self.intermission = Some(INTERMISSION_TIME);
+  }
+
+  fn do_intermission(&mut self, delta: f64) {
+    let Some(time_left) = self.intermission.as_mut() else {
+      return;
+    };
+    *time_left -= delta;
+    if *time_left <= 0.0 {
+      self.next_level();
+    }
+  }
+
+  fn next_level(&mut self) {
+    let next = (self.level_index + 1) % self.rotation.levels.len();
+    let id = self.rotation.levels[next].clone();
+    self.start_level(&id);
+  }
+
+  ///
+  /// Moves everybody to a level. One that won't load gets logged and play carries on
+  /// where it is.
+  ///
+  fn start_level(&mut self, id: &str) -> bool {
+    let level = match Level::load(id) {
+      Ok(level) => level,
+      Err(e) => {
+        log(format!("could not change level: {}", e).as_str());
+        return false;
+      }
+    };
+
+    if let Some(index) = self.rotation.levels.iter().position(|level| level == id) {
+      self.level_index = index;
+    }
+    let world = World::new_deathmatch(&level, &self.settings, &self.weapons);
+    self.server.change_level(world);
+    self.intermission = None;
+    true
+  }
+
+  ///
+  /// One line typed into the console.
+  ///
+  fn do_command(&mut self, line: &str) {
+    let (command, argument) = match line.split_once(' ') {
+      Some((command, argument)) => (command, argument.trim()),
+      None => (line, ""),
+    };
+
+    match command {
+      "" => (),
+      "help" => {
+        println!("status          who's playing, and the score");
+        println!("say <text>      tells everybody something");
+        println!("kick <name>     throws somebody out");
+        println!("addbot [skill]  adds a bot, skill from 0 to 1");
+        println!("removebot       takes the newest bot out");
+        println!("map <level>     changes level now");
+        println!("next            moves on to the next level in the rotation");
+        println!("quit            says goodbye to everybody and stops");
+      }
+      "status" => self.print_status(),
+      "say" if !argument.is_empty() => {
+        self
+          .server
+          .world
+          .show_message(format!("server: {}", argument).as_str());
+      }
+      "kick" if !argument.is_empty() => {
+        if !self.server.kick(argument, "kicked by the server") {
+          println!("nobody called {} is playing", argument);
+        }
+      }
+      "addbot" => self.add_bot(argument),
+      "removebot" => match self.server.bots.remove(&mut self.server.world) {
+        Some(name) => log(format!("{} left", name).as_str()),
+        None => println!("there aren't any bots"),
+      },
+      "map" if !argument.is_empty() => {
+        self.start_level(argument);
+      }
+      "next" => self.next_level(),
+      "quit" => self.running = false,
+      "say" | "kick" | "map" => println!("{} needs something after it", command),
+      _ => println!("unknown command {}, try help", command),
+    }
+  }
+
+  fn add_bot(&mut self, skill: &str) {
+    if self.server.bots.bots.len() >= MAX_BOTS {
+      println!("that's enough bots");
+      return;
+    }
+    let skill = match skill {
+      "" => self.settings.gameplay.bot_skill,
+      _ => match skill.parse::<f64>() {
+        Ok(skill) if (0.0..=1.0).contains(&skill) => skill,
+        _ => {
+          println!("skill goes from 0 to 1");
+          return;
+        }
+      },
+    };
+    let name = self.server.bots.add(&mut self.server.world, skill);
+    log(format!("{} joined with skill {}", name, skill).as_str());
+  }
+
+  fn print_status(&self) {
+    let world = &self.server.world;
+    println!(
+      "{} ({}), {}:{:02} in",
+      world.level_name,
+      world.level_id,
+      world.level_time as u64 / 60,
+      world.level_time as u64 % 60
+    );
+
+    let clients = self.server.clients();
+    if world.players.is_empty() {
+      println!("nobody's playing");
+    }
+    for player in &world.players {
+      let from = match clients.iter().find(|(name, _)| *name == player.name) {
+        Some((_, address)) => address.to_string(),
+        None => "bot".to_string(),
+      };
+      println!(
+        "{:<16} {:>4} frags {:>4} deaths  {}",
+        player.name, player.frags, player.deaths, from
+      );
+    }
+  }
+}
+
+///
+/// Reads stdin on its own thread and hands over one line at a time. When stdin closes,
+/// like when it's running as a service, the server just carries on without a console.
+///
+fn read_console() -> Receiver<String> {
+  let (sender, lines) = mpsc::channel();
+  let reader = move |sender: Sender<String>| {
+    for line in io::stdin().lock().lines() {
+      let Ok(line) = line else {
+        return;
+      };
+      if sender.send(line).is_err() {
+        return;
+      }
+    }
+  };
+
+  if let Err(e) = thread::Builder::new()
+    .name("console".to_string())
+    .spawn(move || reader(sender))
+  {
+    eprintln!("boom_server: no console: {}", e);
+  }
+  lines
+}
+
+#[cfg(test)]
+mod tests {
+  use std::net::UdpSocket;
+
+  use super::*;
+
+  fn parse(args: &[&str]) -> ServerOptions {
+    ServerOptions::parse(args.iter().map(|arg| arg.to_string()))
+  }
+
+  ///
+  /// A server on the first level of the shipped rotation, on a localhost port, with no
+  /// console and nobody playing.
+  ///
+  fn test_server() -> DedicatedServer {
+    let rotation = Rotation::load(&Rotation::default_path()).unwrap();
+    let weapons = Weapons::load(&Weapons::default_path()).unwrap();
+    let settings = Settings::default();
+    let level = Level::load(&rotation.levels[0]).unwrap();
+    let world = World::new_deathmatch(&level, &settings, &weapons);
+
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket.set_nonblocking(true).unwrap();
+    DedicatedServer {
+      server: NetServer::with_socket(socket, world),
+      rotation,
+      level_index: 0,
+      settings,
+      weapons,
+      intermission: None,
+      console: mpsc::channel().1,
+      running: true,
+      soak: None,
+    }
+  }
+
+  #[test]
+  fn good_options_parse() {
+    let options = parse(&[
+      "--port", "9000", "--level", "hangar", "--bots", "3", "--soak", "2.5",
+    ]);
+    assert_eq!(options.port, Some(9000));
+    assert_eq!(options.level.as_deref(), Some("hangar"));
+    assert_eq!(options.bots, 3);
+    assert_eq!(options.soak, Some(2.5));
+  }
+
+  #[test]
+  fn bad_options_are_refused() {
+    let too_many_bots = (MAX_BOTS + 1).to_string();
+    for args in [
+      &["--bots", "99"][..],
+      &["--bots", &too_many_bots],
+      &["--bots", "-1"],
+      &["--bots"],
+      &["--soak", "-1"],
+      &["--soak", "0"],
+      &["--soak", "nan"],
+      &["--soak", "inf"],
+      &["--port", "70000"],
+      &["--frobnicate"],
+    ] {
+      let options = parse(args);
+      assert_eq!(options.bots, 0, "{:?}", args);
+      assert_eq!(options.soak, None, "{:?}", args);
+      assert_eq!(options.port, None, "{:?}", args);
+    }
+  }
+
+  #[test]
+  fn unknown_commands_change_nothing() {
+    let mut server = test_server();
+    server.do_command("addbot");
+    let level = server.server.world.level_id.clone();
+    let messages = server.server.world.messages.len();
+
+    for line in [
+      "frobnicate",
+      "say",
+      "kick",
+      "map",
+      "addbot 2",
+      "kick nobody",
+    ] {
+      server.do_command(line);
+      assert!(server.running, "{}", line);
+      assert_eq!(server.level_index, 0, "{}", line);
+      assert_eq!(server.intermission, None, "{}", line);
+      assert_eq!(server.server.world.level_id, level, "{}", line);
+      assert_eq!(server.server.world.players.len(), 1, "{}", line);
+      assert_eq!(server.server.world.messages.len(), messages, "{}", line);
+    }
+
+    server.do_command("quit");
+    assert!(!server.running);
+  }
+
+  #[test]
+  fn frag_limit_moves_the_rotation_on() {
+    let mut server = test_server();
+    server.rotation.frag_limit = 3;
+    server.do_command("addbot");
+    server.do_command("addbot");
+
+    server.server.world.players[1].frags = 2;
+    server.check_limits();
+    assert_eq!(server.intermission, None);
+
+    server.server.world.players[1].frags = 3;
+    server.check_limits();
+    assert_eq!(server.intermission, Some(INTERMISSION_TIME));
+
+    server.do_intermission(INTERMISSION_TIME / 2.0);
+    assert_eq!(server.level_index, 0);
+    server.do_intermission(INTERMISSION_TIME / 2.0);
+    assert_eq!(server.level_index, 1);
+    assert_eq!(server.intermission, None);
+    assert_eq!(server.server.world.level_id, server.rotation.levels[1]);
+    // Everybody comes along, with a clean slate.
+    assert_eq!(server.server.world.players.len(), 2);
+    assert!(server
+      .server
+      .world
+      .players
+      .iter()
+      .all(|player| player.frags == 0));
+  }
+}
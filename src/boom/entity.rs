@@ -0,0 +1,572 @@
+use glam::{DVec2, IVec2};
+use serde::{Deserialize, Serialize};
+
+use super::{spatial::SpatialGrid, texture::texture_id, world::ItemKind};
+
+pub const MOB_HEALTH: i32 = 30;
+// How fat a mob is for bullets, in cells.
+const MOB_RADIUS: f64 = 0.3;
+/// Same for players.
+pub const PLAYER_RADIUS: f64 = 0.3;
+// Cells per second, once something has woken them up.
+const MOB_SPEED: f64 = 1.5;
+// Items are drawn this fraction of a wall tall, sitting on the floor.
+const ITEM_SCALE: f64 = 0.4;
+// Projectiles that never hit anything still go away eventually.
+const BULLET_LIFETIME: f64 = 10.0;
+
+///
+/// A handle to something in the world.
+///
+/// Indexes get reused once an entity is gone, but the generation goes up every time, so a
+/// handle to something that was despawned never points at whatever took its place.
+///
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct EntityId {
+  pub index: u32,
+  pub generation: u32,
+}
+
+///
+/// Where something is and which way it's facing.
+///
+#[derive(Clone, Copy, Debug)]
+pub struct Transform {
+  pub position: DVec2,
+  pub yaw: f64,
+}
+
+///
+/// Drawn as a flat sprite that always faces the camera. Scale 1 is as tall as a wall.
+///
+#[derive(Clone, Copy, Debug)]
+pub struct Sprite {
+  pub texture: usize,
+  pub scale: f64,
+}
+
+///
+/// Can be hurt, and dies at 0.
+///
+#[derive(Clone, Copy, Debug)]
+pub struct Health {
+  pub current: i32,
+  /// Swapped in for the sprite on death.
+  pub dead_texture: Option<usize>,
+}
+
+impl Health {
+  pub fn alive(&self) -> bool {
+    self.current > 0
+  }
+}
+
+///
+/// A circle that bullets and other things bump into.
+///
+#[derive(Clone, Copy, Debug)]
+pub struct Collider {
+  pub radius: f64,
+}
+
+///
+/// What a mob is up to.
+///
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum AiState {
+  /// Standing around until something wakes it up.
+  #[default]
+  Idle,
+  /// Knows where the player is and is coming for them.
+  Alert,
+}
+
+///
+/// Anything with a brain. Everything with one of these counts as a mob.
+///
+#[derive(Clone, Copy, Debug)]
+pub struct Ai {
+  pub state: AiState,
+  /// Cells per second.
+  pub speed: f64,
+}
+
+///
+/// Despawns the entity once the time runs out.
+///
+#[derive(Clone, Copy, Debug)]
+pub struct Lifetime {
+  pub time_left: f64,
+}
+
+///
+/// The bullet the player can shoot. Pew pew.
+///
+#[derive(Clone, Copy, Debug)]
+pub struct Bullet {
+  pub direction: DVec2,
+  /// Cells per second. None is hitscan, which hits whatever it's pointing at on the next update.
+  pub speed: Option<f64>,
+  pub damage: i32,
+  /// Whoever fired it, so they don't shoot themselves in the back of the head.
+  pub owner: Option<EntityId>,
+}
+
+///
+/// Something lying on the floor waiting to be picked up.
+///
+#[derive(Clone, Debug)]
+pub struct Pickup {
+  pub kind: ItemKind,
+  /// Which of the level's items this is, so saves can tell which ones are gone.
+  /// None for anything that showed up later.
+  pub placed: Option<usize>,
+}
+
+///
+/// One component type for every entity. Slots are indexed by the entity's index and
+/// remember the generation they were set for, so stale handles just see nothing.
+///
+pub struct Components<T> {
+  slots: Vec<Option<(u32, T)>>,
+}
+
+impl<T> Default for Components<T> {
+  fn default() -> Self {
+    Components { slots: Vec::new() }
+  }
+}
+
+impl<T> Components<T> {
+  pub fn insert(&mut self, id: EntityId, value: T) {
+    let index = id.index as usize;
+    if index >= self.slots.len() {
+      self.slots.resize_with(index + 1, || None);
+    }
+    self.slots[index] = Some((id.generation, value));
+  }
+
+  pub fn remove(&mut self, id: EntityId) -> Option<T> {
+    let slot = self.slots.get_mut(id.index as usize)?;
+    match slot {
+      Some((generation, _)) if *generation == id.generation => slot.take().map(|(_, value)| value),
+      _ => None,
+    }
+  }
+
+  pub fn get(&self, id: EntityId) -> Option<&T> {
+    match self.slots.get(id.index as usize)? {
+      Some((generation, value)) if *generation == id.generation => Some(value),
+      _ => None,
+    }
+  }
+
+  pub fn get_mut(&mut self, id: EntityId) -> Option<&mut T> {
+    match self.slots.get_mut(id.index as usize)? {
+      Some((generation, value)) if *generation == id.generation => Some(value),
+      _ => None,
+    }
+  }
+
+  pub fn contains(&self, id: EntityId) -> bool {
+    self.get(id).is_some()
+  }
+
+  ///
+  /// Every entity that has this component, oldest index first.
+  ///
+  pub fn iter(&self) -> impl Iterator<Item = (EntityId, &T)> {
+    self.slots.iter().enumerate().filter_map(|(index, slot)| {
+      slot.as_ref().map(|(generation, value)| {
+        (
+          EntityId {
+            index: index as u32,
+            generation: *generation,
+          },
+          value,
+        )
+      })
+    })
+  }
+
+  pub fn iter_mut(&mut self) -> impl Iterator<Item = (EntityId, &mut T)> {
+    self
+      .slots
+      .iter_mut()
+      .enumerate()
+      .filter_map(|(index, slot)| {
+        slot.as_mut().map(|(generation, value)| {
+          (
+            EntityId {
+              index: index as u32,
+              generation: *generation,
+            },
+            value,
+          )
+        })
+      })
+  }
+
+  pub fn len(&self) -> usize {
+    self.slots.iter().filter(|slot| slot.is_some()).count()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+}
+
+///
+/// Everything in the world that isn't the map.
+///
+/// An entity is just an id, what it is comes from which components it has. A mob is a
+/// transform, sprite, health, collider and AI. A bullet is a transform, bullet and lifetime.
+/// Players have a body made of a transform, sprite and collider, the rest of them lives in
+/// their Player.
+/// Systems in World::on_tick go over whichever components they care about.
+///
+/// Anything with a transform is also in the grid. Move things with move_to, not by writing
+/// to the transform, or the grid loses track of them.
+///
+#[derive(Default)]
+pub struct Entities {
+  generations: Vec<u32>,
+  alive: Vec<bool>,
+  free: Vec<u32>,
+  pub transforms: Components<Transform>,
+  pub sprites: Components<Sprite>,
+  pub healths: Components<Health>,
+  pub colliders: Components<Collider>,
+  pub ais: Components<Ai>,
+  pub lifetimes: Components<Lifetime>,
+  pub bullets: Components<Bullet>,
+  pub pickups: Components<Pickup>,
+  pub grid: SpatialGrid,
+}
+
+impl Entities {
+  ///
+  /// The size is the map's, so the grid lines up with it.
+  ///
+  pub fn new(size: IVec2) -> Self {
+    Entities {
+      grid: SpatialGrid::new(size),
+      ..Default::default()
+    }
+  }
+
+  ///
+  /// A fresh entity with no components.
+  ///
+  pub fn spawn(&mut self) -> EntityId {
+    match self.free.pop() {
+      Some(index) => {
+        self.alive[index as usize] = true;
+        EntityId {
+          index,
+          generation: self.generations[index as usize],
+        }
+      }
+      None => {
+        self.generations.push(0);
+        self.alive.push(true);
+        EntityId {
+          index: self.generations.len() as u32 - 1,
+          generation: 0,
+        }
+      }
+    }
+  }
+
+  ///
+  /// Removes an entity and all its components. Returns false if it was already gone.
+  ///
+  pub fn despawn(&mut self, id: EntityId) -> bool {
+    if !self.exists(id) {
+      return false;
+    }
+
+    self.transforms.remove(id);
+    self.sprites.remove(id);
+    self.healths.remove(id);
+    self.colliders.remove(id);
+    self.ais.remove(id);
+    self.lifetimes.remove(id);
+    self.bullets.remove(id);
+    self.pickups.remove(id);
+    self.grid.remove(id);
+
+    let index = id.index as usize;
+    self.alive[index] = false;
+    self.generations[index] += 1;
+    self.free.push(id.index);
+    true
+  }
+
+  pub fn exists(&self, id: EntityId) -> bool {
+    let index = id.index as usize;
+    index < self.generations.len() && self.alive[index] && self.generations[index] == id.generation
+  }
+
+  pub fn count(&self) -> usize {
+    self.alive.iter().filter(|alive| **alive).count()
+  }
+
+  pub fn position(&self, id: EntityId) -> Option<DVec2> {
+    self.transforms.get(id).map(|transform| transform.position)
+  }
+
+  ///
+  /// Gives an entity a transform and puts it in the grid.
+  ///
+  pub fn place(&mut self, id: EntityId, position: DVec2) {
+    self.transforms.insert(id, Transform { position, yaw: 0.0 });
+    self.grid.insert(id, position);
+  }
+
+  pub fn move_to(&mut self, id: EntityId, position: DVec2) {
+    if let Some(transform) = self.transforms.get_mut(id) {
+      transform.position = position;
+      self.grid.insert(id, position);
+    }
+  }
+
+  ///
+  /// Everything with one of these components within radius, closest first.
+  ///
+  pub fn near<T>(
+    &self,
+    components: &Components<T>,
+    center: DVec2,
+    radius: f64,
+  ) -> Vec<(EntityId, f64)> {
+    let mut found: Vec<(EntityId, f64)> = self
+      .grid
+      .query_radius(center, radius)
+      .into_iter()
+      .filter(|(id, _)| components.contains(*id))
+      .map(|(id, position)| (id, position.distance(center)))
+      .collect();
+    found.sort_by(|a, b| a.1.total_cmp(&b.1));
+    found
+  }
+
+  pub fn spawn_mob(&mut self, position: DVec2) -> EntityId {
+    let id = self.spawn();
+    self.place(id, position);
+    self.sprites.insert(
+      id,
+      Sprite {
+        texture: texture_id("oerkki"),
+        scale: 1.0,
+      },
+    );
+    self.healths.insert(
+      id,
+      Health {
+        current: MOB_HEALTH,
+        dead_texture: Some(texture_id("oerkki_dead")),
+      },
+    );
+    self.colliders.insert(id, Collider { radius: MOB_RADIUS });
+    self.ais.insert(
+      id,
+      Ai {
+        state: AiState::Idle,
+        speed: MOB_SPEED,
+      },
+    );
+    id
+  }
+
+  ///
+  /// A body for a player. World keeps it where the player is.
+  ///
+  pub fn spawn_player(&mut self, position: DVec2) -> EntityId {
+    let id = self.spawn();
+    self.place(id, position);
+    self.sprites.insert(
+      id,
+      Sprite {
+        texture: texture_id("dm"),
+        scale: 1.0,
+      },
+    );
+    self.colliders.insert(
+      id,
+      Collider {
+        radius: PLAYER_RADIUS,
+      },
+    );
+    id
+  }
+
+  pub fn spawn_bullet(&mut self, position: DVec2, bullet: Bullet) -> EntityId {
+    let id = self.spawn();
+    self.place(id, position);
+    self.bullets.insert(id, bullet);
+    self.lifetimes.insert(
+      id,
+      Lifetime {
+        time_left: BULLET_LIFETIME,
+      },
+    );
+    id
+  }
+
+  ///
+  /// Panics if the item has no texture, levels check for that when they load.
+  ///
+  pub fn spawn_item(&mut self, position: DVec2, kind: ItemKind, placed: Option<usize>) -> EntityId {
+    let texture = kind
+      .sprite()
+      .unwrap_or_else(|| panic!("no texture for item {:?}", kind));
+
+    let id = self.spawn();
+    self.place(id, position);
+    self.sprites.insert(
+      id,
+      Sprite {
+        texture,
+        scale: ITEM_SCALE,
+      },
+    );
+    self.pickups.insert(id, Pickup { kind, placed });
+    id
+  }
+
+  ///
+  /// Takes health off something and knocks it over if that was too much. Anything with a
+  /// brain wakes up when it gets hurt.
+  ///
+  pub fn hurt(&mut self, id: EntityId, damage: i32) {
+    if let Some(ai) = self.ais.get_mut(id) {
+      ai.state = AiState::Alert;
+    }
+    let Some(health) = self.healths.get_mut(id) else {
+      return;
+    };
+    if !health.alive() {
+      return;
+    }
+    health.current -= damage;
+    if !health.alive() {
+      self.kill(id);
+    }
+  }
+
+  ///
+  /// Dead things stay where they fell, but stop getting in the way.
+  ///
+  pub fn kill(&mut self, id: EntityId) {
+    let Some(health) = self.healths.get_mut(id) else {
+      return;
+    };
+    health.current = health.current.min(0);
+
+    if let (Some(texture), Some(sprite)) = (health.dead_texture, self.sprites.get_mut(id)) {
+      sprite.texture = texture;
+    }
+    self.colliders.remove(id);
+  }
+
+  ///
+  /// Things with health count as alive until it runs out, everything else is alive as long
+  /// as it exists.
+  ///
+  pub fn is_alive(&self, id: EntityId) -> bool {
+    self.exists(id) && self.healths.get(id).map_or(true, |health| health.alive())
+  }
+
+  ///
+  /// Every mob, dead or not, oldest first.
+  ///
+  pub fn mobs(&self) -> impl Iterator<Item = EntityId> + '_ {
+    self.ais.iter().map(|(id, _)| id)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn bullet() -> Bullet {
+    Bullet {
+      direction: DVec2::X,
+      speed: Some(1.0),
+      damage: 1,
+      owner: None,
+    }
+  }
+
+  ///
+  /// A mob that got despawned, and a bullet that got its slot.
+  ///
+  fn reused_slot() -> (Entities, EntityId, EntityId) {
+    let mut entities = Entities::new(IVec2::splat(8));
+    let mob = entities.spawn_mob(DVec2::new(2.5, 2.5));
+    assert!(entities.despawn(mob));
+    let bullet = entities.spawn_bullet(DVec2::new(5.5, 5.5), bullet());
+    assert_eq!(bullet.index, mob.index);
+    assert_ne!(bullet.generation, mob.generation);
+    (entities, mob, bullet)
+  }
+
+  #[test]
+  fn stale_ids_see_nothing() {
+    let (entities, mob, bullet) = reused_slot();
+
+    assert!(!entities.exists(mob));
+    assert!(!entities.is_alive(mob));
+    assert!(entities.transforms.get(mob).is_none());
+    assert!(entities.sprites.get(mob).is_none());
+    assert!(entities.healths.get(mob).is_none());
+    assert!(entities.colliders.get(mob).is_none());
+    assert!(entities.ais.get(mob).is_none());
+    assert!(entities.lifetimes.get(mob).is_none());
+    assert!(entities.bullets.get(mob).is_none());
+    assert!(entities.pickups.get(mob).is_none());
+    assert_eq!(entities.position(mob), None);
+
+    assert!(entities.exists(bullet));
+    assert!(entities.bullets.get(bullet).is_some());
+    assert_eq!(entities.count(), 1);
+  }
+
+  #[test]
+  fn despawning_a_stale_id_leaves_the_new_one_alone() {
+    let (mut entities, mob, bullet) = reused_slot();
+
+    assert!(!entities.despawn(mob));
+    assert!(entities.exists(bullet));
+    assert_eq!(entities.position(bullet), Some(DVec2::new(5.5, 5.5)));
+    assert!(entities.bullets.get(bullet).is_some());
+    assert!(entities.lifetimes.get(bullet).is_some());
+    let nearby = entities.near(&entities.bullets, DVec2::new(5.5, 5.5), 1.0);
+    assert_eq!(nearby.len(), 1);
+    assert_eq!(nearby[0].0, bullet);
+
+    assert!(entities.despawn(bullet));
+    assert!(!entities.despawn(bullet));
+    assert_eq!(entities.count(), 0);
+  }
+
+  #[test]
+  fn killing_keeps_the_body() {
+    let mut entities = Entities::new(IVec2::splat(8));
+    let mob = entities.spawn_mob(DVec2::new(2.5, 2.5));
+    entities.kill(mob);
+
+    assert!(entities.exists(mob));
+    assert!(!entities.is_alive(mob));
+    assert!(entities.colliders.get(mob).is_none());
+    let sprite = entities
+      .sprites
+      .get(mob)
+      .expect("dead mobs keep their sprite");
+    assert_eq!(sprite.texture, texture_id("oerkki_dead"));
+    assert_eq!(entities.position(mob), Some(DVec2::new(2.5, 2.5)));
+    assert!(entities.ais.get(mob).is_some());
+  }
+}
@@ -0,0 +1,511 @@
+use glam::IVec2;
+
+use super::framebuffer::FrameBuffer;
+
+pub const GLYPH_WIDTH: i32 = 5;
+pub const GLYPH_HEIGHT: i32 = 7;
+// Glyphs get a column of space after them and two rows between lines.
+pub const ADVANCE: i32 = GLYPH_WIDTH + 1;
+pub const LINE_HEIGHT: i32 = GLYPH_HEIGHT + 2;
+
+// Printable ASCII, space through tilde. Each row is 5 bits, the top bit is the left pixel.
+const FIRST_GLYPH: char = ' ';
+const GLYPHS: [[u8; GLYPH_HEIGHT as usize]; 95] = [
+  // ' '
+  [
+    0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000,
+  ],
+  // '!'
+  [
+    0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00000, 0b00100,
+  ],
+  // '"'
+  [
+    0b01010, 0b01010, 0b01010, 0b00000, 0b00000, 0b00000, 0b00000,
+  ],
+  // '#'
+  [
+    0b01010, 0b01010, 0b11111, 0b01010, 0b11111, 0b01010, 0b01010,
+  ],
+  // '$'
+  [
+    0b00100, 0b01111, 0b10100, 0b01110, 0b00101, 0b11110, 0b00100,
+  ],
+  // '%'
+  [
+    0b11000, 0b11001, 0b00010, 0b00100, 0b01000, 0b10011, 0b00011,
+  ],
+  // '&'
+  [
+    0b01100, 0b10010, 0b10100, 0b01000, 0b10101, 0b10010, 0b01101,
+  ],
+  // "'"
+  [
+    0b00100, 0b00100, 0b01000, 0b00000, 0b00000, 0b00000, 0b00000,
+  ],
+  // '('
+  [
+    0b00010, 0b00100, 0b01000, 0b01000, 0b01000, 0b00100, 0b00010,
+  ],
+  // ')'
+  [
+    0b01000, 0b00100, 0b00010, 0b00010, 0b00010, 0b00100, 0b01000,
+  ],
+  // '*'
+  [
+    0b00000, 0b00100, 0b10101, 0b01110, 0b10101, 0b00100, 0b00000,
+  ],
+  // '+'
+  [
+    0b00000, 0b00100, 0b00100, 0b11111, 0b00100, 0b00100, 0b00000,
+  ],
+  // ','
+  [
+    0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b00100, 0b01000,
+  ],
+  // '-'
+  [
+    0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000,
+  ],
+  // '.'
+  [
+    0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b01100,
+  ],
+  // '/'
+  [
+    0b00000, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b00000,
+  ],
+  // '0'
+  [
+    0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110,
+  ],
+  // '1'
+  [
+    0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110,
+  ],
+  // '2'
+  [
+    0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111,
+  ],
+  // '3'
+  [
+    0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110,
+  ],
+  // '4'
+  [
+    0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010,
+  ],
+  // '5'
+  [
+    0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110,
+  ],
+  // '6'
+  [
+    0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110,
+  ],
+  // '7'
+  [
+    0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000,
+  ],
+  // '8'
+  [
+    0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110,
+  ],
+  // '9'
+  [
+    0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100,
+  ],
+  // ':'
+  [
+    0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b01100, 0b00000,
+  ],
+  // ';'
+  [
+    0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b00100, 0b01000,
+  ],
+  // '<'
+  [
+    0b00010, 0b00100, 0b01000, 0b10000, 0b01000, 0b00100, 0b00010,
+  ],
+  // '='
+  [
+    0b00000, 0b00000, 0b11111, 0b00000, 0b11111, 0b00000, 0b00000,
+  ],
+  // '>'
+  [
+    0b01000, 0b00100, 0b00010, 0b00001, 0b00010, 0b00100, 0b01000,
+  ],
+  // '?'
+  [
+    0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b00000, 0b00100,
+  ],
+  // '@'
+  [
+    0b01110, 0b10001, 0b00001, 0b01101, 0b10101, 0b10101, 0b01110,
+  ],
+  // 'A'
+  [
+    0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001,
+  ],
+  // 'B'
+  [
+    0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110,
+  ],
+  // 'C'
+  [
+    0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110,
+  ],
+  // 'D'
+  [
+    0b11100, 0b10010, 0b10001, 0b10001, 0b10001, 0b10010, 0b11100,
+  ],
+  // 'E'
+  [
+    0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111,
+  ],
+  // 'F'
+  [
+    0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000,
+  ],
+  // 'G'
+  [
+    0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111,
+  ],
+  // 'H'
+  [
+    0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001,
+  ],
+  // 'I'
+  [
+    0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110,
+  ],
+  // 'J'
+  [
+    0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100,
+  ],
+  // 'K'
+  [
+    0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001,
+  ],
+  // 'L'
+  [
+    0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111,
+  ],
+  // 'M'
+  [
+    0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001,
+  ],
+  // 'N'
+  [
+    0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001,
+  ],
+  // 'O'
+  [
+    0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110,
+  ],
+  // 'P'
+  [
+    0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000,
+  ],
+  // 'Q'
+  [
+    0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101,
+  ],
+  // 'R'
+  [
+    0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001,
+  ],
+  // 'S'
+  [
+    0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110,
+  ],
+  // 'T'
+  [
+    0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100,
+  ],
+  // 'U'
+  [
+    0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110,
+  ],
+  // 'V'
+  [
+    0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100,
+  ],
+  // 'W'
+  [
+    0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010,
+  ],
+  // 'X'
+  [
+    0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001,
+  ],
+  // 'Y'
+  [
+    0b10001, 0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100,
+  ],
+  // 'Z'
+  [
+    0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111,
+  ],
+  // '['
+  [
+    0b01110, 0b01000, 0b01000, 0b01000, 0b01000, 0b01000, 0b01110,
+  ],
+  // '\\'
+  [
+    0b00000, 0b10000, 0b01000, 0b00100, 0b00010, 0b00001, 0b00000,
+  ],
+  // ']'
+  [
+    0b01110, 0b00010, 0b00010, 0b00010, 0b00010, 0b00010, 0b01110,
+  ],
+  // '^'
+  [
+    0b00100, 0b01010, 0b10001, 0b00000, 0b00000, 0b00000, 0b00000,
+  ],
+  // '_'
+  [
+    0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b11111,
+  ],
+  // '`'
+  [
+    0b01000, 0b00100, 0b00010, 0b00000, 0b00000, 0b00000, 0b00000,
+  ],
+  // 'a'
+  [
+    0b00000, 0b00000, 0b01110, 0b00001, 0b01111, 0b10001, 0b01111,
+  ],
+  // 'b'
+  [
+    0b10000, 0b10000, 0b10110, 0b11001, 0b10001, 0b10001, 0b11110,
+  ],
+  // 'c'
+  [
+    0b00000, 0b00000, 0b01110, 0b10000, 0b10000, 0b10001, 0b01110,
+  ],
+  // 'd'
+  [
+    0b00001, 0b00001, 0b01101, 0b10011, 0b10001, 0b10001, 0b01111,
+  ],
+  // 'e'
+  [
+    0b00000, 0b00000, 0b01110, 0b10001, 0b11111, 0b10000, 0b01110,
+  ],
+  // 'f'
+  [
+    0b00110, 0b01001, 0b01000, 0b11100, 0b01000, 0b01000, 0b01000,
+  ],
+  // 'g'
+  [
+    0b00000, 0b01111, 0b10001, 0b10001, 0b01111, 0b00001, 0b01110,
+  ],
+  // 'h'
+  [
+    0b10000, 0b10000, 0b10110, 0b11001, 0b10001, 0b10001, 0b10001,
+  ],
+  // 'i'
+  [
+    0b00100, 0b00000, 0b01100, 0b00100, 0b00100, 0b00100, 0b01110,
+  ],
+  // 'j'
+  [
+    0b00010, 0b00000, 0b00110, 0b00010, 0b00010, 0b10010, 0b01100,
+  ],
+  // 'k'
+  [
+    0b10000, 0b10000, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010,
+  ],
+  // 'l'
+  [
+    0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110,
+  ],
+  // 'm'
+  [
+    0b00000, 0b00000, 0b11010, 0b10101, 0b10101, 0b10001, 0b10001,
+  ],
+  // 'n'
+  [
+    0b00000, 0b00000, 0b10110, 0b11001, 0b10001, 0b10001, 0b10001,
+  ],
+  // 'o'
+  [
+    0b00000, 0b00000, 0b01110, 0b10001, 0b10001, 0b10001, 0b01110,
+  ],
+  // 'p'
+  [
+    0b00000, 0b00000, 0b11110, 0b10001, 0b11110, 0b10000, 0b10000,
+  ],
+  // 'q'
+  [
+    0b00000, 0b00000, 0b01101, 0b10011, 0b01111, 0b00001, 0b00001,
+  ],
+  // 'r'
+  [
+    0b00000, 0b00000, 0b10110, 0b11001, 0b10000, 0b10000, 0b10000,
+  ],
+  // 's'
+  [
+    0b00000, 0b00000, 0b01110, 0b10000, 0b01110, 0b00001, 0b11110,
+  ],
+  // 't'
+  [
+    0b01000, 0b01000, 0b11100, 0b01000, 0b01000, 0b01001, 0b00110,
+  ],
+  // 'u'
+  [
+    0b00000, 0b00000, 0b10001, 0b10001, 0b10001, 0b10011, 0b01101,
+  ],
+  // 'v'
+  [
+    0b00000, 0b00000, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100,
+  ],
+  // 'w'
+  [
+    0b00000, 0b00000, 0b10001, 0b10001, 0b10101, 0b10101, 0b01010,
+  ],
+  // 'x'
+  [
+    0b00000, 0b00000, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001,
+  ],
+  // 'y'
+  [
+    0b00000, 0b00000, 0b10001, 0b10001, 0b01111, 0b00001, 0b01110,
+  ],
+  // 'z'
+  [
+    0b00000, 0b00000, 0b11111, 0b00010, 0b00100, 0b01000, 0b11111,
+  ],
+  // '{'
+  [
+    0b00010, 0b00100, 0b00100, 0b01000, 0b00100, 0b00100, 0b00010,
+  ],
+  // '|'
+  [
+    0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100,
+  ],
+  // '}'
+  [
+    0b01000, 0b00100, 0b00100, 0b00010, 0b00100, 0b00100, 0b01000,
+  ],
+  // '~'
+  [
+    0b00000, 0b00000, 0b01000, 0b10101, 0b00010, 0b00000, 0b00000,
+  ],
+];
+
+///
+/// Where the x position of some text sits relative to the text.
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Align {
+  Left,
+  Center,
+  Right,
+}
+
+///
+/// How to draw some text.
+///
+#[derive(Clone, Copy, Debug)]
+pub struct TextStyle {
+  pub color: [u8; 3],
+  /// Every font pixel becomes a scale x scale block.
+  pub scale: i32,
+  pub align: Align,
+  /// Draws a black copy one font pixel down and right first.
+  pub shadow: bool,
+}
+
+impl Default for TextStyle {
+  fn default() -> Self {
+    TextStyle {
+      color: [255, 255, 255],
+      scale: 1,
+      align: Align::Left,
+      shadow: false,
+    }
+  }
+}
+
+fn glyph(character: char) -> &'static [u8; GLYPH_HEIGHT as usize] {
+  let index = (character as usize).wrapping_sub(FIRST_GLYPH as usize);
+  // Anything the font doesn't have turns into a question mark.
+  GLYPHS
+    .get(index)
+    .unwrap_or(&GLYPHS['?' as usize - FIRST_GLYPH as usize])
+}
+
+fn line_width(line: &str, scale: i32) -> i32 {
+  let characters = line.chars().count() as i32;
+  if characters == 0 {
+    return 0;
+  }
+  (characters * ADVANCE - 1) * scale
+}
+
+///
+/// How much space some text takes up in pixels. Newlines start a new line.
+///
+pub fn text_size(text: &str, scale: i32) -> IVec2 {
+  let width = text
+    .lines()
+    .map(|line| line_width(line, scale))
+    .max()
+    .unwrap_or(0);
+  let lines = text.lines().count() as i32;
+  IVec2::new(width, (lines * LINE_HEIGHT - 2).max(0) * scale)
+}
+
+fn draw_line_of_text(
+  frame: &mut FrameBuffer,
+  line: &str,
+  position: IVec2,
+  color: [u8; 3],
+  scale: i32,
+) {
+  let mut x = position.x;
+
+  for character in line.chars() {
+    for (row, bits) in glyph(character).iter().enumerate() {
+      for column in 0..GLYPH_WIDTH {
+        if bits & (1 << (GLYPH_WIDTH - 1 - column)) == 0 {
+          continue;
+        }
+        frame.fill_rect(
+          IVec2::new(x + column * scale, position.y + row as i32 * scale),
+          IVec2::splat(scale),
+          color,
+        );
+      }
+    }
+    x += ADVANCE * scale;
+  }
+}
+
+///
+/// Blits text into the framebuffer. The position is the top of the first line.
+///
+pub fn draw_text(frame: &mut FrameBuffer, text: &str, position: IVec2, style: TextStyle) {
+  let scale = style.scale.max(1);
+
+  for (line_number, line) in text.lines().enumerate() {
+    let width = line_width(line, scale);
+    let x = match style.align {
+      Align::Left => position.x,
+      Align::Center => position.x - width / 2,
+      Align::Right => position.x - width,
+    };
+    let y = position.y + line_number as i32 * LINE_HEIGHT * scale;
+
+    if style.shadow {
+      draw_line_of_text(
+        frame,
+        line,
+        IVec2::new(x + scale, y + scale),
+        [0, 0, 0],
+        scale,
+      );
+    }
+    draw_line_of_text(frame, line, IVec2::new(x, y), style.color, scale);
+  }
+}
@@ -0,0 +1,127 @@
+use glam::IVec2;
+
+///
+/// The RGBA frame the renderer draws into, with some simple drawing helpers on top.
+///
+/// Everything here clips to the edges so overlays don't have to care.
+///
+pub struct FrameBuffer<'a> {
+  pub buffer: &'a mut [u8],
+  pub size: IVec2,
+  pub pitch: usize,
+  // Anything outside this rectangle doesn't get drawn. Position then size.
+  clip: Option<(IVec2, IVec2)>,
+}
+
+impl<'a> FrameBuffer<'a> {
+  pub fn new(buffer: &'a mut [u8], size: IVec2, pitch: usize) -> Self {
+    FrameBuffer {
+      buffer,
+      size,
+      pitch,
+      clip: None,
+    }
+  }
+
+  ///
+  /// Only draw inside this rectangle until clear_clip gets called.
+  ///
+  pub fn set_clip(&mut self, position: IVec2, size: IVec2) {
+    self.clip = Some((position, size));
+  }
+
+  pub fn clear_clip(&mut self) {
+    self.clip = None;
+  }
+
+  ///
+  /// A smaller frame looking at one rectangle of this one, with its own top left corner.
+  ///
+  /// Split screen hands one of these to each player so nothing drawing into it has to
+  /// know it isn't the whole screen.
+  ///
+  pub fn view(&mut self, position: IVec2, size: IVec2) -> FrameBuffer<'_> {
+    let position = position.clamp(IVec2::ZERO, self.size - IVec2::ONE);
+    let size = size.clamp(IVec2::ONE, self.size - position);
+    let start = position.y as usize * self.pitch + position.x as usize * 4;
+    let end = start + (size.y as usize - 1) * self.pitch + size.x as usize * 4;
+    FrameBuffer::new(&mut self.buffer[start..end], size, self.pitch)
+  }
+
+  fn index(&self, x: i32, y: i32) -> Option<usize> {
+    if x < 0 || y < 0 || x >= self.size.x || y >= self.size.y {
+      return None;
+    }
+    if let Some((position, size)) = self.clip {
+      let end = position + size;
+      if x < position.x || y < position.y || x >= end.x || y >= end.y {
+        return None;
+      }
+    }
+    Some(y as usize * self.pitch + x as usize * 4)
+  }
+
+  pub fn put_pixel(&mut self, x: i32, y: i32, color: [u8; 3]) {
+    if let Some(index) = self.index(x, y) {
+      self.buffer[index..index + 4].copy_from_slice(&[color[0], color[1], color[2], 255]);
+    }
+  }
+
+  ///
+  /// Mixes a color over what's already there. 255 alpha is the same as put_pixel.
+  ///
+  pub fn blend_pixel(&mut self, x: i32, y: i32, color: [u8; 3], alpha: u8) {
+    if let Some(index) = self.index(x, y) {
+      let alpha = alpha as u32;
+      for (channel, new) in color.iter().enumerate() {
+        let old = self.buffer[index + channel] as u32;
+        self.buffer[index + channel] = ((*new as u32 * alpha + old * (255 - alpha)) / 255) as u8;
+      }
+    }
+  }
+
+  pub fn fill_rect(&mut self, position: IVec2, size: IVec2, color: [u8; 3]) {
+    for y in position.y..position.y + size.y {
+      for x in position.x..position.x + size.x {
+        self.put_pixel(x, y, color);
+      }
+    }
+  }
+
+  ///
+  /// Dims a rectangle so text on top of it is readable no matter what's behind it.
+  ///
+  pub fn darken_rect(&mut self, position: IVec2, size: IVec2) {
+    for y in position.y..position.y + size.y {
+      for x in position.x..position.x + size.x {
+        self.blend_pixel(x, y, [0, 0, 0], 160);
+      }
+    }
+  }
+
+  ///
+  /// Bresenham again, but this one clips.
+  ///
+  pub fn draw_line(&mut self, from: IVec2, to: IVec2, color: [u8; 3]) {
+    let delta = IVec2::new((to.x - from.x).abs(), -(to.y - from.y).abs());
+    let step = IVec2::new((to.x - from.x).signum(), (to.y - from.y).signum());
+    let mut error = delta.x + delta.y;
+    let mut point = from;
+
+    loop {
+      self.put_pixel(point.x, point.y, color);
+      if point == to {
+        break;
+      }
+      let doubled = error * 2;
+      if doubled >= delta.y {
+        error += delta.y;
+        point.x += step.x;
+      }
+      if doubled <= delta.x {
+        error += delta.x;
+        point.y += step.y;
+      }
+    }
+  }
+}
@@ -0,0 +1,758 @@
+use std::time::{Duration, Instant};
+
+use sdl2::keyboard::Keycode;
+use spin_sleep::LoopHelper;
+
+use super::{
+  audio::{self, Audio},
+  bot::{Bots, MAX_BOTS},
+  input::InputSource,
+  launch_options::LaunchOptions,
+  level::{Campaign, Level},
+  menu::{Menu, MenuAction},
+  net::{ListenServer, NetClient},
+  player_input::PlayerInput,
+  profiler::{Phase, Profiler},
+  renderer::Renderer,
+  save::{SaveGame, QUICKSAVE_SLOT},
+  settings::Settings,
+  weapon::Weapons,
+  win_handler::WinHandler,
+  world::{Loadout, World},
+};
+
+// What --host and local deathmatch play when --level doesn't say.
+const DEFAULT_DEATHMATCH_LEVEL: &str = "arena";
+// How many players local deathmatch is set up for until somebody changes it in the menu.
+const DEFAULT_SPLIT_PLAYERS: usize = 2;
+
+///
+/// What the game is doing right now.
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GameState {
+  MainMenu,
+  Playing,
+  Paused,
+  Options,
+  SaveMenu,
+  LoadMenu,
+  Intermission,
+  GameOver,
+}
+
+pub struct Boom {
+  settings: Settings,
+  window: WinHandler,
+  renderer: Renderer,
+  audio: Audio,
+  world: World,
+  should_close: bool,
+  loop_helper: LoopHelper,
+  delta: f64,
+  current_fps: f64,
+  profiler: Profiler,
+  launch_options: LaunchOptions,
+  state: GameState,
+  // Where the options, save and load menus go back to.
+  menu_from: GameState,
+  menu: Option<Menu>,
+  campaign: Campaign,
+  weapons: Weapons,
+  // Which level of the campaign is being played.
+  level_index: usize,
+  // What the player walked into the current level with, for trying again. None is a fresh player.
+  level_loadout: Option<Loadout>,
+  // Set while in a deathmatch. The world is then just what the server last said, plus prediction.
+  net: Option<NetClient>,
+  // Set while hosting one.
+  server: Option<ListenServer>,
+  // One per player sitting at the screen, in the same order as the world's players.
+  input_sources: Vec<InputSource>,
+  // What the main menu's deathmatch item is set to.
+  split_players: usize,
+  // The computer players in a deathmatch at this screen. Ones in a hosted game live on
+  // the server instead.
+  bots: Bots,
+  // What the main menu's bots item is set to.
+  bot_count: usize,
+}
+
+impl Boom {
+  pub fn new() -> Self {
+    let settings = Settings::load();
+    let launch_options = LaunchOptions::from_args();
+
+    // SDL only reads this when it starts, so it has to go in before anything touches SDL.
+    if let Some(driver) = &launch_options.audio_driver {
+      std::env::set_var("SDL_AUDIODRIVER", driver);
+    }
+    if launch_options.audio_test {
+      audio::self_test();
+      std::process::exit(0);
+    }
+
+    let campaign_path = launch_options
+      .campaign
+      .clone()
+      .unwrap_or_else(Campaign::default_path);
+    let campaign = Campaign::load(&campaign_path).unwrap_or_else(|e| panic!("{}", e));
+
+    let level_index = match &launch_options.level {
+      Some(name) => campaign.find(name).unwrap_or_else(|e| {
+        eprintln!("boom: {}, starting at the first level", e);
+        0
+      }),
+      None => 0,
+    };
+    let level = Level::load(&campaign.levels[level_index]).unwrap_or_else(|e| panic!("{}", e));
+    let weapons = Weapons::load(&Weapons::default_path()).unwrap_or_else(|e| panic!("{}", e));
+
+    let window = WinHandler::new(&settings);
+    let audio = Audio::new(window.sdl(), &settings.audio);
+
+    let bot_count = launch_options.bots.unwrap_or(0);
+    let mut boom = Boom {
+      window,
+      renderer: Renderer::new(&settings),
+      audio,
+      world: World::new(&level, &settings, &weapons),
+      should_close: false,
+      loop_helper: LoopHelper::builder()
+        .report_interval(Duration::new(1, 0))
+        .build_with_target_rate(settings.video.target_fps),
+      delta: 0.0,
+      current_fps: 0.0,
+      profiler: Profiler::new(240, launch_options.profile_csv.is_some()),
+      launch_options,
+      state: GameState::MainMenu,
+      menu_from: GameState::MainMenu,
+      menu: Some(Menu::main(DEFAULT_SPLIT_PLAYERS, bot_count)),
+      settings,
+      campaign,
+      weapons,
+      level_index,
+      level_loadout: None,
+      net: None,
+      server: None,
+      input_sources: vec![InputSource::KeyboardMouse],
+      split_players: DEFAULT_SPLIT_PLAYERS,
+      bots: Bots::default(),
+      bot_count,
+    };
+
+    boom.audio.play_music(boom.world.music.as_deref());
+    let host = boom.launch_options.host;
+    let connect = boom.launch_options.connect.clone();
+    let split = boom.launch_options.split;
+    if let Some(port) = host {
+      let level = boom.deathmatch_level();
+      if boom.host_game(port, &level) {
+        boom.set_state(GameState::Playing);
+      }
+    } else if let Some(address) = connect {
+      if boom.join_game(&address) {
+        boom.set_state(GameState::Playing);
+      }
+    } else if let Some(players) = split {
+      if boom.start_local_deathmatch(players) {
+        boom.set_state(GameState::Playing);
+      }
+    } else if boom.launch_options.bots.is_some() {
+      if boom.start_local_deathmatch(1) {
+        boom.set_state(GameState::Playing);
+      }
+    } else if boom.launch_options.level.is_some() {
+      boom.set_state(GameState::Playing);
+    }
+
+    return boom;
+  }
+
+  ///
+  /// Moves to a new state, swapping the menu out and grabbing or freeing the mouse to suit.
+  ///
+  fn set_state(&mut self, state: GameState) {
+    let submenu = |state| {
+      matches!(
+        state,
+        GameState::Options | GameState::SaveMenu | GameState::LoadMenu
+      )
+    };
+    if submenu(state) && !submenu(self.state) {
+      self.menu_from = self.state;
+    }
+
+    // The main menu is as far out of a network game as it gets.
+    if state == GameState::MainMenu {
+      self.leave_game();
+    }
+
+    self.state = state;
+    self.menu = match state {
+      GameState::MainMenu => Some(Menu::main(self.split_players, self.bot_count)),
+      GameState::Playing => None,
+      GameState::Paused => Some(Menu::pause()),
+      GameState::Options => Some(Menu::options(&self.settings)),
+      GameState::SaveMenu => Some(Menu::save_slots(true, false)),
+      GameState::LoadMenu => Some(Menu::save_slots(
+        false,
+        self.menu_from == GameState::MainMenu,
+      )),
+      GameState::Intermission => Some(Menu::intermission(
+        &self.world.level_name,
+        self.world.stats(),
+        self.level_index + 1 == self.campaign.levels.len(),
+      )),
+      GameState::GameOver => Some(Menu::game_over()),
+    };
+
+    self.window.set_mouse_capture(state == GameState::Playing);
+  }
+
+  ///
+  /// Does whatever a picked menu item says. Step is which way left/right pushed it, 0 for enter.
+  ///
+  fn do_menu_action(&mut self, action: MenuAction, step: i32) {
+    // Enter starts a deathmatch, left and right only change how many it's for.
+    if action == MenuAction::SplitScreen && step == 0 {
+      if self.start_local_deathmatch(self.split_players) {
+        self.set_state(GameState::Playing);
+      }
+      return;
+    }
+
+    // Enter on a value just bumps it up.
+    let step = if step == 0 { 1 } else { step };
+
+    match action {
+      MenuAction::NewGame => {
+        if self.start_level(0, None) {
+          self.set_state(GameState::Playing);
+        }
+      }
+      MenuAction::SplitScreen => {
+        self.split_players = (self.split_players as i32 + step).clamp(1, 4) as usize;
+        self.refresh_main_menu();
+      }
+      MenuAction::Bots => {
+        self.bot_count = (self.bot_count as i32 + step).clamp(0, MAX_BOTS as i32) as usize;
+        self.refresh_main_menu();
+      }
+      MenuAction::NextLevel => {
+        let next = self.level_index + 1;
+        let loadout = self.world.player().loadout();
+        if next < self.campaign.levels.len() && self.start_level(next, Some(loadout)) {
+          self.set_state(GameState::Playing);
+        } else {
+          self.set_state(GameState::MainMenu);
+        }
+      }
+      MenuAction::RestartLevel => {
+        if self.start_level(self.level_index, self.level_loadout.clone()) {
+          self.set_state(GameState::Playing);
+        }
+      }
+      MenuAction::Load => self.set_state(GameState::LoadMenu),
+      MenuAction::Save => self.set_state(GameState::SaveMenu),
+      MenuAction::LoadSlot(slot) => {
+        if self.load_game(slot) {
+          self.set_state(GameState::Playing);
+        }
+      }
+      MenuAction::SaveSlot(slot) => {
+        self.save_game(slot);
+        self.set_state(self.menu_from);
+      }
+      MenuAction::Options => self.set_state(GameState::Options),
+      MenuAction::Quit => self.should_close = true,
+      MenuAction::Resume => self.set_state(GameState::Playing),
+      MenuAction::QuitToMenu => self.set_state(GameState::MainMenu),
+      MenuAction::Back => self.set_state(self.menu_from),
+      MenuAction::Fov => {
+        self.settings.video.fov = (self.settings.video.fov + 5.0 * step as f64).clamp(30.0, 150.0);
+      }
+      MenuAction::MouseSensitivity => {
+        self.settings.input.mouse_sensitivity =
+          (self.settings.input.mouse_sensitivity + step as f64).clamp(1.0, 100.0);
+      }
+      MenuAction::RenderResolution => {
+        self.settings.video.render_resolution =
+          self.settings.video.render_resolution.cycle_preset(step);
+      }
+      MenuAction::UpscaleFilter => {
+        self.settings.video.upscale_filter = self.settings.video.upscale_filter.toggled();
+      }
+      MenuAction::SfxVolume => {
+        // Whole percents, so stepping doesn't pile up float fuzz in the settings file.
+        let percent = (self.settings.audio.sfx_volume * 100.0).round() + 10.0 * step as f64;
+        self.settings.audio.sfx_volume = percent.clamp(0.0, 100.0) / 100.0;
+      }
+      MenuAction::MusicVolume => {
+        let percent = (self.settings.audio.music_volume * 100.0).round() + 10.0 * step as f64;
+        self.settings.audio.music_volume = percent.clamp(0.0, 100.0) / 100.0;
+      }
+    }
+
+    // Rebuild it so the values on screen are fresh, but stay on the same line.
+    if self.state == GameState::Options {
+      let selected = self.menu.as_ref().map_or(0, |menu| menu.selected);
+      let mut menu = Menu::options(&self.settings);
+      menu.selected = selected;
+      self.menu = Some(menu);
+    }
+  }
+
+  ///
+  /// Escape pauses while playing and backs out of menus.
+  ///
+  fn handle_escape(&mut self) {
+    match self.state {
+      GameState::Playing => self.set_state(GameState::Paused),
+      GameState::Paused => self.set_state(GameState::Playing),
+      GameState::Options | GameState::SaveMenu | GameState::LoadMenu => {
+        self.set_state(self.menu_from)
+      }
+      GameState::MainMenu | GameState::Intermission | GameState::GameOver => (),
+    }
+  }
+
+  ///
+  /// Builds a new world out of a level in the campaign. The loadout comes along from the last level.
+  ///
+  /// A level that won't load gets reported and the current world stays.
+  ///
+  fn start_level(&mut self, index: usize, loadout: Option<Loadout>) -> bool {
+    match Level::load(&self.campaign.levels[index]) {
+      Ok(level) => {
+        self.leave_game();
+        self.world = World::new(&level, &self.settings, &self.weapons);
+        self.audio.stop_all();
+        self.audio.play_music(self.world.music.as_deref());
+        if let Some(loadout) = &loadout {
+          self.world.player_mut().apply_loadout(loadout.clone());
+        }
+        self.level_index = index;
+        self.level_loadout = loadout;
+        true
+      }
+      Err(e) => {
+        eprintln!("boom: {}", e);
+        false
+      }
+    }
+  }
+
+  ///
+  /// Writes the world into a save slot. Complains instead of crashing if that fails.
+  ///
+  fn save_game(&mut self, slot: usize) {
+    if self.net.is_some() {
+      eprintln!("boom: network games can't be saved");
+      return;
+    }
+    if self.world.local_players > 1 {
+      eprintln!("boom: split screen games can't be saved");
+      return;
+    }
+    if !self.bots.bots.is_empty() {
+      eprintln!("boom: games with bots can't be saved");
+      return;
+    }
+    match SaveGame::from_world(&self.world).write(slot) {
+      Ok(path) => println!("game saved to {}", path.display()),
+      Err(e) => eprintln!("boom: {}", e),
+    }
+  }
+
+  ///
+  /// Swaps the world for the one in a save slot. The current world stays if the save is broken.
+  ///
+  fn load_game(&mut self, slot: usize) -> bool {
+    let save = match SaveGame::read(slot) {
+      Ok(save) => save,
+      Err(e) => {
+        eprintln!("boom: {}", e);
+        return false;
+      }
+    };
+
+    // Saves remember the level by id, so they still work if the campaign gets reordered.
+    let loaded = self
+      .campaign
+      .find(&save.level)
+      .and_then(|index| Level::load(&self.campaign.levels[index]).map(|level| (index, level)));
+    let (index, level) = match loaded {
+      Ok(loaded) => loaded,
+      Err(e) => {
+        eprintln!("boom: {}", e);
+        return false;
+      }
+    };
+
+    match save.to_world(&level, &self.settings, &self.weapons) {
+      Ok(world) => {
+        self.leave_game();
+        self.level_index = index;
+        self.level_loadout = Some(world.player().loadout());
+        self.world = world;
+        self.audio.stop_all();
+        self.audio.play_music(self.world.music.as_deref());
+        true
+      }
+      Err(e) => {
+        eprintln!("boom: {}", e);
+        false
+      }
+    }
+  }
+
+  ///
+  /// The level deathmatch gets played on. Whatever --level said, or the arena.
+  ///
+  fn deathmatch_level(&self) -> String {
+    match self.launch_options.level {
+      Some(_) => self.campaign.levels[self.level_index].clone(),
+      None => DEFAULT_DEATHMATCH_LEVEL.to_string(),
+    }
+  }
+
+  ///
+  /// Rebuilds the main menu to show what its items are set to now, staying on the same item.
+  ///
+  fn refresh_main_menu(&mut self) {
+    let selected = self.menu.as_ref().map_or(0, |menu| menu.selected);
+    let mut menu = Menu::main(self.split_players, self.bot_count);
+    menu.selected = selected;
+    self.menu = Some(menu);
+  }
+
+  ///
+  /// A deathmatch for everybody sitting at this screen, each with their own slice of it,
+  /// plus however many bots the menu says.
+  ///
+  /// The keyboard and mouse go to the first player and controllers to the rest, see
+  /// InputSource::assign.
+  ///
+  fn start_local_deathmatch(&mut self, players: usize) -> bool {
+    let level = match Level::load(&self.deathmatch_level()) {
+      Ok(level) => level,
+      Err(e) => {
+        eprintln!("boom: {}", e);
+        return false;
+      }
+    };
+
+    self.input_sources = InputSource::assign(players, &self.window);
+    if self.input_sources.len() < players {
+      eprintln!(
+        "boom: only enough input for {} of {} players, plug in more gamepads",
+        self.input_sources.len(),
+        players
+      );
+    }
+
+    self.leave_game();
+    let mut world = World::new_deathmatch(&level, &self.settings, &self.weapons);
+    for number in 1..=players {
+      world.add_player(&format!("player {}", number));
+    }
+    world.local_players = players;
+    for _ in 0..self.bot_count {
+      self.bots.add(&mut world, self.settings.gameplay.bot_skill);
+    }
+    self.world = world;
+    self.audio.stop_all();
+    self.audio.play_music(self.world.music.as_deref());
+    true
+  }
+
+  ///
+  /// Starts a deathmatch server on its own thread, then joins it like anybody else would.
+  ///
+  fn host_game(&mut self, port: u16, level_id: &str) -> bool {
+    let level = match Level::load(level_id) {
+      Ok(level) => level,
+      Err(e) => {
+        eprintln!("boom: {}", e);
+        return false;
+      }
+    };
+    let settings = self.settings.clone();
+    match ListenServer::start(port, level, settings, self.weapons.clone(), self.bot_count) {
+      Ok(server) => {
+        let address = format!("127.0.0.1:{}", server.port);
+        self.server = Some(server);
+        self.join_game(&address)
+      }
+      Err(e) => {
+        eprintln!("boom: could not host: {}", e);
+        false
+      }
+    }
+  }
+
+  ///
+  /// Connects to a deathmatch server and swaps the world for its level.
+  ///
+  fn join_game(&mut self, address: &str) -> bool {
+    let name = self.player_name();
+    let mut client = match NetClient::connect(address, &name) {
+      Ok(client) => client,
+      Err(e) => {
+        eprintln!("boom: could not join {}: {}", address, e);
+        self.server = None;
+        return false;
+      }
+    };
+    let level = match Level::load(&client.level) {
+      Ok(level) => level,
+      Err(e) => {
+        eprintln!("boom: {}", e);
+        self.server = None;
+        return false;
+      }
+    };
+
+    let mut world = World::new_deathmatch(&level, &self.settings, &self.weapons);
+    client.join(&mut world, &name);
+    self.world = world;
+    self.net = Some(client);
+    self.audio.stop_all();
+    self.audio.play_music(self.world.music.as_deref());
+    true
+  }
+
+  ///
+  /// What --name says, or just player.
+  ///
+  fn player_name(&self) -> String {
+    self
+      .launch_options
+      .name
+      .clone()
+      .unwrap_or_else(|| "player".to_string())
+  }
+
+  ///
+  /// The server moved on to another level, so the world gets rebuilt for it. A level we
+  /// don't have means we can't follow, so that's the end of the game.
+  ///
+  fn follow_level_change(&mut self) {
+    let name = self.player_name();
+    let Some(net) = self.net.as_mut() else {
+      return;
+    };
+    let level = match Level::load(&net.level) {
+      Ok(level) => level,
+      Err(e) => {
+        eprintln!("boom: {}", e);
+        self.set_state(GameState::MainMenu);
+        return;
+      }
+    };
+
+    let mut world = World::new_deathmatch(&level, &self.settings, &self.weapons);
+    net.join(&mut world, &name);
+    self.world = world;
+    self.audio.stop_all();
+    self.audio.play_music(self.world.music.as_deref());
+  }
+
+  ///
+  /// Hangs up on the server, and shuts it down if it's ours. Any bots go too.
+  ///
+  fn leave_game(&mut self) {
+    self.net = None;
+    self.server = None;
+    self.bots = Bots::default();
+  }
+
+  ///
+  /// F5 quicksaves and F9 quickloads.
+  ///
+  fn handle_quicksave_keys(&mut self) {
+    if self.window.key_pressed(Keycode::F5) {
+      self.save_game(QUICKSAVE_SLOT);
+    }
+    if self.window.key_pressed(Keycode::F9) {
+      self.load_game(QUICKSAVE_SLOT);
+    }
+  }
+
+  ///
+  /// Options that can be tweaked with hotkeys while playing.
+  ///
+  /// = and - change mouse sensitivity, [ and ] change the FOV.
+  /// F2 flips through render resolutions, F4 swaps the upscale filter.
+  ///
+  fn handle_setting_keys(&mut self) {
+    if self.window.key_pressed(Keycode::Equals) {
+      self.do_menu_action(MenuAction::MouseSensitivity, 1);
+    }
+    if self.window.key_pressed(Keycode::Minus) {
+      self.do_menu_action(MenuAction::MouseSensitivity, -1);
+    }
+    if self.window.key_pressed(Keycode::RightBracket) {
+      self.do_menu_action(MenuAction::Fov, 1);
+    }
+    if self.window.key_pressed(Keycode::LeftBracket) {
+      self.do_menu_action(MenuAction::Fov, -1);
+    }
+    if self.window.key_pressed(Keycode::F2) {
+      self.do_menu_action(MenuAction::RenderResolution, 1);
+    }
+    if self.window.key_pressed(Keycode::F4) {
+      self.do_menu_action(MenuAction::UpscaleFilter, 1);
+    }
+  }
+
+  ///
+  /// Pushes any changed settings out to everything that uses them, then writes them back to the settings file.
+  ///
+  fn apply_settings(&mut self, old_settings: &Settings) {
+    self.settings.video.window_width = self.window.window_size.x;
+    self.settings.video.window_height = self.window.window_size.y;
+
+    if self.settings != *old_settings {
+      self.window.mouse_sensitivity = self.settings.input.mouse_sensitivity;
+      self.world.set_fov(self.settings.video.fov);
+      self.renderer.render_resolution = self.settings.video.render_resolution;
+      self.renderer.upscale_filter = self.settings.video.upscale_filter;
+      self.audio.set_volumes(&self.settings.audio);
+      self.settings.save();
+    }
+  }
+
+  ///
+  /// Automatically poll the FPS counter from spin_sleep.
+  ///
+  fn update_fps(&mut self) {
+    if let Some(fps) = self.loop_helper.report_rate() {
+      self.current_fps = fps;
+    }
+  }
+
+  ///
+  /// Main logic of Boom.
+  ///
+  fn main(&mut self) {
+    self.delta = self.loop_helper.loop_start_s();
+
+    let started = Instant::now();
+    self.window.poll();
+    self.profiler.record(Phase::Poll, started.elapsed());
+
+    if self.window.quit_received {
+      self.should_close = true;
+    }
+
+    if self.window.key_pressed(Keycode::F3) {
+      self.profiler.show_overlay = !self.profiler.show_overlay;
+    }
+
+    let old_settings = self.settings.clone();
+
+    if self.window.key_pressed(Keycode::Escape) {
+      self.handle_escape();
+    } else if self.state == GameState::Playing {
+      self.handle_setting_keys();
+      if self.net.is_none() {
+        self.handle_quicksave_keys();
+      }
+      self.renderer.automap.handle_input(&self.window, self.delta);
+
+      let mut inputs: Vec<PlayerInput> = self
+        .input_sources
+        .iter()
+        .take(self.world.local_player_count())
+        .map(|source| source.read(&self.window, &self.settings.input, self.delta))
+        .collect();
+      let started = Instant::now();
+      // Bots press their buttons in the same places everybody else does.
+      if !self.bots.bots.is_empty() {
+        inputs.resize(self.world.players.len(), PlayerInput::default());
+        for (index, input) in self.bots.think(&self.world, self.delta) {
+          inputs[index] = input;
+        }
+      }
+      match self.net.as_mut() {
+        Some(net) => net.update(&mut self.world, &inputs[0], self.delta),
+        None => self.world.on_tick(self.delta, &inputs),
+      }
+      self.profiler.record(Phase::Tick, started.elapsed());
+      self.audio.update(&mut self.world);
+
+      if !self.world.deathmatch && self.world.player().health <= 0 {
+        self.set_state(GameState::GameOver);
+      } else if self.world.exited {
+        self.set_state(GameState::Intermission);
+      }
+    } else if let Some((action, step)) = self
+      .menu
+      .as_mut()
+      .and_then(|menu| menu.handle_input(&self.window))
+    {
+      self.do_menu_action(action, step);
+    }
+
+    // Deathmatch doesn't stop for menus, and the server needs to keep hearing from us.
+    if let (false, Some(net)) = (self.state == GameState::Playing, self.net.as_mut()) {
+      net.update(&mut self.world, &PlayerInput::default(), self.delta);
+      self.audio.update(&mut self.world);
+    }
+    if let Some(reason) = self.net.as_ref().and_then(|net| net.disconnected.clone()) {
+      eprintln!("boom: {}", reason);
+      self.set_state(GameState::MainMenu);
+    } else if self.net.as_ref().is_some_and(|net| net.level_changed) {
+      self.follow_level_change();
+    }
+
+    self.apply_settings(&old_settings);
+
+    self.update_fps();
+
+    self
+      .window
+      .change_title(format!("Boom | FPS: {} | delta: {}", self.current_fps, self.delta).as_str());
+
+    self.renderer.draw(
+      &mut self.window,
+      &mut self.world,
+      &mut self.profiler,
+      self.menu.as_ref(),
+    );
+
+    self.profiler.end_frame();
+
+    self.loop_helper.loop_sleep();
+  }
+
+  ///
+  /// Entry point to Boom.
+  ///
+  pub fn enter_main_loop(&mut self) {
+    while !self.should_close {
+      self.main();
+    }
+
+    if let Some(path) = &self.launch_options.profile_csv {
+      match self.profiler.write_csv(path) {
+        Ok(()) => println!("frame timings written to {}", path.display()),
+        Err(e) => eprintln!("boom: could not write {}: {}", path.display(), e),
+      }
+    }
+  }
+}
+
+impl Default for Boom {
+  fn default() -> Self {
+    Boom::new()
+  }
+}
+
+impl Drop for Boom {
+  fn drop(&mut self) {
+    println!("Boom dropped!")
+  }
+}
@@ -0,0 +1,209 @@
+use glam::IVec2;
+
+use super::{
+  font::{draw_text, Align, TextStyle, LINE_HEIGHT},
+  framebuffer::FrameBuffer,
+  texture::TextureData,
+  world::World,
+};
+
+///
+/// Everything drawn flat on top of the 3D view. Crosshair, counters and the weapon.
+///
+/// It all scales with the render resolution so 320x200 looks the same as 4k, just chunkier.
+/// In split screen frame is just this player's slice and it all scales down with it.
+///
+pub fn draw_hud(frame: &mut FrameBuffer, world: &World, view: usize, textures: &[TextureData]) {
+  let size = frame.size;
+  // The classic 200 pixel tall screen is scale 1.
+  let scale = (size.y / 200).max(1);
+
+  draw_weapon(frame, world, view, textures);
+  draw_crosshair(frame, scale);
+  draw_messages(frame, world, view, scale);
+
+  let player = &world.players[view];
+  let margin = 4 * scale;
+  let y = size.y - margin - 7 * scale;
+
+  let health_color = if player.health <= 25 {
+    [255, 60, 60]
+  } else {
+    [255, 255, 255]
+  };
+
+  draw_text(
+    frame,
+    format!("HEALTH {}", player.health).as_str(),
+    IVec2::new(margin, y),
+    TextStyle {
+      color: health_color,
+      scale,
+      shadow: true,
+      ..Default::default()
+    },
+  );
+  draw_text(
+    frame,
+    format!("ARMOR {}", player.armor).as_str(),
+    IVec2::new(margin + 72 * scale, y),
+    TextStyle {
+      color: [120, 180, 255],
+      scale,
+      shadow: true,
+      ..Default::default()
+    },
+  );
+  // Keys sit in a row after the armor, one little block each.
+  let key_left = margin + 136 * scale;
+  for (index, key) in player.keys.iter().enumerate() {
+    frame.fill_rect(
+      IVec2::new(key_left + index as i32 * 6 * scale, y),
+      IVec2::new(4 * scale, 7 * scale),
+      key.color(),
+    );
+  }
+
+  let ammo = match world.weapons.get(&player.weapon) {
+    Some(weapon) => format!("{} {}", weapon.ammo.name(), player.ammo_of(weapon.ammo)),
+    None => "-".to_string(),
+  };
+  draw_text(
+    frame,
+    ammo.to_uppercase().as_str(),
+    IVec2::new(size.x - margin, y),
+    TextStyle {
+      color: [255, 220, 80],
+      scale,
+      align: Align::Right,
+      shadow: true,
+    },
+  );
+
+  if world.deathmatch {
+    draw_scores(frame, world, view, scale);
+  }
+}
+
+///
+/// Frags in the corner, and a countdown across the middle while you're lying on the floor.
+///
+fn draw_scores(frame: &mut FrameBuffer, world: &World, view: usize, scale: i32) {
+  let size = frame.size;
+  let player = &world.players[view];
+  let margin = 4 * scale;
+
+  draw_text(
+    frame,
+    format!("FRAGS {}", player.frags).as_str(),
+    IVec2::new(size.x - margin, size.y - margin - (7 + LINE_HEIGHT) * scale),
+    TextStyle {
+      color: [255, 255, 255],
+      scale,
+      align: Align::Right,
+      shadow: true,
+    },
+  );
+
+  if !player.alive {
+    draw_text(
+      frame,
+      format!("RESPAWNING IN {}", player.respawn_time.max(0.0).ceil()).as_str(),
+      IVec2::new(size.x / 2, size.y / 3),
+      TextStyle {
+        color: [255, 60, 60],
+        scale: scale * 2,
+        align: Align::Center,
+        shadow: true,
+      },
+    );
+  }
+}
+
+///
+/// Messages stack down from the top left corner and fade out in their last second.
+///
+fn draw_messages(frame: &mut FrameBuffer, world: &World, view: usize, scale: i32) {
+  let margin = 4 * scale;
+
+  let player_id = world.players[view].id;
+  let messages = world
+    .messages
+    .iter()
+    .filter(|message| message.to.map_or(true, |to| to == player_id));
+
+  for (line, message) in messages.enumerate() {
+    let fade = message.time_left.min(1.0);
+    let color = [255, 255, 255].map(|channel: u8| (channel as f64 * fade) as u8);
+
+    draw_text(
+      frame,
+      &message.text,
+      IVec2::new(margin, margin + line as i32 * LINE_HEIGHT * scale),
+      TextStyle {
+        color,
+        scale,
+        shadow: true,
+        ..Default::default()
+      },
+    );
+  }
+}
+
+fn draw_crosshair(frame: &mut FrameBuffer, scale: i32) {
+  let center = frame.size / 2;
+  let gap = 2 * scale;
+  let length = 4 * scale;
+
+  for offset in gap..gap + length {
+    for thickness in 0..scale {
+      frame.put_pixel(center.x + offset, center.y + thickness, [255, 255, 255]);
+      frame.put_pixel(center.x - offset, center.y + thickness, [255, 255, 255]);
+      frame.put_pixel(center.x + thickness, center.y + offset, [255, 255, 255]);
+      frame.put_pixel(center.x + thickness, center.y - offset, [255, 255, 255]);
+    }
+  }
+}
+
+///
+/// The weapon sits at the bottom middle and swings in a figure eight while you walk.
+///
+/// Its art is a texture per animation frame, with one texture pixel blown up to a 64th of
+/// the screen height.
+///
+fn draw_weapon(frame: &mut FrameBuffer, world: &World, view: usize, textures: &[TextureData]) {
+  let player = &world.players[view];
+  if !player.alive {
+    return;
+  }
+  let Some(weapon) = world.weapons.get(&player.weapon) else {
+    return;
+  };
+  let texture = &textures[weapon.frame(player.since_shot)];
+
+  let size = frame.size;
+  let pixel_size = (size.y / 64).max(1);
+  let art_size = IVec2::new(texture.width as i32, texture.height as i32) * pixel_size;
+
+  let swing = player.bob_amount * pixel_size as f64 * 2.0;
+  let bob = IVec2::new(
+    (player.bob_phase.cos() * swing) as i32,
+    (player.bob_phase.sin().abs() * swing) as i32,
+  );
+
+  let top_left = IVec2::new((size.x - art_size.x) / 2, size.y - art_size.y) + bob;
+
+  for row in 0..texture.height {
+    for column in 0..texture.width {
+      let [r, g, b, a] = texture.pixel(column, row);
+      if a == 0 {
+        continue;
+      }
+      frame.fill_rect(
+        top_left + IVec2::new(column as i32, row as i32) * pixel_size,
+        IVec2::splat(pixel_size),
+        [r, g, b],
+      );
+    }
+  }
+}
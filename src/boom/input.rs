@@ -0,0 +1,155 @@
+use super::{
+  player_input::PlayerInput,
+  settings::InputSettings,
+  win_handler::{Gamepad, WinHandler},
+};
+use sdl2::{
+  controller::{Axis, Button},
+  keyboard::Keycode,
+};
+
+// Number keys pick weapons by their slot.
+const SLOT_KEYS: [Keycode; 9] = [
+  Keycode::Num1,
+  Keycode::Num2,
+  Keycode::Num3,
+  Keycode::Num4,
+  Keycode::Num5,
+  Keycode::Num6,
+  Keycode::Num7,
+  Keycode::Num8,
+  Keycode::Num9,
+];
+
+impl PlayerInput {
+  ///
+  /// Whatever the keyboard and mouse did since the last poll.
+  ///
+  pub fn from_window(window: &WinHandler) -> Self {
+    let slot = (1..)
+      .zip(SLOT_KEYS)
+      .filter(|(_, key)| window.key_pressed(*key))
+      .map(|(slot, _)| slot)
+      .last()
+      .unwrap_or(0);
+
+    PlayerInput {
+      forward: window.forward_down,
+      backward: window.backward_down,
+      left: window.left_down,
+      right: window.right_down,
+      jump: window.jump_down,
+      crouch: window.crouch_down,
+      fire: window.fire_down,
+      use_wall: window.key_pressed(Keycode::E),
+      turn: window.mouse_delta.x,
+      look: window.mouse_delta.y,
+      slot,
+      wheel: window.mouse_wheel,
+    }
+  }
+
+  ///
+  /// The right hand side of the keyboard, for a second player without a controller.
+  ///
+  /// Arrows walk and turn, comma and period strafe, right ctrl shoots, right shift uses,
+  /// enter jumps, right alt crouches and backspace cycles weapons.
+  ///
+  pub fn from_arrow_keys(window: &WinHandler, settings: &InputSettings, delta: f64) -> Self {
+    let turn = window.key_down(Keycode::Right) as i32 - window.key_down(Keycode::Left) as i32;
+
+    PlayerInput {
+      forward: window.key_down(Keycode::Up),
+      backward: window.key_down(Keycode::Down),
+      left: window.key_down(Keycode::Comma),
+      right: window.key_down(Keycode::Period),
+      jump: window.key_down(Keycode::Return),
+      crouch: window.key_down(Keycode::RAlt),
+      fire: window.key_down(Keycode::RCtrl),
+      use_wall: window.key_pressed(Keycode::RShift),
+      turn: turn as f64 * settings.gamepad_turn_speed * delta,
+      look: 0.0,
+      slot: 0,
+      wheel: -(window.key_pressed(Keycode::Backspace) as i32),
+    }
+  }
+
+  ///
+  /// Twin sticks. Left stick walks, right stick looks, right trigger shoots, A jumps,
+  /// B crouches, X uses and the shoulders cycle weapons.
+  ///
+  /// Movement is on or off like the keys are, past the dead zone counts as held.
+  ///
+  pub fn from_gamepad(gamepad: &Gamepad, settings: &InputSettings, delta: f64) -> Self {
+    let dead_zone = settings.gamepad_dead_zone;
+    let move_x = gamepad.axis(Axis::LeftX, dead_zone);
+    let move_y = gamepad.axis(Axis::LeftY, dead_zone);
+    let look = |axis| gamepad.axis(axis, dead_zone) * settings.gamepad_turn_speed * delta;
+    let wheel = gamepad.button_pressed(Button::LeftShoulder) as i32
+      - gamepad.button_pressed(Button::RightShoulder) as i32;
+
+    PlayerInput {
+      forward: move_y < 0.0 || gamepad.button_down(Button::DPadUp),
+      backward: move_y > 0.0 || gamepad.button_down(Button::DPadDown),
+      left: move_x < 0.0 || gamepad.button_down(Button::DPadLeft),
+      right: move_x > 0.0 || gamepad.button_down(Button::DPadRight),
+      jump: gamepad.button_down(Button::A),
+      crouch: gamepad.button_down(Button::B),
+      fire: gamepad.axis(Axis::TriggerRight, dead_zone) > 0.0,
+      use_wall: gamepad.button_pressed(Button::X),
+      turn: look(Axis::RightX),
+      look: look(Axis::RightY),
+      slot: 0,
+      wheel,
+    }
+  }
+}
+
+///
+/// Where one of the players sitting at this screen gets their input from.
+///
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum InputSource {
+  /// WASD and the mouse.
+  KeyboardMouse,
+  /// See PlayerInput::from_arrow_keys.
+  ArrowKeys,
+  /// A controller, by its SDL instance id.
+  Gamepad(u32),
+}
+
+impl InputSource {
+  ///
+  /// Hands out inputs to however many players are sitting down. The first one gets the
+  /// keyboard and mouse, then controllers go in the order they were plugged in. If that's
+  /// not enough the next player gets the arrow keys.
+  ///
+  /// Anybody left over after that just stands there, so it's worth plugging in more pads.
+  ///
+  pub fn assign(players: usize, window: &WinHandler) -> Vec<InputSource> {
+    let mut sources = vec![InputSource::KeyboardMouse];
+    sources.extend(
+      window
+        .gamepads
+        .iter()
+        .map(|gamepad| InputSource::Gamepad(gamepad.id())),
+    );
+    sources.push(InputSource::ArrowKeys);
+    sources.truncate(players);
+    sources
+  }
+
+  ///
+  /// What this player did this frame. A controller that's been unplugged does nothing.
+  ///
+  pub fn read(&self, window: &WinHandler, settings: &InputSettings, delta: f64) -> PlayerInput {
+    match self {
+      InputSource::KeyboardMouse => PlayerInput::from_window(window),
+      InputSource::ArrowKeys => PlayerInput::from_arrow_keys(window, settings, delta),
+      InputSource::Gamepad(id) => window
+        .gamepad(*id)
+        .map(|gamepad| PlayerInput::from_gamepad(gamepad, settings, delta))
+        .unwrap_or_default(),
+    }
+  }
+}
@@ -0,0 +1,87 @@
+use std::path::PathBuf;
+
+use super::bot::MAX_BOTS;
+
+///
+/// Things you can pass on the command line.
+///
+/// --profile-csv <path>  dumps every frame's timings to a csv when the game closes.
+/// --campaign <path>     plays a different campaign file.
+/// --level <name>        skips the menu and starts at a level, by id or by number in the campaign.
+/// --audio-driver <name> picks the SDL audio driver. dummy and disk work without a sound card.
+/// --audio-test          plays every sound from a few directions without a window and quits.
+/// --host <port>         runs a deathmatch server and joins it. --level picks the level.
+/// --connect <address>   joins somebody else's deathmatch, host:port or just host.
+/// --name <name>         what everybody else sees you as in deathmatch.
+/// --split <players>     2 to 4 player deathmatch on one screen. --level picks the level.
+/// --bots <count>        adds computer players to --host or --split. On its own it's you
+///                       against them.
+///
+#[derive(Default, Debug)]
+pub struct LaunchOptions {
+  pub profile_csv: Option<PathBuf>,
+  pub campaign: Option<PathBuf>,
+  pub level: Option<String>,
+  pub audio_driver: Option<String>,
+  pub audio_test: bool,
+  pub host: Option<u16>,
+  pub connect: Option<String>,
+  pub name: Option<String>,
+  pub split: Option<usize>,
+  pub bots: Option<usize>,
+}
+
+impl LaunchOptions {
+  pub fn from_args() -> Self {
+    LaunchOptions::parse(std::env::args().skip(1))
+  }
+
+  pub fn parse(mut args: impl Iterator<Item = String>) -> Self {
+    let mut options = LaunchOptions::default();
+
+    while let Some(arg) = args.next() {
+      match arg.as_str() {
+        "--profile-csv" => match args.next() {
+          Some(path) => options.profile_csv = Some(PathBuf::from(path)),
+          None => eprintln!("boom: --profile-csv needs a path"),
+        },
+        "--campaign" => match args.next() {
+          Some(path) => options.campaign = Some(PathBuf::from(path)),
+          None => eprintln!("boom: --campaign needs a path"),
+        },
+        "--level" => match args.next() {
+          Some(level) => options.level = Some(level),
+          None => eprintln!("boom: --level needs a level name or number"),
+        },
+        "--audio-driver" => match args.next() {
+          Some(driver) => options.audio_driver = Some(driver),
+          None => eprintln!("boom: --audio-driver needs a driver name"),
+        },
+        "--audio-test" => options.audio_test = true,
+        "--host" => match args.next().map(|port| port.parse::<u16>()) {
+          Some(Ok(port)) => options.host = Some(port),
+          _ => eprintln!("boom: --host needs a port number"),
+        },
+        "--connect" => match args.next() {
+          Some(address) => options.connect = Some(address),
+          None => eprintln!("boom: --connect needs an address"),
+        },
+        "--name" => match args.next() {
+          Some(name) => options.name = Some(name),
+          None => eprintln!("boom: --name needs a name"),
+        },
+        "--split" => match args.next().map(|players| players.parse::<usize>()) {
+          Some(Ok(players)) if (2..=4).contains(&players) => options.split = Some(players),
+          _ => eprintln!("boom: --split needs a number of players from 2 to 4"),
+        },
+        "--bots" => match args.next().map(|count| count.parse::<usize>()) {
+          Some(Ok(count)) if count <= MAX_BOTS => options.bots = Some(count),
+          _ => eprintln!("boom: --bots needs a number of bots from 0 to {}", MAX_BOTS),
+        },
+        _ => eprintln!("boom: ignoring unknown argument {}", arg),
+      }
+    }
+
+    options
+  }
+}
@@ -0,0 +1,527 @@
+use std::{
+  fmt, fs, io,
+  path::{Path, PathBuf},
+};
+
+use glam::{DVec2, IVec2};
+use serde::Deserialize;
+
+use super::{
+  sound::music_path,
+  trigger::{Action, Trigger},
+  world::{Door, ItemKind},
+};
+
+///
+/// Where level and campaign files live, next to the textures folder.
+///
+pub const LEVELS_DIR: &str = "levels";
+
+///
+/// Something went wrong reading a level or campaign file.
+///
+#[derive(Debug)]
+pub enum LevelError {
+  Io(PathBuf, io::Error),
+  Parse(PathBuf, toml::de::Error),
+  Invalid(PathBuf, String),
+  UnknownLevel(String),
+}
+
+impl fmt::Display for LevelError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      LevelError::Io(path, e) => write!(f, "could not access {}: {}", path.display(), e),
+      LevelError::Parse(path, e) => write!(f, "could not parse {}: {}", path.display(), e),
+      LevelError::Invalid(path, reason) => {
+        write!(f, "invalid level {}: {}", path.display(), reason)
+      }
+      LevelError::UnknownLevel(name) => write!(f, "no level called {} in the campaign", name),
+    }
+  }
+}
+
+///
+/// A level file, exactly as it's written.
+///
+/// The map is one string per x, with one character per y. A dot is floor and a digit is a wall
+/// of that color. Exits are cells that end the level when the player walks onto them.
+/// Doors are walls that open when used, `{ cell = [3, 4], key = "red" }` needs the red key.
+///
+/// Triggers are [[triggers]] tables, see trigger.rs for what they can do. Scripts are
+/// rhai files, see script.rs for the hooks and what they can touch. Music is a file in
+/// the music folder. Spawns are where deathmatch players appear, the start if there are none.
+///
+#[derive(Deserialize, Debug)]
+struct LevelFile {
+  name: String,
+  map: Vec<String>,
+  start: [f64; 2],
+  #[serde(default = "default_direction")]
+  direction: [f64; 2],
+  #[serde(default)]
+  spawns: Vec<[f64; 2]>,
+  #[serde(default)]
+  mobs: Vec<[f64; 2]>,
+  #[serde(default)]
+  exits: Vec<[i32; 2]>,
+  #[serde(default)]
+  triggers: Vec<Trigger>,
+  #[serde(default)]
+  items: Vec<ItemPlacement>,
+  #[serde(default)]
+  doors: Vec<Door>,
+  /// A rhai file next to the level file.
+  #[serde(default)]
+  script: Option<String>,
+  /// A .wav or .ogg in the music folder, looped for as long as the level is played.
+  #[serde(default)]
+  music: Option<String>,
+}
+
+///
+/// `{ position = [3.5, 4.5], item = { health = 25 } }`
+///
+#[derive(Deserialize, Debug)]
+struct ItemPlacement {
+  position: [f64; 2],
+  item: ItemKind,
+}
+
+fn default_direction() -> [f64; 2] {
+  [-1.0, 0.0]
+}
+
+///
+/// A level, checked and ready to build a World out of.
+///
+#[derive(Clone, Debug)]
+pub struct Level {
+  /// The file name without the extension, what campaigns and saves call it.
+  pub id: String,
+  /// What the player sees.
+  pub name: String,
+  pub map: Vec<Vec<i32>>,
+  pub start: DVec2,
+  pub direction: DVec2,
+  /// Never empty, it's just the start when the level doesn't list any.
+  pub spawns: Vec<DVec2>,
+  pub mobs: Vec<DVec2>,
+  pub exits: Vec<IVec2>,
+  pub triggers: Vec<Trigger>,
+  pub items: Vec<(DVec2, ItemKind)>,
+  pub doors: Vec<Door>,
+  pub script: Option<LevelScript>,
+  /// Where the level's music is. Only checked to exist, it gets decoded while it plays.
+  pub music: Option<PathBuf>,
+}
+
+///
+/// The source of a level's script. It gets compiled when the World is built, so a broken
+/// script shows up in game instead of stopping the level from loading.
+///
+#[derive(Clone, Debug)]
+pub struct LevelScript {
+  pub name: String,
+  pub source: String,
+}
+
+impl Level {
+  pub fn path(id: &str) -> PathBuf {
+    Path::new(LEVELS_DIR).join(format!("{}.toml", id))
+  }
+
+  ///
+  /// Loads levels/<id>.toml.
+  ///
+  pub fn load(id: &str) -> Result<Self, LevelError> {
+    let path = Level::path(id);
+    let raw = fs::read_to_string(&path).map_err(|e| LevelError::Io(path.clone(), e))?;
+    let file: LevelFile = toml::from_str(&raw).map_err(|e| LevelError::Parse(path.clone(), e))?;
+
+    let invalid = |reason: String| LevelError::Invalid(path.clone(), reason);
+
+    let map = file
+      .map
+      .iter()
+      .enumerate()
+      .map(|(x, row)| {
+        row
+          .chars()
+          .enumerate()
+          .map(|(y, cell)| match cell {
+            '.' => Ok(0),
+            '1'..='9' => Ok(cell as i32 - '0' as i32),
+            _ => Err(invalid(format!("unknown cell {:?} at {}, {}", cell, x, y))),
+          })
+          .collect::<Result<Vec<i32>, LevelError>>()
+      })
+      .collect::<Result<Vec<Vec<i32>>, LevelError>>()?;
+
+    check_map(&map).map_err(invalid)?;
+    let (width, height) = (map.len(), map[0].len());
+    let floor = |position: DVec2| on_floor(&map, position);
+
+    let start = DVec2::from(file.start);
+    if !floor(start) {
+      return Err(invalid("player starts inside a wall".to_string()));
+    }
+    let direction = DVec2::from(file.direction).normalize_or_zero();
+    if direction == DVec2::ZERO {
+      return Err(invalid("start direction can't be zero".to_string()));
+    }
+
+    let mut spawns: Vec<DVec2> = file.spawns.into_iter().map(DVec2::from).collect();
+    if let Some(spawn) = spawns.iter().find(|spawn| !floor(**spawn)) {
+      return Err(invalid(format!(
+        "spawn at {}, {} is inside a wall",
+        spawn.x, spawn.y
+      )));
+    }
+    if spawns.is_empty() {
+      spawns.push(start);
+    }
+
+    let mobs: Vec<DVec2> = file.mobs.into_iter().map(DVec2::from).collect();
+    if let Some(mob) = mobs.iter().find(|mob| !floor(**mob)) {
+      return Err(invalid(format!(
+        "mob at {}, {} is inside a wall",
+        mob.x, mob.y
+      )));
+    }
+
+    let exits: Vec<IVec2> = file.exits.into_iter().map(IVec2::from).collect();
+    if let Some(exit) = exits.iter().find(|exit| !floor(exit.as_dvec2())) {
+      return Err(invalid(format!(
+        "exit at {}, {} is not floor",
+        exit.x, exit.y
+      )));
+    }
+
+    let items: Vec<(DVec2, ItemKind)> = file
+      .items
+      .into_iter()
+      .map(|placement| (DVec2::from(placement.position), placement.item))
+      .collect();
+    for (position, kind) in &items {
+      if !floor(*position) {
+        return Err(invalid(format!(
+          "item at {}, {} is inside a wall",
+          position.x, position.y
+        )));
+      }
+      if kind.sprite().is_none() {
+        return Err(invalid(format!("item {:?} has no texture", kind)));
+      }
+    }
+
+    let size = IVec2::new(width as i32, height as i32);
+    for door in &file.doors {
+      let cell = door.cell;
+      if cell.x <= 0 || cell.y <= 0 || cell.x >= size.x - 1 || cell.y >= size.y - 1 {
+        return Err(invalid(format!(
+          "door at {}, {} must be inside the map edge",
+          cell.x, cell.y
+        )));
+      }
+      if map[cell.x as usize][cell.y as usize] == 0 {
+        return Err(invalid(format!(
+          "door at {}, {} needs a wall to open",
+          cell.x, cell.y
+        )));
+      }
+    }
+
+    check_triggers(&file.triggers, &map).map_err(invalid)?;
+
+    let script = match file.script {
+      Some(name) => {
+        let path = Path::new(LEVELS_DIR).join(&name);
+        let source = fs::read_to_string(&path).map_err(|e| LevelError::Io(path.clone(), e))?;
+        Some(LevelScript { name, source })
+      }
+      None => None,
+    };
+
+    let music = file.music.map(|name| music_path(&name));
+    if let Some(path) = music.as_ref().filter(|path| !path.is_file()) {
+      return Err(invalid(format!("no music file at {}", path.display())));
+    }
+
+    Ok(Level {
+      id: id.to_string(),
+      name: file.name,
+      map,
+      start,
+      direction,
+      spawns,
+      mobs,
+      exits,
+      triggers: file.triggers,
+      items,
+      doors: file.doors,
+      script,
+      music,
+    })
+  }
+}
+
+///
+/// Makes sure a map is something the game can run on. At least 3x3, every row the same
+/// length, no negative walls and walled in all the way round. Says what's wrong with it
+/// if not.
+///
+pub fn check_map(map: &[Vec<i32>]) -> Result<(), String> {
+  let width = map.len();
+  let height = map.first().map_or(0, |row| row.len());
+  if width < 3 || height < 3 {
+    return Err("map must be at least 3x3".to_string());
+  }
+  if map.iter().any(|row| row.len() != height) {
+    return Err("every map row must be the same length".to_string());
+  }
+
+  // The raycaster walks until it hits a wall, so an open edge would send it off the map.
+  for (x, row) in map.iter().enumerate() {
+    for (y, cell) in row.iter().enumerate() {
+      if *cell < 0 {
+        return Err(format!("wall {} at {}, {} is negative", cell, x, y));
+      }
+      let edge = x == 0 || y == 0 || x == width - 1 || y == height - 1;
+      if edge && *cell == 0 {
+        return Err(format!("map edge is open at {}, {}", x, y));
+      }
+    }
+  }
+  Ok(())
+}
+
+///
+/// Makes sure a map's triggers only touch cells on it, can't open its edge and only spawn
+/// mobs on the floor, where they can be got at. The map should have passed check_map.
+///
+pub fn check_triggers(triggers: &[Trigger], map: &[Vec<i32>]) -> Result<(), String> {
+  let size = IVec2::new(map.len() as i32, map[0].len() as i32);
+  for trigger in triggers {
+    if let Some(cell) = trigger
+      .cells()
+      .into_iter()
+      .find(|cell| cell.cmplt(IVec2::ZERO).any() || cell.cmpge(size).any())
+    {
+      return Err(format!(
+        "trigger cell {}, {} is off the map",
+        cell.x, cell.y
+      ));
+    }
+    if let Some(wall) = trigger.actions.iter().find_map(|action| match action {
+      Action::ChangeWall { wall, .. } if *wall < 0 => Some(wall),
+      _ => None,
+    }) {
+      return Err(format!("trigger sets a negative wall {}", wall));
+    }
+    if let Some(cell) = trigger
+      .opened_cells()
+      .into_iter()
+      .find(|cell| cell.x == 0 || cell.y == 0 || cell.x == size.x - 1 || cell.y == size.y - 1)
+    {
+      return Err(format!(
+        "trigger opens the map edge at {}, {}",
+        cell.x, cell.y
+      ));
+    }
+    if let Some(cell) = trigger.actions.iter().find_map(|action| match action {
+      Action::SpawnMob(cell) if !on_floor(map, cell.as_dvec2() + 0.5) => Some(cell),
+      _ => None,
+    }) {
+      return Err(format!(
+        "trigger spawns a mob inside a wall at {}, {}",
+        cell.x, cell.y
+      ));
+    }
+  }
+  Ok(())
+}
+
+///
+/// Is this spot on a floor cell? Anything off the map isn't.
+///
+pub fn on_floor(map: &[Vec<i32>], position: DVec2) -> bool {
+  let cell = position.floor().as_ivec2();
+  cell.x >= 0
+    && cell.y >= 0
+    && map
+      .get(cell.x as usize)
+      .and_then(|row| row.get(cell.y as usize))
+      == Some(&0)
+}
+
+///
+/// An ordered list of levels to play through.
+///
+#[derive(Deserialize, Clone, Debug)]
+pub struct Campaign {
+  pub name: String,
+  /// Level ids, in the order they get played.
+  pub levels: Vec<String>,
+}
+
+impl Campaign {
+  pub fn default_path() -> PathBuf {
+    Path::new(LEVELS_DIR).join("campaign.toml")
+  }
+
+  pub fn load(path: &Path) -> Result<Self, LevelError> {
+    let raw = fs::read_to_string(path).map_err(|e| LevelError::Io(path.to_path_buf(), e))?;
+    let campaign: Campaign =
+      toml::from_str(&raw).map_err(|e| LevelError::Parse(path.to_path_buf(), e))?;
+
+    if campaign.levels.is_empty() {
+      return Err(LevelError::Invalid(
+        path.to_path_buf(),
+        "a campaign needs at least one level".to_string(),
+      ));
+    }
+
+    Ok(campaign)
+  }
+
+  ///
+  /// Finds a level either by id or by its 1 based position in the campaign.
+  ///
+  pub fn find(&self, name: &str) -> Result<usize, LevelError> {
+    if let Some(index) = self.levels.iter().position(|level| level == name) {
+      return Ok(index);
+    }
+
+    match name.parse::<usize>() {
+      Ok(number) if (1..=self.levels.len()).contains(&number) => Ok(number - 1),
+      _ => Err(LevelError::UnknownLevel(name.to_string())),
+    }
+  }
+}
+
+///
+/// The deathmatch levels a dedicated server cycles through, and when each one ends.
+///
+#[derive(Deserialize, Clone, Debug)]
+pub struct Rotation {
+  /// Level ids, played in this order and then around again.
+  pub levels: Vec<String>,
+  /// Minutes before moving on to the next level. 0 is no limit.
+  #[serde(default)]
+  pub time_limit: f64,
+  /// Frags that win the round. 0 is no limit.
+  #[serde(default)]
+  pub frag_limit: i32,
+}
+
+impl Rotation {
+  pub fn default_path() -> PathBuf {
+    Path::new(LEVELS_DIR).join("rotation.toml")
+  }
+
+  ///
+  /// Every level in it gets loaded once up front, so a broken one shows up now instead
+  /// of halfway through the night.
+  ///
+  pub fn load(path: &Path) -> Result<Self, LevelError> {
+    let raw = fs::read_to_string(path).map_err(|e| LevelError::Io(path.to_path_buf(), e))?;
+    let rotation: Rotation =
+      toml::from_str(&raw).map_err(|e| LevelError::Parse(path.to_path_buf(), e))?;
+
+    if rotation.levels.is_empty() {
+      return Err(LevelError::Invalid(
+        path.to_path_buf(),
+        "a rotation needs at least one level".to_string(),
+      ));
+    }
+    if rotation.time_limit < 0.0 || rotation.frag_limit < 0 {
+      return Err(LevelError::Invalid(
+        path.to_path_buf(),
+        "limits can't be negative".to_string(),
+      ));
+    }
+    for level in &rotation.levels {
+      Level::load(level)?;
+    }
+
+    Ok(rotation)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::boom::trigger::Condition;
+
+  #[test]
+  fn closed_maps_pass() {
+    let map = vec![vec![1, 1, 1], vec![2, 0, 1], vec![1, 3, 1]];
+    assert_eq!(check_map(&map), Ok(()));
+    assert!(on_floor(&map, DVec2::new(1.5, 1.5)));
+    assert!(!on_floor(&map, DVec2::new(0.5, 1.5)));
+    assert!(!on_floor(&map, DVec2::new(-0.5, 1.5)));
+    assert!(!on_floor(&map, DVec2::new(1.5, 7.0)));
+  }
+
+  #[test]
+  fn open_and_negative_walls_fail() {
+    let open = vec![vec![1, 1, 1], vec![1, 0, 0], vec![1, 1, 1]];
+    assert!(check_map(&open).is_err());
+    let negative_edge = vec![vec![1, -1, 1], vec![1, 0, 1], vec![1, 1, 1]];
+    assert!(check_map(&negative_edge).is_err());
+    let negative_inside = vec![vec![1, 1, 1, 1], vec![1, -3, 0, 1], vec![1, 1, 1, 1]];
+    assert!(check_map(&negative_inside).is_err());
+    let ragged = vec![vec![1, 1, 1], vec![1, 0], vec![1, 1, 1]];
+    assert!(check_map(&ragged).is_err());
+  }
+
+  #[test]
+  fn triggers_stay_on_the_floor() {
+    let map = vec![
+      vec![1, 1, 1, 1],
+      vec![1, 0, 2, 1],
+      vec![1, 0, 0, 1],
+      vec![1, 1, 1, 1],
+    ];
+    let trigger = |action: Action| Trigger {
+      when: Condition::AllMobsDead,
+      actions: vec![action],
+      once: true,
+      fired: false,
+      active: false,
+    };
+
+    let good = [
+      trigger(Action::SpawnMob(IVec2::new(2, 2))),
+      trigger(Action::OpenDoor(IVec2::new(1, 2))),
+    ];
+    assert_eq!(check_triggers(&good, &map), Ok(()));
+
+    for action in [
+      Action::SpawnMob(IVec2::new(1, 2)),
+      Action::SpawnMob(IVec2::new(0, 0)),
+      Action::SpawnMob(IVec2::new(5, 1)),
+      Action::OpenDoor(IVec2::new(0, 1)),
+      Action::ChangeWall {
+        cell: IVec2::new(1, 2),
+        wall: -1,
+      },
+    ] {
+      assert!(
+        check_triggers(&[trigger(action.clone())], &map).is_err(),
+        "{:?} got through",
+        action
+      );
+    }
+  }
+
+  #[test]
+  fn every_shipped_level_loads() {
+    let campaign = Campaign::load(&Campaign::default_path()).unwrap();
+    for id in &campaign.levels {
+      Level::load(id).unwrap_or_else(|e| panic!("{}", e));
+    }
+    Rotation::load(&Rotation::default_path()).unwrap_or_else(|e| panic!("{}", e));
+  }
+}
@@ -0,0 +1,340 @@
+use glam::IVec2;
+use sdl2::keyboard::Keycode;
+
+use super::{
+  font::{draw_text, Align, TextStyle, LINE_HEIGHT},
+  framebuffer::FrameBuffer,
+  save::{SaveGame, QUICKSAVE_SLOT, SAVE_SLOTS},
+  settings::Settings,
+  win_handler::WinHandler,
+  world::LevelStats,
+};
+
+///
+/// What picking a menu item does. Boom decides what that actually means.
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MenuAction {
+  NewGame,
+  SplitScreen,
+  Bots,
+  Load,
+  Save,
+  LoadSlot(usize),
+  SaveSlot(usize),
+  NextLevel,
+  RestartLevel,
+  Options,
+  Quit,
+  Resume,
+  QuitToMenu,
+  Back,
+  Fov,
+  MouseSensitivity,
+  RenderResolution,
+  UpscaleFilter,
+  SfxVolume,
+  MusicVolume,
+}
+
+pub struct MenuItem {
+  pub label: String,
+  pub action: MenuAction,
+  pub enabled: bool,
+}
+
+impl MenuItem {
+  pub fn new(label: &str, action: MenuAction) -> Self {
+    MenuItem {
+      label: label.to_string(),
+      action,
+      enabled: true,
+    }
+  }
+
+  pub fn disabled(label: &str, action: MenuAction) -> Self {
+    MenuItem {
+      enabled: false,
+      ..MenuItem::new(label, action)
+    }
+  }
+}
+
+///
+/// A title and a list of things to pick from.
+///
+/// Up and down move, enter picks, left and right change values.
+///
+pub struct Menu {
+  pub title: String,
+  pub items: Vec<MenuItem>,
+  pub selected: usize,
+  /// Covers the world completely instead of dimming it.
+  pub opaque: bool,
+}
+
+impl Menu {
+  pub fn new(title: &str, items: Vec<MenuItem>, opaque: bool) -> Self {
+    let mut menu = Menu {
+      title: title.to_string(),
+      items,
+      selected: 0,
+      opaque,
+    };
+    if !menu.items[0].enabled {
+      menu.move_selection(1);
+    }
+    menu
+  }
+
+  ///
+  /// Deathmatch shows how many players it's for and how many bots they get to shoot at,
+  /// left and right change those.
+  ///
+  pub fn main(split_players: usize, bots: usize) -> Self {
+    let players = match split_players {
+      1 => "Deathmatch: 1 player".to_string(),
+      _ => format!("Deathmatch: {} players", split_players),
+    };
+    Menu::new(
+      "BOOM",
+      vec![
+        MenuItem::new("New game", MenuAction::NewGame),
+        MenuItem::new(players.as_str(), MenuAction::SplitScreen),
+        MenuItem::new(format!("Bots: {}", bots).as_str(), MenuAction::Bots),
+        MenuItem::new("Load", MenuAction::Load),
+        MenuItem::new("Options", MenuAction::Options),
+        MenuItem::new("Quit", MenuAction::Quit),
+      ],
+      true,
+    )
+  }
+
+  pub fn pause() -> Self {
+    Menu::new(
+      "PAUSED",
+      vec![
+        MenuItem::new("Resume", MenuAction::Resume),
+        MenuItem::new("Save", MenuAction::Save),
+        MenuItem::new("Load", MenuAction::Load),
+        MenuItem::new("Options", MenuAction::Options),
+        MenuItem::new("Quit to menu", MenuAction::QuitToMenu),
+        MenuItem::new("Quit", MenuAction::Quit),
+      ],
+      false,
+    )
+  }
+
+  pub fn game_over() -> Self {
+    Menu::new(
+      "YOU DIED",
+      vec![
+        MenuItem::new("Try again", MenuAction::RestartLevel),
+        MenuItem::new("Quit to menu", MenuAction::QuitToMenu),
+      ],
+      false,
+    )
+  }
+
+  ///
+  /// The stats screen between levels. The stats are disabled items so only continue can be picked.
+  ///
+  pub fn intermission(level_name: &str, stats: LevelStats, last_level: bool) -> Self {
+    let seconds = stats.time as u64;
+    Menu::new(
+      if last_level {
+        "CAMPAIGN COMPLETE"
+      } else {
+        "LEVEL COMPLETE"
+      },
+      vec![
+        MenuItem::disabled(level_name, MenuAction::NextLevel),
+        MenuItem::disabled(
+          format!("Kills: {}/{}", stats.kills, stats.total_mobs).as_str(),
+          MenuAction::NextLevel,
+        ),
+        MenuItem::disabled(
+          format!("Time: {}:{:02}", seconds / 60, seconds % 60).as_str(),
+          MenuAction::NextLevel,
+        ),
+        MenuItem::new(
+          if last_level {
+            "Back to menu"
+          } else {
+            "Continue"
+          },
+          MenuAction::NextLevel,
+        ),
+      ],
+      true,
+    )
+  }
+
+  ///
+  /// Lists the save slots. Loading also offers the quicksave, and empty slots can't be loaded.
+  ///
+  pub fn save_slots(saving: bool, opaque: bool) -> Self {
+    let first = if saving { 1 } else { QUICKSAVE_SLOT };
+
+    let mut items: Vec<MenuItem> = (first..=SAVE_SLOTS)
+      .map(|slot| {
+        let name = if slot == QUICKSAVE_SLOT {
+          "Quicksave".to_string()
+        } else {
+          format!("Slot {}", slot)
+        };
+        let action = if saving {
+          MenuAction::SaveSlot(slot)
+        } else {
+          MenuAction::LoadSlot(slot)
+        };
+
+        match SaveGame::describe_slot(slot) {
+          Some(description) => MenuItem::new(format!("{}: {}", name, description).as_str(), action),
+          None if saving => MenuItem::new(format!("{}: empty", name).as_str(), action),
+          None => MenuItem::disabled(format!("{}: empty", name).as_str(), action),
+        }
+      })
+      .collect();
+    items.push(MenuItem::new("Back", MenuAction::Back));
+
+    Menu::new(if saving { "SAVE" } else { "LOAD" }, items, opaque)
+  }
+
+  ///
+  /// The options menu shows the current values, so it gets rebuilt whenever one changes.
+  ///
+  pub fn options(settings: &Settings) -> Self {
+    Menu::new(
+      "OPTIONS",
+      vec![
+        MenuItem::new(
+          format!("FOV: {}", settings.video.fov).as_str(),
+          MenuAction::Fov,
+        ),
+        MenuItem::new(
+          format!("Mouse sensitivity: {}", settings.input.mouse_sensitivity).as_str(),
+          MenuAction::MouseSensitivity,
+        ),
+        MenuItem::new(
+          format!("Resolution: {}", settings.video.render_resolution.label()).as_str(),
+          MenuAction::RenderResolution,
+        ),
+        MenuItem::new(
+          format!("Upscale: {}", settings.video.upscale_filter.sdl_hint()).as_str(),
+          MenuAction::UpscaleFilter,
+        ),
+        MenuItem::new(
+          format!(
+            "SFX volume: {}%",
+            (settings.audio.sfx_volume * 100.0).round()
+          )
+          .as_str(),
+          MenuAction::SfxVolume,
+        ),
+        MenuItem::new(
+          format!(
+            "Music volume: {}%",
+            (settings.audio.music_volume * 100.0).round()
+          )
+          .as_str(),
+          MenuAction::MusicVolume,
+        ),
+        MenuItem::new("Back", MenuAction::Back),
+      ],
+      false,
+    )
+  }
+
+  fn move_selection(&mut self, step: i32) {
+    let count = self.items.len() as i32;
+    for _ in 0..count {
+      self.selected = (self.selected as i32 + step).rem_euclid(count) as usize;
+      if self.items[self.selected].enabled {
+        return;
+      }
+    }
+  }
+
+  ///
+  /// Returns the picked action and which way it was pushed. 0 is enter, -1 and 1 are left and right.
+  ///
+  pub fn handle_input(&mut self, window: &WinHandler) -> Option<(MenuAction, i32)> {
+    if window.key_pressed(Keycode::Up) || window.key_pressed(Keycode::W) {
+      self.move_selection(-1);
+    }
+    if window.key_pressed(Keycode::Down) || window.key_pressed(Keycode::S) {
+      self.move_selection(1);
+    }
+
+    let action = self.items[self.selected].action;
+
+    if window.key_pressed(Keycode::Return) || window.key_pressed(Keycode::Space) {
+      return Some((action, 0));
+    }
+    if window.key_pressed(Keycode::Left) || window.key_pressed(Keycode::A) {
+      return Some((action, -1));
+    }
+    if window.key_pressed(Keycode::Right) || window.key_pressed(Keycode::D) {
+      return Some((action, 1));
+    }
+
+    None
+  }
+
+  pub fn draw(&self, frame: &mut FrameBuffer) {
+    let size = frame.size;
+    let scale = (size.y / 200).max(1);
+
+    if self.opaque {
+      frame.fill_rect(IVec2::ZERO, size, [16, 8, 8]);
+    } else {
+      frame.darken_rect(IVec2::ZERO, size);
+    }
+
+    let center_x = size.x / 2;
+    let mut y = size.y / 4;
+
+    draw_text(
+      frame,
+      &self.title,
+      IVec2::new(center_x, y),
+      TextStyle {
+        color: [255, 60, 40],
+        scale: scale * 3,
+        align: Align::Center,
+        shadow: true,
+      },
+    );
+    y += LINE_HEIGHT * scale * 5;
+
+    for (index, item) in self.items.iter().enumerate() {
+      let selected = index == self.selected;
+      let color = if !item.enabled {
+        [100, 100, 100]
+      } else if selected {
+        [255, 220, 80]
+      } else {
+        [220, 220, 220]
+      };
+      let label = if selected {
+        format!("> {} <", item.label)
+      } else {
+        item.label.clone()
+      };
+
+      draw_text(
+        frame,
+        &label,
+        IVec2::new(center_x, y),
+        TextStyle {
+          color,
+          scale,
+          align: Align::Center,
+          shadow: true,
+        },
+      );
+      y += LINE_HEIGHT * scale * 2;
+    }
+  }
+}
@@ -0,0 +1,295 @@
+use std::{
+  fs::File,
+  io::BufReader,
+  path::{Path, PathBuf},
+  sync::mpsc::{self, Receiver, SyncSender},
+  thread,
+};
+
+use super::audio::{AudioError, MIX_RATE};
+
+// Frames of audio the decoder hands over at a time.
+const CHUNK_FRAMES: usize = 4096;
+// Chunks the decoder is allowed to get ahead by. About three quarters of a second.
+const CHUNKS_AHEAD: usize = 8;
+// Seconds one track takes to fade into the next.
+const CROSSFADE_TIME: f32 = 2.0;
+
+///
+/// Reads a music file a packet at a time, so the whole song never has to be in memory.
+///
+enum Decoder {
+  Wav(hound::WavReader<BufReader<File>>),
+  Ogg(Box<lewton::inside_ogg::OggStreamReader<BufReader<File>>>),
+}
+
+impl Decoder {
+  fn open(path: &Path) -> Result<Self, AudioError> {
+    match path.extension().and_then(|extension| extension.to_str()) {
+      Some("wav") => hound::WavReader::open(path)
+        .map(Decoder::Wav)
+        .map_err(|e| AudioError::Wav(path.to_path_buf(), e)),
+      Some("ogg") => {
+        let file = File::open(path).map_err(|e| AudioError::Io(path.to_path_buf(), e))?;
+        lewton::inside_ogg::OggStreamReader::new(BufReader::new(file))
+          .map(|reader| Decoder::Ogg(Box::new(reader)))
+          .map_err(|e| AudioError::Ogg(path.to_path_buf(), e))
+      }
+      _ => Err(AudioError::Invalid(
+        path.to_path_buf(),
+        "only .wav and .ogg are supported".to_string(),
+      )),
+    }
+  }
+
+  fn rate(&self) -> u32 {
+    match self {
+      Decoder::Wav(reader) => reader.spec().sample_rate,
+      Decoder::Ogg(reader) => reader.ident_hdr.audio_sample_rate,
+    }
+  }
+
+  ///
+  /// The next bit of the song as stereo frames, or None once it's over.
+  ///
+  fn next_chunk(&mut self, path: &Path) -> Result<Option<Vec<[f32; 2]>>, AudioError> {
+    let (samples, channels) = match self {
+      Decoder::Wav(reader) => {
+        let spec = reader.spec();
+        let wanted = CHUNK_FRAMES * spec.channels as usize;
+        let samples = match spec.sample_format {
+          hound::SampleFormat::Float => reader
+            .samples::<f32>()
+            .take(wanted)
+            .collect::<Result<Vec<f32>, _>>(),
+          hound::SampleFormat::Int => {
+            let scale = 1.0 / (1i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+              .samples::<i32>()
+              .take(wanted)
+              .map(|sample| sample.map(|sample| sample as f32 * scale))
+              .collect()
+          }
+        }
+        .map_err(|e| AudioError::Wav(path.to_path_buf(), e))?;
+        (samples, spec.channels as usize)
+      }
+      Decoder::Ogg(reader) => {
+        let channels = reader.ident_hdr.audio_channels as usize;
+        let packet = reader
+          .read_dec_packet_itl()
+          .map_err(|e| AudioError::Ogg(path.to_path_buf(), e))?;
+        match packet {
+          Some(packet) => (
+            packet
+              .iter()
+              .map(|sample| *sample as f32 / 32768.0)
+              .collect(),
+            channels,
+          ),
+          None => (Vec::new(), channels),
+        }
+      }
+    };
+
+    if channels == 0 {
+      return Err(AudioError::Invalid(
+        path.to_path_buf(),
+        "no channels".to_string(),
+      ));
+    }
+    if samples.is_empty() {
+      return Ok(None);
+    }
+
+    // Mono goes to both ears, anything past stereo gets dropped.
+    let frames = samples
+      .chunks_exact(channels)
+      .map(|frame| [frame[0], frame[channels.min(2) - 1]])
+      .collect();
+    Ok(Some(frames))
+  }
+}
+
+///
+/// Linear resampling that carries on from one chunk to the next without a seam.
+///
+struct Resampler {
+  step: f64,
+  /// How far past the last frame of the previous chunk the next output frame is.
+  position: f64,
+  previous: [f32; 2],
+}
+
+impl Resampler {
+  fn new(from: u32) -> Self {
+    Resampler {
+      step: from as f64 / MIX_RATE as f64,
+      position: 0.0,
+      previous: [0.0, 0.0],
+    }
+  }
+
+  ///
+  /// Interleaved stereo at the mix rate.
+  ///
+  fn push(&mut self, frames: &[[f32; 2]]) -> Vec<f32> {
+    let mut out = Vec::with_capacity((frames.len() as f64 / self.step) as usize * 2 + 2);
+    let previous = self.previous;
+    let frame_at = |index: usize| {
+      if index == 0 {
+        previous
+      } else {
+        frames[index - 1]
+      }
+    };
+
+    while self.position < frames.len() as f64 {
+      let before = self.position.floor() as usize;
+      let blend = (self.position - before as f64) as f32;
+      let (a, b) = (frame_at(before), frame_at(before + 1));
+      out.push(a[0] + (b[0] - a[0]) * blend);
+      out.push(a[1] + (b[1] - a[1]) * blend);
+      self.position += self.step;
+    }
+
+    self.position -= frames.len() as f64;
+    if let Some(last) = frames.last() {
+      self.previous = *last;
+    }
+    out
+  }
+}
+
+///
+/// Decodes a track forever, starting over at the end, and hands it over a chunk at a time.
+///
+/// Stops as soon as nobody's listening any more, which is when the MusicTrack gets dropped.
+///
+fn decode(path: PathBuf, chunks: SyncSender<Vec<f32>>) {
+  loop {
+    let mut decoder = match Decoder::open(&path) {
+      Ok(decoder) => decoder,
+      Err(e) => {
+        eprintln!("boom: {}", e);
+        return;
+      }
+    };
+    let mut resampler = Resampler::new(decoder.rate().max(1));
+    let mut played_anything = false;
+
+    loop {
+      match decoder.next_chunk(&path) {
+        Ok(Some(frames)) => {
+          played_anything = true;
+          if chunks.send(resampler.push(&frames)).is_err() {
+            return;
+          }
+        }
+        Ok(None) => break,
+        Err(e) => {
+          eprintln!("boom: {}", e);
+          return;
+        }
+      }
+    }
+
+    // An empty file would just spin here forever.
+    if !played_anything {
+      return;
+    }
+  }
+}
+
+///
+/// A song playing on the mixer. The decoding happens on its own thread, this just plays
+/// whatever has been decoded so far and fades in and out.
+///
+pub struct MusicTrack {
+  pub path: PathBuf,
+  chunks: Receiver<Vec<f32>>,
+  current: Vec<f32>,
+  cursor: usize,
+  /// 0 is silent, 1 is full volume.
+  fade: f32,
+  /// Where the fade is heading.
+  pub fade_target: f32,
+}
+
+impl MusicTrack {
+  ///
+  /// Starts decoding in the background. The track starts silent and fades in.
+  ///
+  pub fn start(path: &Path) -> Self {
+    let (sender, chunks) = mpsc::sync_channel(CHUNKS_AHEAD);
+    let decode_path = path.to_path_buf();
+    if let Err(e) = thread::Builder::new()
+      .name("music".to_string())
+      .spawn(move || decode(decode_path, sender))
+    {
+      eprintln!("boom: could not start decoding {}: {}", path.display(), e);
+    }
+
+    MusicTrack {
+      path: path.to_path_buf(),
+      chunks,
+      current: Vec::new(),
+      cursor: 0,
+      fade: 0.0,
+      fade_target: 1.0,
+    }
+  }
+
+  ///
+  /// Faded all the way out and not coming back.
+  ///
+  pub fn finished(&self) -> bool {
+    self.fade == 0.0 && self.fade_target == 0.0
+  }
+
+  ///
+  /// Adds the next stretch of the song onto out. If the decoder has fallen behind the
+  /// rest is just left quiet, it's not worth holding up the sound effects for.
+  ///
+  pub fn mix(&mut self, out: &mut [f32], channels: usize, volume: f32) {
+    let fade_step = 1.0 / (CROSSFADE_TIME * MIX_RATE as f32);
+
+    for frame in out.chunks_exact_mut(channels) {
+      if self.cursor >= self.current.len() {
+        match self.chunks.try_recv() {
+          Ok(chunk) => {
+            self.current = chunk;
+            self.cursor = 0;
+          }
+          Err(_) => break,
+        }
+        if self.current.is_empty() {
+          continue;
+        }
+      }
+
+      self.fade = if self.fade < self.fade_target {
+        (self.fade + fade_step).min(self.fade_target)
+      } else {
+        (self.fade - fade_step).max(self.fade_target)
+      };
+
+      let gain = self.fade * volume;
+      let left = self.current[self.cursor] * gain;
+      let right = self.current[self.cursor + 1] * gain;
+      self.cursor += 2;
+
+      if channels == 1 {
+        frame[0] += (left + right) * 0.5;
+      } else {
+        frame[0] += left;
+        frame[1] += right;
+      }
+    }
+
+    // A track that's fading out doesn't need to wait on the decoder to finish the job.
+    if self.fade_target == 0.0 && self.cursor >= self.current.len() {
+      self.fade = (self.fade - fade_step * (out.len() / channels) as f32).max(0.0);
+    }
+  }
+}
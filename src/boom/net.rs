@@ -0,0 +1,1482 @@
+use std::{
+  collections::{HashMap, VecDeque},
+  fmt, io,
+  net::{SocketAddr, ToSocketAddrs, UdpSocket},
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+  },
+  thread::{self, JoinHandle},
+  time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use glam::DVec2;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use spin_sleep::LoopHelper;
+
+use super::{
+  bot::Bots,
+  entity::{EntityId, Sprite},
+  level::Level,
+  player_input::PlayerInput,
+  settings::Settings,
+  sound::{SoundEvent, SOUND_NAMES},
+  texture::{texture_id, TEXTURE_NAMES},
+  weapon::{AmmoType, Weapons, MAX_WEAPONS},
+  world::{Player, World},
+};
+
+pub const DEFAULT_PORT: u16 = 7777;
+///
+/// How many times a second the server moves the world along and sends snapshots out.
+///
+pub const TICK_RATE: f64 = 30.0;
+
+// Bump this whenever anything that goes over the wire changes shape.
+const PROTOCOL_VERSION: u32 = 2;
+// Biggest thing UDP can carry. Snapshots are nowhere near it, but reads need the room.
+const MAX_PACKET: usize = 65507;
+// Anybody who's been quiet this long is gone.
+const TIMEOUT: Duration = Duration::from_secs(5);
+// How long connect keeps knocking, and how often.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+const HELLO_INTERVAL: Duration = Duration::from_millis(250);
+// Snapshots both ends remember, for deltas to be made against. Two seconds of them.
+const SNAPSHOT_HISTORY: usize = 60;
+// Every input packet repeats this many of the newest commands, so losing one costs nothing.
+const REDUNDANT_COMMANDS: usize = 16;
+// Commands a client keeps around waiting for the server to get to them.
+const MAX_PENDING: usize = 256;
+// Remote players are drawn this many seconds in the past, so there's always a snapshot
+// on either side to blend between.
+const INTERPOLATION_DELAY: f64 = 0.1;
+// Seconds of game a client's commands are allowed to get ahead of the server's clock.
+// Stops a client from walking at double speed by sending twice the commands.
+const MAX_COMMAND_BUDGET: f64 = 0.5;
+// Longest a single command gets to be. A client hitching can send a long one, but not
+// half a second of walking in one go.
+const MAX_COMMAND_DELTA: f64 = 2.0 / TICK_RATE;
+// Nobody scrolls further than this in one tick, and i32::MIN doesn't negate.
+const MAX_WHEEL: i32 = 16;
+const MAX_PLAYERS: usize = 16;
+
+///
+/// Something went wrong with the connection.
+///
+#[derive(Debug)]
+pub enum NetError {
+  Io(io::Error),
+  /// The server said no, and why.
+  Refused(String),
+  TimedOut,
+  NoAddress(String),
+}
+
+impl fmt::Display for NetError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      NetError::Io(e) => write!(f, "network error: {}", e),
+      NetError::Refused(reason) => write!(f, "server refused the connection: {}", reason),
+      NetError::TimedOut => write!(f, "no answer from the server"),
+      NetError::NoAddress(address) => write!(f, "could not find {}", address),
+    }
+  }
+}
+
+impl From<io::Error> for NetError {
+  fn from(e: io::Error) -> Self {
+    NetError::Io(e)
+  }
+}
+
+///
+/// One tick of one player's input, and how long that tick was.
+///
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct Command {
+  /// Goes up by one every command, so the server can tell which ones it's already run.
+  pub sequence: u32,
+  pub delta: f64,
+  pub input: PlayerInput,
+}
+
+impl Command {
+  ///
+  /// NaNs and infinities would end up in the player's position and direction, and
+  /// from there in everybody's snapshots.
+  ///
+  fn is_sane(&self) -> bool {
+    self.delta.is_finite() && self.input.turn.is_finite() && self.input.look.is_finite()
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+enum ClientMessage {
+  Hello {
+    protocol: u32,
+    name: String,
+  },
+  Input {
+    /// The newest snapshot the client has, so the next one can be a delta against it.
+    ack: u32,
+    commands: Vec<Command>,
+  },
+  Bye,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+enum ServerMessage {
+  Welcome {
+    player: u32,
+    level: String,
+    /// Every weapon id in the order snapshots count them in.
+    weapons: Vec<String>,
+  },
+  Refused(String),
+  /// The server moved on to another level, and everybody got a new player in it. Sent
+  /// again every tick until the client acks a snapshot from the new level.
+  ChangeLevel {
+    player: u32,
+    level: String,
+    /// The first tick of the new level. Anything older is from the last one.
+    tick: u32,
+  },
+  Snapshot(SnapshotDelta),
+  /// The server is done with this client, and says why.
+  Bye(String),
+}
+
+///
+/// Everything about a player that goes over the wire. Fixed size, so two of them can be
+/// XORed byte for byte and whatever didn't change comes out as zeros.
+///
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+pub struct PlayerState {
+  pub id: u32,
+  pub alive: bool,
+  pub position: [f64; 2],
+  pub direction: [f64; 2],
+  pub z: f64,
+  pub z_velocity: f64,
+  pub crouching: bool,
+  pub health: i32,
+  pub armor: i32,
+  pub frags: i32,
+  pub deaths: i32,
+  pub respawn_time: f32,
+  /// Index into the weapon list from Welcome.
+  pub weapon: u8,
+  /// One bit per weapon carried, same order.
+  pub weapons: u32,
+  /// In AmmoType::ALL order.
+  pub ammo: [i32; 2],
+  pub weapon_cooldown: f64,
+  pub since_shot: f32,
+}
+
+impl PlayerState {
+  fn capture(player: &Player, weapon_ids: &[String]) -> Self {
+    let weapon_index = |id: &String| weapon_ids.iter().position(|other| other == id);
+    PlayerState {
+      id: player.id,
+      alive: player.alive,
+      position: player.position.to_array(),
+      direction: player.direction.to_array(),
+      z: player.z,
+      z_velocity: player.z_velocity,
+      crouching: player.crouching,
+      health: player.health,
+      armor: player.armor,
+      frags: player.frags,
+      deaths: player.deaths,
+      respawn_time: player.respawn_time as f32,
+      weapon: weapon_index(&player.weapon).unwrap_or(0) as u8,
+      weapons: player
+        .weapons
+        .iter()
+        .filter_map(weapon_index)
+        .fold(0, |bits, index| bits | 1 << index),
+      ammo: AmmoType::ALL.map(|ammo| player.ammo_of(ammo)),
+      weapon_cooldown: player.weapon_cooldown,
+      since_shot: player.since_shot.min(f32::MAX as f64) as f32,
+    }
+  }
+
+  ///
+  /// Pours the state into a player. The pitch stays, the server doesn't care where you look.
+  ///
+  fn apply(&self, player: &mut Player, weapon_ids: &[String]) {
+    player.alive = self.alive;
+    player.position = DVec2::from(self.position);
+    player.face(DVec2::from(self.direction));
+    player.z = self.z;
+    player.z_velocity = self.z_velocity;
+    player.crouching = self.crouching;
+    player.health = self.health;
+    player.armor = self.armor;
+    player.frags = self.frags;
+    player.deaths = self.deaths;
+    player.respawn_time = self.respawn_time as f64;
+    if let Some(id) = weapon_ids.get(self.weapon as usize) {
+      player.weapon.clone_from(id);
+    }
+    player.weapons = (0..weapon_ids.len())
+      .filter(|index| self.weapons & 1 << index != 0)
+      .map(|index| weapon_ids[index].clone())
+      .collect();
+    for (ammo, amount) in AmmoType::ALL.into_iter().zip(self.ammo) {
+      player.ammo.insert(ammo, amount);
+    }
+    player.weapon_cooldown = self.weapon_cooldown;
+    player.since_shot = self.since_shot as f64;
+  }
+}
+
+///
+/// Anything else with a sprite. Clients don't simulate any of it, they just draw it.
+///
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+pub struct ObjectState {
+  /// The entity id on the server, index and generation packed together.
+  pub id: u64,
+  pub texture: u16,
+  pub scale: f32,
+  pub position: [f32; 2],
+}
+
+///
+/// Things that go in a snapshot, looked up by a key that stays the same from one to the next.
+///
+trait Record: Serialize + DeserializeOwned + Copy + PartialEq {
+  fn key(&self) -> u64;
+}
+
+impl Record for PlayerState {
+  fn key(&self) -> u64 {
+    self.id as u64
+  }
+}
+
+impl Record for ObjectState {
+  fn key(&self) -> u64 {
+    self.id
+  }
+}
+
+///
+/// Everybody and everything the clients need to know about, as of one tick.
+///
+#[derive(Clone, Default, Debug)]
+struct Snapshot {
+  tick: u32,
+  players: Vec<PlayerState>,
+  objects: Vec<ObjectState>,
+}
+
+impl Snapshot {
+  fn capture(world: &World, tick: u32, weapon_ids: &[String]) -> Self {
+    let entities = &world.entities;
+    let bodies: Vec<EntityId> = world.players.iter().map(|player| player.entity).collect();
+
+    Snapshot {
+      tick,
+      players: world
+        .players
+        .iter()
+        .map(|player| PlayerState::capture(player, weapon_ids))
+        .collect(),
+      objects: entities
+        .sprites
+        .iter()
+        .filter(|(id, _)| !bodies.contains(id))
+        .filter_map(|(id, sprite)| {
+          Some(ObjectState {
+            id: (id.index as u64) << 32 | id.generation as u64,
+            texture: sprite.texture as u16,
+            scale: sprite.scale as f32,
+            position: entities.position(id)?.as_vec2().to_array(),
+          })
+        })
+        .collect(),
+    }
+  }
+}
+
+///
+/// How one list of records changed since the base snapshot.
+///
+#[derive(Serialize, Deserialize, Default, Debug)]
+struct RecordsDelta {
+  removed: Vec<u64>,
+  /// Whole records, for anything the base didn't have.
+  added: Vec<Vec<u8>>,
+  /// Keys and packed diffs against the base's record.
+  changed: Vec<(u64, Vec<u8>)>,
+}
+
+fn to_bytes<T: Serialize>(value: &T) -> Vec<u8> {
+  bincode::serialize(value).expect("snapshot records always serialize")
+}
+
+fn encode_records<T: Record>(current: &[T], base: &[T]) -> RecordsDelta {
+  let base_by_key: HashMap<u64, &T> = base.iter().map(|record| (record.key(), record)).collect();
+  let mut delta = RecordsDelta::default();
+
+  for record in current {
+    match base_by_key.get(&record.key()) {
+      None => delta.added.push(to_bytes(record)),
+      Some(old) if *old != record => delta
+        .changed
+        .push((record.key(), pack_diff(&to_bytes(record), &to_bytes(*old)))),
+      Some(_) => (),
+    }
+  }
+
+  let current_keys: Vec<u64> = current.iter().map(Record::key).collect();
+  delta.removed = base
+    .iter()
+    .map(Record::key)
+    .filter(|key| !current_keys.contains(key))
+    .collect();
+  delta
+}
+
+///
+/// None if the delta doesn't fit the base, which means somebody's confused about which base.
+///
+fn decode_records<T: Record>(delta: &RecordsDelta, base: &[T]) -> Option<Vec<T>> {
+  let mut records: Vec<T> = base
+    .iter()
+    .filter(|record| !delta.removed.contains(&record.key()))
+    .copied()
+    .collect();
+
+  for (key, diff) in &delta.changed {
+    let record = records.iter_mut().find(|record| record.key() == *key)?;
+    let bytes = unpack_diff(diff, &to_bytes(record))?;
+    *record = bincode::deserialize(&bytes).ok()?;
+  }
+  for bytes in &delta.added {
+    records.push(bincode::deserialize(bytes).ok()?);
+  }
+  Some(records)
+}
+
+///
+/// XORs new against old and squashes the runs of zeros, which is everything that didn't
+/// change. Comes out as (zeros to skip, bytes that follow) count pairs, then those bytes.
+///
+fn pack_diff(new: &[u8], old: &[u8]) -> Vec<u8> {
+  let xor: Vec<u8> = new.iter().zip(old).map(|(a, b)| a ^ b).collect();
+  let mut packed = Vec::new();
+  let mut at = 0;
+
+  while at < xor.len() {
+    let zeros = xor[at..]
+      .iter()
+      .take(255)
+      .take_while(|byte| **byte == 0)
+      .count();
+    at += zeros;
+    if at == xor.len() {
+      break;
+    }
+    let literal = xor[at..]
+      .iter()
+      .take(255)
+      .take_while(|byte| **byte != 0)
+      .count();
+    packed.push(zeros as u8);
+    packed.push(literal as u8);
+    packed.extend_from_slice(&xor[at..at + literal]);
+    at += literal;
+  }
+  packed
+}
+
+fn unpack_diff(packed: &[u8], old: &[u8]) -> Option<Vec<u8>> {
+  let mut new = old.to_vec();
+  let mut at = 0;
+  let mut read = 0;
+
+  while read < packed.len() {
+    let zeros = *packed.get(read)? as usize;
+    let literal = *packed.get(read + 1)? as usize;
+    read += 2;
+    at += zeros;
+
+    let bytes = packed.get(read..read + literal)?;
+    for (byte, change) in new.get_mut(at..at + literal)?.iter_mut().zip(bytes) {
+      *byte ^= change;
+    }
+    at += literal;
+    read += literal;
+  }
+  Some(new)
+}
+
+///
+/// Things that happened once, rather than state. Every snapshot carries all the ones the
+/// client might not have seen yet, so they get there even if a packet doesn't.
+///
+#[derive(Serialize, Deserialize, Clone, Debug)]
+enum EventKind {
+  Message { to: Option<u32>, text: String },
+  Sound { sound: u16, position: [f32; 2] },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct Event {
+  /// Counts up from 1 for the whole game, so clients can skip the ones they've had.
+  number: u32,
+  tick: u32,
+  kind: EventKind,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct SnapshotDelta {
+  tick: u32,
+  /// The tick this is a delta against, 0 for against nothing.
+  base: u32,
+  /// The newest command of this client's that the server has run.
+  last_sequence: u32,
+  players: RecordsDelta,
+  objects: RecordsDelta,
+  events: Vec<Event>,
+}
+
+///
+/// A line in the server's log, stamped with the time of day in UTC.
+///
+pub fn log(text: &str) {
+  let seconds = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map_or(0, |since| since.as_secs())
+    % 86400;
+  println!(
+    "[{:02}:{:02}:{:02}] {}",
+    seconds / 3600,
+    seconds / 60 % 60,
+    seconds % 60,
+    text
+  );
+}
+
+///
+/// A non-blocking socket on every interface.
+///
+fn listen(port: u16) -> io::Result<UdpSocket> {
+  let socket = UdpSocket::bind(("0.0.0.0", port))?;
+  socket.set_nonblocking(true)?;
+  Ok(socket)
+}
+
+///
+/// Reads every packet waiting on a non-blocking socket.
+///
+fn receive_all<T: DeserializeOwned>(socket: &UdpSocket) -> Vec<(SocketAddr, T)> {
+  let mut buffer = vec![0; MAX_PACKET];
+  let mut received = Vec::new();
+
+  loop {
+    match socket.recv_from(&mut buffer) {
+      Ok((length, from)) => {
+        // Garbage from whoever is just dropped, it's UDP.
+        if let Ok(message) = bincode::deserialize(&buffer[..length]) {
+          received.push((from, message));
+        }
+      }
+      Err(e) if e.kind() == io::ErrorKind::WouldBlock => return received,
+      // Windows reports the other end being gone this way, it's not the socket's fault.
+      Err(e) if e.kind() == io::ErrorKind::ConnectionReset => (),
+      Err(e) => {
+        eprintln!("boom: {}", e);
+        return received;
+      }
+    }
+  }
+}
+
+fn send<T: Serialize>(socket: &UdpSocket, to: SocketAddr, message: &T) {
+  let Ok(bytes) = bincode::serialize(message) else {
+    return;
+  };
+  if let Err(e) = socket.send_to(&bytes, to) {
+    if e.kind() != io::ErrorKind::WouldBlock {
+      eprintln!("boom: could not send to {}: {}", to, e);
+    }
+  }
+}
+
+///
+/// Somebody connected to the server.
+///
+struct Client {
+  player: u32,
+  name: String,
+  /// The newest command of theirs that has been run.
+  last_sequence: u32,
+  /// The newest snapshot they've said they have.
+  acked: u32,
+  last_heard: Instant,
+  /// Seconds of commands they're still allowed to send, topped up as the server ticks.
+  budget: f64,
+  /// Hasn't acked anything from the new level yet, so keeps getting told about it.
+  changing_level: bool,
+}
+
+///
+/// The authority on what's going on. Clients send it their input, it runs everything and
+/// tells them how it turned out.
+///
+pub struct NetServer {
+  socket: UdpSocket,
+  pub world: World,
+  clients: HashMap<SocketAddr, Client>,
+  tick: u32,
+  /// The tick the current level started on.
+  level_tick: u32,
+  history: VecDeque<Snapshot>,
+  events: VecDeque<Event>,
+  next_event: u32,
+  weapon_ids: Vec<String>,
+  /// Players nobody's sitting at. They get run on the server like their input just
+  /// arrived, so clients can't tell them apart from anybody else.
+  pub bots: Bots,
+}
+
+impl NetServer {
+  ///
+  /// Listens on every interface. The world should be a deathmatch one with nobody in it.
+  ///
+  pub fn bind(port: u16, world: World) -> Result<Self, NetError> {
+    Ok(NetServer::with_socket(listen(port)?, world))
+  }
+
+  pub(crate) fn with_socket(socket: UdpSocket, world: World) -> Self {
+    let mut weapon_ids: Vec<String> = world.weapons.defs.keys().cloned().collect();
+    weapon_ids.sort();
+
+    NetServer {
+      socket,
+      world,
+      clients: HashMap::new(),
+      tick: 0,
+      level_tick: 0,
+      history: VecDeque::new(),
+      events: VecDeque::new(),
+      next_event: 1,
+      weapon_ids,
+      bots: Bots::default(),
+    }
+  }
+
+  pub fn port(&self) -> u16 {
+    self.socket.local_addr().map_or(0, |address| address.port())
+  }
+
+  ///
+  /// Who's playing, as (name, address).
+  ///
+  pub fn clients(&self) -> Vec<(String, SocketAddr)> {
+    self
+      .clients
+      .iter()
+      .map(|(address, client)| (client.name.clone(), *address))
+      .collect()
+  }
+
+  ///
+  /// Swaps in a world for another level and moves everybody over to it. Scores start
+  /// over, it's a whole new round.
+  ///
+  pub fn change_level(&mut self, world: World) {
+    // Whatever the old level had left to say still gets said.
+    self.collect_events();
+    self.world = world;
+    self.level_tick = self.tick + 1;
+    self.history.clear();
+
+    for client in self.clients.values_mut() {
+      let index = self.world.add_player(&client.name);
+      client.player = self.world.players[index].id;
+      client.acked = 0;
+      client.changing_level = true;
+    }
+    self.bots.rejoin(&mut self.world);
+    log(format!("changed level to {}", self.world.level_id).as_str());
+  }
+
+  ///
+  /// Reads what the clients sent, moves the world along and tells everybody about it.
+  ///
+  pub fn tick(&mut self, delta: f64) {
+    for client in self.clients.values_mut() {
+      client.budget = (client.budget + delta).min(MAX_COMMAND_BUDGET);
+    }
+    for (from, message) in receive_all(&self.socket) {
+      self.handle(from, message);
+    }
+    for (index, input) in self.bots.think(&self.world, delta) {
+      self.world.run_input(index, &input, delta);
+    }
+
+    self.world.update(delta);
+    self.tick += 1;
+    self.drop_quiet_clients();
+    self.collect_events();
+
+    let snapshot = Snapshot::capture(&self.world, self.tick, &self.weapon_ids);
+    for (address, client) in &self.clients {
+      if client.changing_level {
+        let change = ServerMessage::ChangeLevel {
+          player: client.player,
+          level: self.world.level_id.clone(),
+          tick: self.level_tick,
+        };
+        send(&self.socket, *address, &change);
+      }
+
+      let base = self
+        .history
+        .iter()
+        .find(|old| old.tick == client.acked)
+        .cloned()
+        .unwrap_or_default();
+      let delta = SnapshotDelta {
+        tick: snapshot.tick,
+        base: base.tick,
+        last_sequence: client.last_sequence,
+        players: encode_records(&snapshot.players, &base.players),
+        objects: encode_records(&snapshot.objects, &base.objects),
+        events: self
+          .events
+          .iter()
+          .filter(|event| event.tick > client.acked)
+          .cloned()
+          .collect(),
+      };
+      send(&self.socket, *address, &ServerMessage::Snapshot(delta));
+    }
+
+    self.history.push_back(snapshot);
+    if self.history.len() > SNAPSHOT_HISTORY {
+      self.history.pop_front();
+    }
+  }
+
+  ///
+  /// Runs the server at TICK_RATE until running gets switched off.
+  ///
+  pub fn run(&mut self, running: &AtomicBool) {
+    let mut loop_helper = LoopHelper::builder().build_with_target_rate(TICK_RATE);
+    while running.load(Ordering::Relaxed) {
+      let delta = loop_helper.loop_start_s();
+      // A hitch shouldn't turn into everybody teleporting.
+      self.tick(delta.min(0.25));
+      loop_helper.loop_sleep();
+    }
+    self.shut_down("server shut down");
+  }
+
+  ///
+  /// Says goodbye to everybody.
+  ///
+  pub fn shut_down(&mut self, reason: &str) {
+    for address in self.clients.keys() {
+      send(
+        &self.socket,
+        *address,
+        &ServerMessage::Bye(reason.to_string()),
+      );
+    }
+    self.clients.clear();
+  }
+
+  ///
+  /// Throws somebody out, by name. Returns false if nobody's called that.
+  ///
+  pub fn kick(&mut self, name: &str, reason: &str) -> bool {
+    let Some(address) = self
+      .clients
+      .iter()
+      .find(|(_, client)| client.name == name)
+      .map(|(address, _)| *address)
+    else {
+      return false;
+    };
+    send(
+      &self.socket,
+      address,
+      &ServerMessage::Bye(reason.to_string()),
+    );
+    self.disconnect(address, "was kicked");
+    true
+  }
+
+  fn handle(&mut self, from: SocketAddr, message: ClientMessage) {
+    match message {
+      ClientMessage::Hello { protocol, name } => self.greet(from, protocol, &name),
+      ClientMessage::Input { ack, mut commands } => {
+        let Some(client) = self.clients.get_mut(&from) else {
+          return;
+        };
+        client.last_heard = Instant::now();
+        client.acked = client.acked.max(ack);
+        if client.acked >= self.level_tick {
+          client.changing_level = false;
+        }
+        let Some(index) = self.world.player_index(client.player) else {
+          return;
+        };
+
+        commands.sort_by_key(|command| command.sequence);
+        for mut command in commands {
+          if command.sequence <= client.last_sequence {
+            continue;
+          }
+          // Anything over budget or broken is dropped, the client's prediction gets corrected.
+          client.last_sequence = command.sequence;
+          if !command.is_sane() {
+            continue;
+          }
+          command.input.wheel = command.input.wheel.clamp(-MAX_WHEEL, MAX_WHEEL);
+          let delta = command.delta.clamp(0.0, MAX_COMMAND_DELTA);
+          if delta > client.budget {
+            continue;
+          }
+          client.budget -= delta;
+          self.world.run_input(index, &command.input, delta);
+        }
+      }
+      ClientMessage::Bye => self.disconnect(from, "left"),
+    }
+  }
+
+  fn greet(&mut self, from: SocketAddr, protocol: u32, name: &str) {
+    if protocol != PROTOCOL_VERSION {
+      let reason = format!(
+        "protocol {} doesn't match the server's {}",
+        protocol, PROTOCOL_VERSION
+      );
+      send(&self.socket, from, &ServerMessage::Refused(reason));
+      return;
+    }
+
+    // Welcomes get lost too, so saying hello again just gets the same answer.
+    let player = match self.clients.get(&from) {
+      Some(client) => client.player,
+      None => {
+        if self.clients.len() >= MAX_PLAYERS {
+          let reason = "server is full".to_string();
+          send(&self.socket, from, &ServerMessage::Refused(reason));
+          return;
+        }
+
+        let name = self.unique_name(name);
+        let index = self.world.add_player(&name);
+        let player = self.world.players[index].id;
+        log(format!("{} connected from {}", name, from).as_str());
+        self
+          .world
+          .show_message(format!("{} joined.", name).as_str());
+        self.clients.insert(
+          from,
+          Client {
+            player,
+            name,
+            last_sequence: 0,
+            acked: 0,
+            last_heard: Instant::now(),
+            budget: MAX_COMMAND_BUDGET,
+            changing_level: false,
+          },
+        );
+        player
+      }
+    };
+
+    let welcome = ServerMessage::Welcome {
+      player,
+      level: self.world.level_id.clone(),
+      weapons: self.weapon_ids.clone(),
+    };
+    send(&self.socket, from, &welcome);
+  }
+
+  ///
+  /// Two people called player get told apart as player and player 2.
+  ///
+  fn unique_name(&self, name: &str) -> String {
+    let name: String = name.trim().chars().take(16).collect();
+    let name = if name.is_empty() {
+      "player".to_string()
+    } else {
+      name
+    };
+    let taken = |candidate: &String| self.world.players.iter().any(|p| p.name == *candidate);
+
+    (1..)
+      .map(|number| match number {
+        1 => name.clone(),
+        _ => format!("{} {}", name, number),
+      })
+      .find(|candidate| !taken(candidate))
+      .unwrap_or(name)
+  }
+
+  fn disconnect(&mut self, address: SocketAddr, why: &str) {
+    if let Some(client) = self.clients.remove(&address) {
+      self.world.remove_player(client.player);
+      self
+        .world
+        .show_message(format!("{} {}.", client.name, why).as_str());
+    }
+  }
+
+  fn drop_quiet_clients(&mut self) {
+    let quiet: Vec<SocketAddr> = self
+      .clients
+      .iter()
+      .filter(|(_, client)| client.last_heard.elapsed() > TIMEOUT)
+      .map(|(address, _)| *address)
+      .collect();
+    for address in quiet {
+      self.disconnect(address, "timed out");
+    }
+  }
+
+  ///
+  /// Turns this tick's messages and sounds into events, and forgets the ones everybody
+  /// has had plenty of chances to get. Messages for everybody go in the log too, that's
+  /// where joins, leaves and frags show up.
+  ///
+  fn collect_events(&mut self) {
+    let messages = self.world.messages.drain(..).map(|message| {
+      if message.to.is_none() {
+        log(&message.text);
+      }
+      EventKind::Message {
+        to: message.to,
+        text: message.text,
+      }
+    });
+    let sounds = self.world.sounds.drain(..).map(|sound| EventKind::Sound {
+      sound: sound.sound as u16,
+      position: sound.position.as_vec2().to_array(),
+    });
+
+    for kind in messages.chain(sounds).collect::<Vec<_>>() {
+      self.events.push_back(Event {
+        number: self.next_event,
+        tick: self.tick,
+        kind,
+      });
+      self.next_event += 1;
+    }
+
+    let oldest = self.tick.saturating_sub(SNAPSHOT_HISTORY as u32);
+    while self.events.front().is_some_and(|event| event.tick < oldest) {
+      self.events.pop_front();
+    }
+  }
+}
+
+///
+/// A server running on its own thread, for hosting a game and playing in it at the same time.
+/// Dropping it stops the server.
+///
+pub struct ListenServer {
+  running: Arc<AtomicBool>,
+  thread: Option<JoinHandle<()>>,
+  pub port: u16,
+}
+
+impl ListenServer {
+  ///
+  /// Worlds can't be sent between threads, scripts hang on to them, so the server's gets
+  /// built on the server thread. The port gets taken first so a busy one shows up here.
+  ///
+  pub fn start(
+    port: u16,
+    level: Level,
+    settings: Settings,
+    weapons: Weapons,
+    bots: usize,
+  ) -> Result<Self, NetError> {
+    let socket = listen(port)?;
+    let port = socket.local_addr()?.port();
+    let running = Arc::new(AtomicBool::new(true));
+    let still_running = running.clone();
+
+    let thread = thread::Builder::new()
+      .name("server".to_string())
+      .spawn(move || {
+        let world = World::new_deathmatch(&level, &settings, &weapons);
+        let mut server = NetServer::with_socket(socket, world);
+        for _ in 0..bots {
+          server
+            .bots
+            .add(&mut server.world, settings.gameplay.bot_skill);
+        }
+        server.run(&still_running);
+      })?;
+    log(format!("hosting on port {}", port).as_str());
+
+    Ok(ListenServer {
+      running,
+      thread: Some(thread),
+      port,
+    })
+  }
+}
+
+impl Drop for ListenServer {
+  fn drop(&mut self) {
+    self.running.store(false, Ordering::Relaxed);
+    if let Some(thread) = self.thread.take() {
+      let _ = thread.join();
+    }
+  }
+}
+
+///
+/// The client end. The local player runs ahead of the server on their own input and gets
+/// corrected when the server disagrees, everybody else is drawn a little in the past,
+/// blended between the last two snapshots.
+///
+pub struct NetClient {
+  socket: UdpSocket,
+  /// The local player's id on the server.
+  pub player: u32,
+  /// The id of the level the server is running.
+  pub level: String,
+  /// The tick the server's current level started on.
+  level_tick: u32,
+  /// The server moved to another level. The world needs rebuilding for it, then join
+  /// again, which clears this.
+  pub level_changed: bool,
+  weapon_ids: Vec<String>,
+  sequence: u32,
+  /// Commands the server hasn't run yet, oldest first.
+  pending: VecDeque<Command>,
+  /// Snapshots as they arrived, with the local clock at the time. Oldest first.
+  history: VecDeque<(Snapshot, f64)>,
+  clock: f64,
+  last_event: u32,
+  /// Entities standing in for everything the server told us about.
+  remote_players: HashMap<u32, EntityId>,
+  objects: HashMap<u64, EntityId>,
+  last_heard: Instant,
+  /// Set once the connection is gone, with why.
+  pub disconnected: Option<String>,
+}
+
+impl NetClient {
+  ///
+  /// Says hello until the server answers. Blocks for up to a few seconds.
+  ///
+  pub fn connect(address: &str, name: &str) -> Result<Self, NetError> {
+    let with_port = if address.contains(':') {
+      address.to_string()
+    } else {
+      format!("{}:{}", address, DEFAULT_PORT)
+    };
+    let server = with_port
+      .to_socket_addrs()?
+      .next()
+      .ok_or_else(|| NetError::NoAddress(with_port.clone()))?;
+
+    let socket = UdpSocket::bind(if server.is_ipv4() {
+      "0.0.0.0:0"
+    } else {
+      "[::]:0"
+    })?;
+    socket.connect(server)?;
+    socket.set_read_timeout(Some(HELLO_INTERVAL))?;
+
+    let hello = bincode::serialize(&ClientMessage::Hello {
+      protocol: PROTOCOL_VERSION,
+      name: name.to_string(),
+    })
+    .expect("hello always serializes");
+    let started = Instant::now();
+    let mut buffer = vec![0; MAX_PACKET];
+
+    let (player, level, weapon_ids) = loop {
+      if started.elapsed() > CONNECT_TIMEOUT {
+        return Err(NetError::TimedOut);
+      }
+      // Nobody listening shows up as a refused send or read. The server might just not
+      // be up yet, so that's worth another knock too.
+      let length = match socket.send(&hello).and_then(|_| socket.recv(&mut buffer)) {
+        Ok(length) => length,
+        Err(e)
+          if matches!(
+            e.kind(),
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+          ) =>
+        {
+          continue
+        }
+        Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => {
+          thread::sleep(HELLO_INTERVAL);
+          continue;
+        }
+        Err(e) => return Err(e.into()),
+      };
+      match bincode::deserialize(&buffer[..length]) {
+        Ok(ServerMessage::Welcome {
+          player,
+          level,
+          weapons,
+        }) => break (player, level, weapons),
+        Ok(ServerMessage::Refused(reason)) => return Err(NetError::Refused(reason)),
+        _ => (),
+      }
+    };
+    // Snapshots carry which weapons a player has as one bit each, in a u32.
+    if weapon_ids.len() > MAX_WEAPONS {
+      return Err(NetError::Refused(format!(
+        "the server has {} weapons, there can't be more than {}",
+        weapon_ids.len(),
+        MAX_WEAPONS
+      )));
+    }
+
+    socket.set_nonblocking(true)?;
+    log(format!("connected to {} as player {}", server, player).as_str());
+
+    Ok(NetClient {
+      socket,
+      player,
+      level,
+      level_tick: 0,
+      level_changed: false,
+      weapon_ids,
+      sequence: 0,
+      pending: VecDeque::new(),
+      history: VecDeque::new(),
+      clock: 0.0,
+      last_event: 0,
+      remote_players: HashMap::new(),
+      objects: HashMap::new(),
+      last_heard: Instant::now(),
+      disconnected: None,
+    })
+  }
+
+  ///
+  /// Puts the local player into a fresh deathmatch world of the server's level. Items
+  /// get cleared out, the server sends them along with everything else.
+  ///
+  /// Also what happens after a level change, so anything about the old world gets
+  /// forgotten.
+  ///
+  pub fn join(&mut self, world: &mut World, name: &str) {
+    self.level_changed = false;
+    self.pending.clear();
+    self.history.clear();
+    self.remote_players.clear();
+    self.objects.clear();
+
+    let index = world.add_player(name);
+    world.players[index].id = self.player;
+
+    let entities = &mut world.entities;
+    let items: Vec<EntityId> = entities.pickups.iter().map(|(id, _)| id).collect();
+    for id in items {
+      entities.despawn(id);
+    }
+  }
+
+  ///
+  /// Runs the local player's input, sends it off and catches up on whatever the server said.
+  ///
+  pub fn update(&mut self, world: &mut World, input: &PlayerInput, delta: f64) {
+    if self.disconnected.is_some() {
+      return;
+    }
+    self.clock += delta;
+
+    self.sequence += 1;
+    let command = Command {
+      sequence: self.sequence,
+      delta,
+      input: *input,
+    };
+    world.predict(0, input, delta);
+    world.player_mut().since_shot += delta;
+    self.pending.push_back(command);
+    if self.pending.len() > MAX_PENDING {
+      self.pending.pop_front();
+    }
+
+    let skip = self.pending.len().saturating_sub(REDUNDANT_COMMANDS);
+    let message = ClientMessage::Input {
+      ack: self.newest_tick(),
+      commands: self.pending.iter().skip(skip).copied().collect(),
+    };
+    if let Ok(bytes) = bincode::serialize(&message) {
+      if let Err(e) = self.socket.send(&bytes) {
+        if e.kind() != io::ErrorKind::WouldBlock {
+          eprintln!("boom: {}", e);
+        }
+      }
+    }
+
+    let server = self.socket.peer_addr().ok();
+    for (from, message) in receive_all::<ServerMessage>(&self.socket) {
+      if Some(from) != server {
+        continue;
+      }
+      self.last_heard = Instant::now();
+      match message {
+        ServerMessage::Snapshot(delta) => self.receive_snapshot(world, delta),
+        ServerMessage::Bye(reason) => self.disconnected = Some(reason),
+        ServerMessage::ChangeLevel {
+          player,
+          level,
+          tick,
+        } if tick > self.level_tick => {
+          self.player = player;
+          self.level = level;
+          self.level_tick = tick;
+          self.level_changed = true;
+          self.history.clear();
+        }
+        ServerMessage::ChangeLevel { .. } => (),
+        ServerMessage::Welcome { .. } | ServerMessage::Refused(_) => (),
+      }
+    }
+
+    self.interpolate(world);
+    world.age_messages(delta);
+
+    if self.last_heard.elapsed() > TIMEOUT {
+      self.disconnected = Some("lost connection to the server".to_string());
+    }
+  }
+
+  fn newest_tick(&self) -> u32 {
+    self.history.back().map_or(0, |(snapshot, _)| snapshot.tick)
+  }
+
+  fn receive_snapshot(&mut self, world: &mut World, delta: SnapshotDelta) {
+    // Late ones are no use, anything in them is already in a newer one. Ones from the
+    // last level, or for a level the world hasn't been rebuilt for yet, are no use either.
+    if delta.tick <= self.newest_tick() || delta.tick < self.level_tick || self.level_changed {
+      return;
+    }
+    let base = match delta.base {
+      0 => Snapshot::default(),
+      tick => match self.history.iter().find(|(old, _)| old.tick == tick) {
+        Some((old, _)) => old.clone(),
+        None => return,
+      },
+    };
+    let (Some(players), Some(objects)) = (
+      decode_records(&delta.players, &base.players),
+      decode_records(&delta.objects, &base.objects),
+    ) else {
+      eprintln!("boom: snapshot {} doesn't fit its base", delta.tick);
+      return;
+    };
+    let snapshot = Snapshot {
+      tick: delta.tick,
+      players,
+      objects,
+    };
+
+    for event in delta.events {
+      if event.number <= self.last_event {
+        continue;
+      }
+      self.last_event = event.number;
+      match event.kind {
+        EventKind::Message { to: None, text } => world.show_message(&text),
+        EventKind::Message { to: Some(to), text } => world.tell(to, &text),
+        // A newer server can have sounds this client doesn't, and the mixer indexes by them.
+        EventKind::Sound { sound, .. } if sound as usize >= SOUND_NAMES.len() => {}
+        EventKind::Sound { sound, position } => world.sounds.push(SoundEvent {
+          sound: sound as usize,
+          position: DVec2::new(position[0] as f64, position[1] as f64),
+        }),
+      }
+    }
+
+    self.reconcile(world, &snapshot, delta.last_sequence);
+    self.history.push_back((snapshot, self.clock));
+    if self.history.len() > SNAPSHOT_HISTORY {
+      self.history.pop_front();
+    }
+  }
+
+  ///
+  /// Takes the server's word for where the local player was, then plays the commands it
+  /// hasn't got to yet on top. If prediction was right nothing moves.
+  ///
+  fn reconcile(&mut self, world: &mut World, snapshot: &Snapshot, last_sequence: u32) {
+    let Some(state) = snapshot
+      .players
+      .iter()
+      .find(|state| state.id == self.player)
+    else {
+      return;
+    };
+    self
+      .pending
+      .retain(|command| command.sequence > last_sequence);
+
+    let player = world.player_mut();
+    // Only the local player cares about these, and replaying would make them jump.
+    let (pitch, bob_phase, bob_amount) = (player.pitch, player.bob_phase, player.bob_amount);
+    state.apply(player, &self.weapon_ids);
+
+    for command in &self.pending {
+      world.predict(0, &command.input, command.delta);
+    }
+
+    let replayed: f64 = self.pending.iter().map(|command| command.delta).sum();
+    let player = world.player_mut();
+    player.pitch = pitch;
+    player.bob_phase = bob_phase;
+    player.bob_amount = bob_amount;
+    player.since_shot += replayed;
+  }
+
+  ///
+  /// Moves the stand-ins for remote players and objects to where they were
+  /// INTERPOLATION_DELAY ago, blending between the snapshots on either side of then.
+  ///
+  fn interpolate(&mut self, world: &mut World) {
+    let Some((newest, received)) = self.history.back() else {
+      return;
+    };
+    let now = newest.tick as f64 + (self.clock - received) * TICK_RATE;
+    let then = now - INTERPOLATION_DELAY * TICK_RATE;
+
+    let (before, after, blend) = match self
+      .history
+      .iter()
+      .zip(self.history.iter().skip(1))
+      .find(|((a, _), (b, _))| (a.tick as f64) <= then && then < b.tick as f64)
+    {
+      Some(((a, _), (b, _))) => (a, b, (then - a.tick as f64) / (b.tick - a.tick) as f64),
+      None => (newest, newest, 0.0),
+    };
+    let lerp = |from: DVec2, to: DVec2| from + (to - from) * blend;
+
+    let entities = &mut world.entities;
+    let mut seen = Vec::new();
+    for state in &after.players {
+      if state.id == self.player {
+        continue;
+      }
+      seen.push(state.id);
+      let to = DVec2::from(state.position);
+      let from = before
+        .players
+        .iter()
+        .find(|old| old.id == state.id)
+        .map_or(to, |old| DVec2::from(old.position));
+      let position = lerp(from, to);
+
+      let id = *self
+        .remote_players
+        .entry(state.id)
+        .or_insert_with(|| entities.spawn_player(position));
+      entities.move_to(id, position);
+      if let Some(transform) = entities.transforms.get_mut(id) {
+        transform.yaw = state.direction[1].atan2(state.direction[0]);
+      }
+      if let Some(sprite) = entities.sprites.get_mut(id) {
+        sprite.texture = texture_id(if state.alive { "dm" } else { "dm_dead" });
+      }
+    }
+    self.remote_players.retain(|player, id| {
+      seen.contains(player) || {
+        entities.despawn(*id);
+        false
+      }
+    });
+
+    let mut seen = Vec::new();
+    for state in &after.objects {
+      // A texture we don't have would be a crash in the renderer.
+      if state.texture as usize >= TEXTURE_NAMES.len() {
+        continue;
+      }
+      seen.push(state.id);
+      let as_dvec2 = |position: [f32; 2]| DVec2::new(position[0] as f64, position[1] as f64);
+      let to = as_dvec2(state.position);
+      let from = before
+        .objects
+        .iter()
+        .find(|old| old.id == state.id)
+        .map_or(to, |old| as_dvec2(old.position));
+      let position = lerp(from, to);
+
+      let id = *self.objects.entry(state.id).or_insert_with(|| {
+        let id = entities.spawn();
+        entities.place(id, position);
+        id
+      });
+      entities.move_to(id, position);
+      entities.sprites.insert(
+        id,
+        Sprite {
+          texture: state.texture as usize,
+          scale: state.scale as f64,
+        },
+      );
+    }
+    self.objects.retain(|object, id| {
+      seen.contains(object) || {
+        entities.despawn(*id);
+        false
+      }
+    });
+  }
+}
+
+impl Drop for NetClient {
+  fn drop(&mut self) {
+    if self.disconnected.is_none() {
+      if let Ok(bytes) = bincode::serialize(&ClientMessage::Bye) {
+        let _ = self.socket.send(&bytes);
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::boom::level::Rotation;
+
+  fn player(id: u32, health: i32) -> PlayerState {
+    PlayerState {
+      id,
+      health,
+      position: [id as f64 + 0.5, 2.5],
+      ..PlayerState::default()
+    }
+  }
+
+  #[test]
+  fn diffs_round_trip() {
+    let old: Vec<u8> = (0..1000).map(|at| (at * 7 % 251) as u8).collect();
+    let mut new = old.clone();
+    // A lone byte, a long changed run and a change after a long unchanged one.
+    new[3] ^= 1;
+    for byte in &mut new[100..400] {
+      *byte = byte.wrapping_add(1);
+    }
+    new[999] = 0;
+
+    let packed = pack_diff(&new, &old);
+    assert!(packed.len() < new.len());
+    assert_eq!(unpack_diff(&packed, &old), Some(new.clone()));
+    assert!(pack_diff(&old[..255], &old[..255]).is_empty());
+    assert_eq!(unpack_diff(&pack_diff(&old, &old), &old), Some(old.clone()));
+    assert_eq!(unpack_diff(&[], &old), Some(old.clone()));
+
+    assert_eq!(unpack_diff(&packed[..packed.len() - 1], &old), None);
+    // Runs that go off the end of the old bytes.
+    assert_eq!(unpack_diff(&[255, 2, 1, 1], &old[..10]), None);
+  }
+
+  #[test]
+  fn records_round_trip() {
+    let base = vec![player(1, 100), player(2, 100), player(3, 100)];
+    let current = vec![player(1, 100), player(2, 40), player(4, 100)];
+
+    let delta = encode_records(&current, &base);
+    assert_eq!(delta.removed, vec![3]);
+    assert_eq!(delta.added.len(), 1);
+    assert_eq!(delta.changed.len(), 1);
+    assert_eq!(delta.changed[0].0, 2);
+    assert_eq!(decode_records(&delta, &base), Some(current.clone()));
+
+    let nothing = encode_records(&current, &current);
+    assert!(nothing.removed.is_empty() && nothing.added.is_empty() && nothing.changed.is_empty());
+    assert_eq!(decode_records(&nothing, &current), Some(current.clone()));
+
+    // Against nothing, everything's added.
+    let full = encode_records(&current, &[]);
+    assert_eq!(full.added.len(), 3);
+    assert_eq!(decode_records(&full, &[]), Some(current));
+  }
+
+  #[test]
+  fn broken_records_are_refused() {
+    let base = vec![player(1, 100), player(2, 100)];
+    let current = vec![player(1, 100), player(2, 40)];
+    let delta = encode_records(&current, &base);
+
+    // The wrong base doesn't have the record the change is for.
+    assert_eq!(decode_records(&delta, &base[..1]), None);
+
+    let mut truncated = encode_records(&current, &base);
+    truncated.changed[0].1.pop();
+    assert_eq!(decode_records(&truncated, &base), None);
+
+    let mut added = encode_records(&current, &base[..1]);
+    added.added[0].truncate(4);
+    assert_eq!(decode_records(&added, &base[..1]), None);
+  }
+
+  #[test]
+  fn commands_move_players_over_localhost() {
+    let rotation = Rotation::load(&Rotation::default_path()).unwrap();
+    let level = Level::load(&rotation.levels[0]).unwrap();
+    let settings = Settings::default();
+    let weapons = Weapons::load(&Weapons::default_path()).unwrap();
+
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket.set_nonblocking(true).unwrap();
+    let mut server =
+      NetServer::with_socket(socket, World::new_deathmatch(&level, &settings, &weapons));
+    let address = format!("127.0.0.1:{}", server.port());
+
+    // Connecting blocks until the server answers, and the server only answers when it ticks.
+    let connecting = thread::spawn(move || NetClient::connect(&address, "tester"));
+    let started = Instant::now();
+    while !connecting.is_finished() && started.elapsed() < CONNECT_TIMEOUT * 2 {
+      server.tick(1.0 / TICK_RATE);
+      thread::sleep(Duration::from_millis(5));
+    }
+    let mut client = connecting.join().unwrap().unwrap();
+    let mut world = World::new_deathmatch(&level, &settings, &weapons);
+    client.join(&mut world, "tester");
+
+    // Point the player somewhere they can walk, on both ends.
+    let index = server.world.player_index(client.player).unwrap();
+    let start = server.world.players[index].position;
+    let open = [DVec2::X, DVec2::NEG_X, DVec2::Y, DVec2::NEG_Y]
+      .into_iter()
+      .find(|direction| !server.world.map.is_wall(start + *direction))
+      .expect("somewhere to walk");
+    server.world.players[index].face(open);
+    world.players[0].position = start;
+    world.players[0].face(open);
+
+    let forward = PlayerInput {
+      forward: true,
+      ..PlayerInput::default()
+    };
+    for _ in 0..20 {
+      client.update(&mut world, &forward, 1.0 / TICK_RATE);
+      thread::sleep(Duration::from_millis(5));
+      server.tick(1.0 / TICK_RATE);
+      thread::sleep(Duration::from_millis(5));
+    }
+    client.update(&mut world, &PlayerInput::default(), 1.0 / TICK_RATE);
+
+    let moved = server.world.players[index].position;
+    assert!(moved.distance(start) > 0.1, "{} to {}", start, moved);
+    assert!(client.newest_tick() > 0, "no snapshot arrived");
+    assert!(client.disconnected.is_none());
+  }
+}
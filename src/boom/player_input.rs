@@ -0,0 +1,28 @@
+use serde::{Deserialize, Serialize};
+
+///
+/// Everything a player can tell their body to do for one tick.
+///
+/// The world only ever sees these, never the window, so it doesn't care whether they came
+/// from the keyboard, the other end of a network connection or something else entirely.
+///
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+pub struct PlayerInput {
+  pub forward: bool,
+  pub backward: bool,
+  pub left: bool,
+  pub right: bool,
+  pub jump: bool,
+  pub crouch: bool,
+  pub fire: bool,
+  /// Use got pressed this tick.
+  pub use_wall: bool,
+  /// Radians to turn, positive is to the right.
+  pub turn: f64,
+  /// How far to look up or down, positive is down. Same units as the mouse.
+  pub look: f64,
+  /// Weapon slot picked this tick, 1 to 9. 0 is none.
+  pub slot: u8,
+  /// Mouse wheel clicks this tick, up is positive.
+  pub wheel: i32,
+}
@@ -0,0 +1,279 @@
+use std::{
+  collections::VecDeque,
+  fs::File,
+  io::{self, BufWriter, Write},
+  path::Path,
+  time::Duration,
+};
+
+use glam::IVec2;
+
+use super::{
+  font::{draw_text, text_size, TextStyle, LINE_HEIGHT},
+  framebuffer::FrameBuffer,
+};
+
+///
+/// The parts of a frame that get timed.
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Phase {
+  Poll,
+  Tick,
+  Raycast,
+  Sprites,
+  Upload,
+  Present,
+}
+
+impl Phase {
+  pub const ALL: [Phase; 6] = [
+    Phase::Poll,
+    Phase::Tick,
+    Phase::Raycast,
+    Phase::Sprites,
+    Phase::Upload,
+    Phase::Present,
+  ];
+
+  pub fn name(&self) -> &'static str {
+    match self {
+      Phase::Poll => "poll",
+      Phase::Tick => "tick",
+      Phase::Raycast => "raycast",
+      Phase::Sprites => "sprites",
+      Phase::Upload => "upload",
+      Phase::Present => "present",
+    }
+  }
+
+  ///
+  /// What color this phase gets in the overlay graph.
+  ///
+  pub fn color(&self) -> [u8; 3] {
+    match self {
+      Phase::Poll => [80, 160, 255],
+      Phase::Tick => [80, 255, 120],
+      Phase::Raycast => [255, 90, 60],
+      Phase::Sprites => [255, 200, 40],
+      Phase::Upload => [200, 80, 255],
+      Phase::Present => [200, 200, 200],
+    }
+  }
+
+  fn index(&self) -> usize {
+    *self as usize
+  }
+}
+
+///
+/// Min, average and 99th percentile of one phase over the rolling window.
+///
+#[derive(Default, Clone, Copy, Debug)]
+pub struct PhaseStats {
+  pub min: Duration,
+  pub avg: Duration,
+  pub p99: Duration,
+}
+
+type FrameTimes = [Duration; Phase::ALL.len()];
+
+// A frame this long fills the whole graph.
+const GRAPH_MAX_MS: f64 = 33.3;
+const GRAPH_HEIGHT: i32 = 80;
+const GRAPH_MARGIN: i32 = 4;
+
+///
+/// Keeps track of how long each phase of the last few hundred frames took.
+///
+/// Cheap enough to leave running all the time.
+///
+pub struct Profiler {
+  history: VecDeque<FrameTimes>,
+  capacity: usize,
+  current: FrameTimes,
+  // Every frame ever, only kept when it's going to be dumped to a csv.
+  recording: Option<Vec<FrameTimes>>,
+  pub show_overlay: bool,
+}
+
+impl Profiler {
+  pub fn new(capacity: usize, record_everything: bool) -> Self {
+    Profiler {
+      history: VecDeque::with_capacity(capacity),
+      capacity,
+      current: FrameTimes::default(),
+      recording: record_everything.then(Vec::new),
+      show_overlay: false,
+    }
+  }
+
+  ///
+  /// Adds time to a phase of the current frame.
+  ///
+  pub fn record(&mut self, phase: Phase, duration: Duration) {
+    self.current[phase.index()] += duration;
+  }
+
+  ///
+  /// Closes off the current frame and pushes it into the rolling window.
+  ///
+  pub fn end_frame(&mut self) {
+    if self.history.len() == self.capacity {
+      self.history.pop_front();
+    }
+    self.history.push_back(self.current);
+
+    if let Some(recording) = self.recording.as_mut() {
+      recording.push(self.current);
+    }
+
+    self.current = FrameTimes::default();
+  }
+
+  ///
+  /// Stats for one phase over the rolling window.
+  ///
+  pub fn stats(&self, phase: Phase) -> PhaseStats {
+    if self.history.is_empty() {
+      return PhaseStats::default();
+    }
+
+    let mut times: Vec<Duration> = self
+      .history
+      .iter()
+      .map(|frame| frame[phase.index()])
+      .collect();
+    times.sort();
+
+    let p99_index = ((times.len() as f64 * 0.99).ceil() as usize).saturating_sub(1);
+
+    PhaseStats {
+      min: times[0],
+      avg: times.iter().sum::<Duration>() / times.len() as u32,
+      p99: times[p99_index],
+    }
+  }
+
+  ///
+  /// Draws a stacked bar graph of the rolling window into the bottom left of the framebuffer,
+  /// with min/avg/p99 of each phase above it.
+  ///
+  /// One column per frame, newest on the right. The line is 60 FPS.
+  ///
+  pub fn draw_overlay(&self, frame: &mut FrameBuffer) {
+    if !self.show_overlay {
+      return;
+    }
+
+    let size = frame.size;
+    let width = (self.capacity as i32).min(size.x - GRAPH_MARGIN * 2);
+    let height = GRAPH_HEIGHT.min(size.y - GRAPH_MARGIN * 2);
+    if width <= 0 || height <= 0 {
+      return;
+    }
+
+    let left = GRAPH_MARGIN;
+    let bottom = size.y - GRAPH_MARGIN - 1;
+    let pixels_per_ms = height as f64 / GRAPH_MAX_MS;
+
+    frame.darken_rect(
+      IVec2::new(left, bottom - height + 1),
+      IVec2::new(width, height),
+    );
+
+    let frames = self
+      .history
+      .iter()
+      .skip(self.history.len().saturating_sub(width as usize));
+
+    for (column, frame_times) in frames.enumerate() {
+      let x = left + column as i32;
+      let mut y = bottom;
+
+      for phase in Phase::ALL {
+        let bar =
+          (frame_times[phase.index()].as_secs_f64() * 1000.0 * pixels_per_ms).round() as i32;
+        for _ in 0..bar {
+          if y <= bottom - height {
+            break;
+          }
+          frame.put_pixel(x, y, phase.color());
+          y -= 1;
+        }
+      }
+    }
+
+    let target_line = bottom - (1000.0 / 60.0 * pixels_per_ms) as i32;
+    for x in left..left + width {
+      frame.put_pixel(x, target_line, [255, 255, 255]);
+    }
+
+    // The numbers, one line per phase, stacked up on top of the graph.
+    let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+    let lines: Vec<String> = Phase::ALL
+      .iter()
+      .map(|phase| {
+        let stats = self.stats(*phase);
+        format!(
+          "{:<8}{:>6.2}{:>6.2}{:>6.2}",
+          phase.name(),
+          ms(stats.min),
+          ms(stats.avg),
+          ms(stats.p99)
+        )
+      })
+      .collect();
+    let header = format!("{:<8}{:>6}{:>6}{:>6}", "ms", "min", "avg", "p99");
+
+    let text_height = (lines.len() as i32 + 1) * LINE_HEIGHT;
+    let text_top = bottom - height - text_height - GRAPH_MARGIN;
+    let text_width = text_size(&header, 1).x;
+    frame.darken_rect(
+      IVec2::new(left, text_top - 2),
+      IVec2::new(text_width + 4, text_height + 2),
+    );
+
+    draw_text(
+      frame,
+      &header,
+      IVec2::new(left + 2, text_top),
+      TextStyle::default(),
+    );
+    for (line_number, (phase, line)) in Phase::ALL.iter().zip(lines).enumerate() {
+      draw_text(
+        frame,
+        &line,
+        IVec2::new(left + 2, text_top + (line_number as i32 + 1) * LINE_HEIGHT),
+        TextStyle {
+          color: phase.color(),
+          ..Default::default()
+        },
+      );
+    }
+  }
+
+  ///
+  /// Dumps every recorded frame to a csv, one row per frame and a column per phase in milliseconds.
+  ///
+  pub fn write_csv(&self, path: &Path) -> io::Result<()> {
+    let Some(recording) = &self.recording else {
+      return Ok(());
+    };
+
+    let mut file = BufWriter::new(File::create(path)?);
+
+    let header: Vec<&str> = Phase::ALL.iter().map(|phase| phase.name()).collect();
+    writeln!(file, "frame,{}", header.join(","))?;
+
+    for (frame_number, frame) in recording.iter().enumerate() {
+      let columns: Vec<String> = frame
+        .iter()
+        .map(|time| format!("{:.4}", time.as_secs_f64() * 1000.0))
+        .collect();
+      writeln!(file, "{},{}", frame_number, columns.join(","))?;
+    }
+
+    file.flush()
+  }
+}
@@ -1,13 +1,80 @@
-use std::mem::swap;
+use std::{
+  mem::{swap, take},
+  time::Instant,
+};
+
+use glam::{DVec2, IVec2};
+
+use sdl2::{pixels::PixelFormatEnum, render::Texture};
+
+use super::{
+  automap::Automap,
+  framebuffer::FrameBuffer,
+  hud::draw_hud,
+  menu::Menu,
+  profiler::{Phase, Profiler},
+  settings::{RenderResolution, Settings, UpscaleFilter},
+  texture::{load_textures, TextureData},
+  win_handler::WinHandler,
+  world::World,
+};
 
-use glam::IVec2;
-
-use sdl2::pixels::PixelFormatEnum;
+///
+/// The flat color of a wall type. Shared with the automap so it matches.
+///
+pub fn wall_color(cell: i32) -> [u8; 3] {
+  match cell {
+    1 => [255, 0, 0],     //red
+    2 => [0, 255, 0],     //green
+    3 => [0, 0, 255],     //blue
+    4 => [255, 255, 255], //white
+    _ => [255, 255, 0],   //yellow
+  }
+}
 
-use super::{win_handler::WinHandler, world::World};
+///
+/// Cuts the screen up between the players sitting at it. Position then size, one each.
+///
+/// Two players get a wide half each, one over the other. Three or four get a quarter.
+///
+pub fn split_screen(players: usize, size: IVec2) -> Vec<(IVec2, IVec2)> {
+  let half = size / 2;
+  match players {
+    0 | 1 => vec![(IVec2::ZERO, size)],
+    2 => vec![
+      (IVec2::ZERO, IVec2::new(size.x, half.y)),
+      (IVec2::new(0, half.y), IVec2::new(size.x, size.y - half.y)),
+    ],
+    _ => [
+      IVec2::ZERO,
+      IVec2::new(half.x, 0),
+      IVec2::new(0, half.y),
+      half,
+    ]
+    .into_iter()
+    .take(players.min(4))
+    .map(|position| {
+      let far = IVec2::new(
+        if position.x == 0 { half.x } else { size.x },
+        if position.y == 0 { half.y } else { size.y },
+      );
+      (position, far - position)
+    })
+    .collect(),
+  }
+}
 
 pub struct Renderer {
   texture_size: i32,
+  textures: Vec<TextureData>,
+  z_buffer: Vec<f64>,
+  pub render_resolution: RenderResolution,
+  pub upscale_filter: UpscaleFilter,
+  frame: Option<Texture<'static>>,
+  frame_size: IVec2,
+  frame_filter: UpscaleFilter,
+  framebuffer: Vec<u8>,
+  pub automap: Automap,
 }
 
 ///
@@ -23,16 +90,35 @@ pub struct Renderer {
 /// It also stores texture data. How fancy.
 ///
 impl Renderer {
-  pub fn new() -> Self {
-    Renderer { texture_size: 64 }
+  pub fn new(settings: &Settings) -> Self {
+    Renderer {
+      texture_size: 64,
+      textures: load_textures(),
+      z_buffer: vec![],
+      render_resolution: settings.video.render_resolution,
+      upscale_filter: settings.video.upscale_filter,
+      frame: None,
+      frame_size: IVec2::ZERO,
+      frame_filter: settings.video.upscale_filter,
+      framebuffer: vec![],
+      automap: Automap::new(),
+    }
   }
 
   ///
-  /// The actual raycast into the world. Draws to the framebuffer.
+  /// The actual raycast into the world, through the eyes of players[view]. Draws to the
+  /// framebuffer.
   ///
   /// This creates an oddly powerful feeling with the implementation.
   ///
-  fn raycast(&mut self, world: &World, window_size: &IVec2, buffer: &mut [u8], pitch: usize) {
+  fn raycast(
+    &mut self,
+    world: &mut World,
+    view: usize,
+    window_size: &IVec2,
+    buffer: &mut [u8],
+    pitch: usize,
+  ) {
     let mut draw_pixel = |x: usize, y: usize, r: u8, b: u8, g: u8, a: u8| {
       let index = y * pitch + x * 4;
 
@@ -84,16 +170,23 @@ impl Renderer {
     // These are here to help me keep my sanity translating this tutorial.
     let w = window_size.x;
     let h = window_size.y;
-    let dir = world.player.direction;
+    let player = &world.players[view];
+    let dir = player.direction;
     let dirX = dir.x;
     let dirY = dir.y;
-    let plane = world.plane;
+    let plane = player.plane;
     let planeX = plane.x;
     let planeY = plane.y;
-    let pos = world.player.position;
+    let pos = player.position;
     let posX = pos.x;
     let posY = pos.y;
-    let worldMap = world.map.data;
+    let worldMap = &world.map.data;
+    // How many pixels tall a wall one unit away is. Derived from the FOV so the aspect ratio stays correct.
+    let projection_scale = world.projection_scale(window_size);
+    let eye_height = player.eye_height();
+    let horizon = player.horizon(window_size);
+
+    self.z_buffer.resize(w as usize, 0.0);
 
     // println!("plane: {:?}", plane);
 
@@ -167,8 +260,10 @@ impl Renderer {
           mapY += stepY;
           side = 1;
         }
-        //Check if ray has hit a wall
-        if (worldMap[mapX as usize][mapY as usize] > 0) {
+        //the automap only shows cells you've actually seen
+        world.map.revealed[mapX as usize][mapY as usize] = true;
+        //Check if ray has hit a wall, anything but floor is one, same as Map::cast
+        if (worldMap[mapX as usize][mapY as usize] != 0) {
           hit = 1
         };
       }
@@ -185,38 +280,37 @@ impl Renderer {
       };
 
       //Calculate height of line to draw on screen
-      let lineHeight: i32 = ((h as f64) / perpWallDist) as i32;
+      let lineHeight: i32 = (projection_scale / perpWallDist) as i32;
+
+      self.z_buffer[x as usize] = perpWallDist;
 
       //calculate lowest and highest pixel to fill in current stripe
-      let mut drawStart: i32 = -lineHeight / 2 + h / 2;
+      //the eye height decides how much of the wall is above the horizon
+      let mut drawStart: i32 = horizon - (lineHeight as f64 * (1.0 - eye_height)) as i32;
+      let mut drawEnd: i32 = horizon + (lineHeight as f64 * eye_height) as i32;
+
+      //ceiling above the stripe, floor below it
+      if drawStart > 0 {
+        draw_line(x, 0, x, (drawStart - 1).min(h - 1), 40, 40, 48, 255);
+      }
+      if drawEnd < h - 1 {
+        draw_line(x, (drawEnd + 1).max(0), x, h - 1, 80, 72, 64, 255);
+      }
+
       if (drawStart < 0) {
         drawStart = 0
       };
-      let mut drawEnd: i32 = lineHeight / 2 + h / 2;
       if (drawEnd >= h) {
         drawEnd = h - 1
       };
+      //looked far enough up or down that the wall is off the screen
+      if drawStart > drawEnd {
+        continue;
+      }
 
       //choose wall color
-      let mut r: u8 = 0;
-      let mut g: u8 = 0;
-      let mut b: u8 = 0;
-      let mut a: u8 = 0;
-      match (worldMap[mapX as usize][mapY as usize]) {
-        1 => r = 255, //red
-        2 => g = 255, //green
-        3 => b = 255, //blue
-        4 => {
-          r = 255;
-          g = 255;
-          b = 255;
-        } //white
-        // default: color = RGB_Yellow; break; //yellow
-        _ => {
-          r = 255;
-          g = 255;
-        }
-      }
+      let [mut r, mut g, mut b] = wall_color(worldMap[mapX as usize][mapY as usize]);
+      let a: u8 = 255;
 
       //give x and y sides different brightness
       if (side == 1) {
@@ -230,33 +324,197 @@ impl Renderer {
     }
   }
 
+  ///
+  /// Draws every entity with a sprite as a flat sprite that always faces you.
+  ///
+  /// Uses the z buffer from raycast so they hide behind walls.
+  ///
+  fn draw_sprites(
+    &self,
+    world: &World,
+    view: usize,
+    window_size: &IVec2,
+    buffer: &mut [u8],
+    pitch: usize,
+  ) {
+    let w = window_size.x;
+    let h = window_size.y;
+    let player = &world.players[view];
+    let pos = player.position;
+    let dir = player.direction;
+    let plane = player.plane;
+    let projection_scale = world.projection_scale(window_size);
+    let eye_height = player.eye_height();
+    let horizon = player.horizon(window_size);
+
+    // Furthest first so the close ones get drawn over them. Your own body would just be
+    // in the way.
+    let entities = &world.entities;
+    let mut sprites: Vec<(DVec2, usize, f64)> = entities
+      .sprites
+      .iter()
+      .filter(|(id, _)| *id != player.entity)
+      .filter_map(|(id, sprite)| Some((entities.position(id)?, sprite.texture, sprite.scale)))
+      .collect();
+    sprites.sort_by(|a, b| {
+      pos
+        .distance_squared(b.0)
+        .total_cmp(&pos.distance_squared(a.0))
+    });
+
+    let inv_det = 1.0 / (plane.x * dir.y - dir.x * plane.y);
+
+    for (sprite_position, sprite, scale) in sprites {
+      // Move the sprite into camera space. transform_y is the depth.
+      let relative = sprite_position - pos;
+      let transform_x = inv_det * (dir.y * relative.x - dir.x * relative.y);
+      let transform_y = inv_det * (-plane.y * relative.x + plane.x * relative.y);
+
+      if transform_y <= 0.1 {
+        continue;
+      }
+
+      let screen_x = ((w as f64 / 2.0) * (1.0 + transform_x / transform_y)) as i32;
+      let wall_size = projection_scale / transform_y;
+      let size = (wall_size * scale) as i32;
+
+      if size <= 0 {
+        continue;
+      }
+
+      // Sprites stand on the floor, so they move with the eye height like walls do.
+      let bottom = horizon + (wall_size * eye_height) as i32;
+      let top = bottom - size;
+      let left = screen_x - size / 2;
+
+      let texture = &self.textures[sprite];
+
+      for stripe in left.max(0)..(left + size).min(w) {
+        if transform_y >= self.z_buffer[stripe as usize] {
+          continue;
+        }
+
+        let tex_x = ((stripe - left) as usize * texture.width) / size as usize;
+
+        for y in top.max(0)..bottom.min(h) {
+          let tex_y = ((y - top) as usize * texture.height) / size as usize;
+          let [r, g, b, a] = texture.pixel(tex_x, tex_y);
+
+          if a == 0 {
+            continue;
+          }
+
+          let index = y as usize * pitch + stripe as usize * 4;
+          buffer[index..index + 4].copy_from_slice(&[r, g, b, 255]);
+        }
+      }
+    }
+  }
+
+  ///
+  /// Makes sure the frame texture and framebuffer match the render size.
+  ///
+  /// They only get rebuilt when the size or the upscale filter changes, not every frame.
+  ///
+  fn prepare_frame(&mut self, window: &WinHandler, render_size: IVec2) {
+    if self.frame.is_some()
+      && self.frame_size == render_size
+      && self.frame_filter == self.upscale_filter
+    {
+      return;
+    }
+
+    // SDL reads this when the texture gets created.
+    sdl2::hint::set("SDL_RENDER_SCALE_QUALITY", self.upscale_filter.sdl_hint());
+
+    self.frame = Some(
+      window
+        .texture_creator
+        .unwrap()
+        .create_texture_streaming(
+          PixelFormatEnum::RGBA32,
+          render_size.x as u32,
+          render_size.y as u32,
+        )
+        .map_err(|e| panic!("{}", e))
+        .unwrap(),
+    );
+    self.frame_size = render_size;
+    self.frame_filter = self.upscale_filter;
+    self.framebuffer = vec![0; (render_size.x * render_size.y * 4) as usize];
+  }
+
   ///
   /// Handles all logic for drawing things to the Window's framebuffer.
   ///
-  pub fn draw(&mut self, window: &mut WinHandler, world: &mut World) {
-    // We create a new frame buffer literally every frame.
+  pub fn draw(
+    &mut self,
+    window: &mut WinHandler,
+    world: &mut World,
+    profiler: &mut Profiler,
+    menu: Option<&Menu>,
+  ) {
+    let started = Instant::now();
     window.canvas.as_mut().unwrap().clear();
+    profiler.record(Phase::Present, started.elapsed());
 
-    let window_size = &window.window_size;
+    // The frame gets rendered at this size, then SDL stretches it over the window.
+    let render_size = self.render_resolution.render_size(window.window_size);
+    self.prepare_frame(window, render_size);
+    let pitch = render_size.x as usize * 4;
 
-    let texture_creator = window.canvas.as_ref().unwrap().texture_creator();
-    let mut texture = texture_creator
-      .create_texture_streaming(
-        PixelFormatEnum::RGBA8888,
-        window_size.x as u32,
-        window_size.y as u32,
-      )
-      .map_err(|e| panic!("{}", e))
-      .unwrap();
+    // Borrowed out of self for a moment so raycast can still have &mut self.
+    let mut framebuffer = take(&mut self.framebuffer);
+
+    // No point drawing the world if a menu is going to cover all of it.
+    let world_visible = menu.map_or(true, |menu| !menu.opaque);
+
+    let mut frame = FrameBuffer::new(&mut framebuffer, render_size, pitch);
+
+    if world_visible {
+      let views = split_screen(world.local_player_count(), render_size);
+
+      for (view, (position, size)) in views.iter().enumerate() {
+        let mut view_frame = frame.view(*position, *size);
+
+        let started = Instant::now();
+        self.raycast(world, view, &view_frame.size, view_frame.buffer, pitch);
+        profiler.record(Phase::Raycast, started.elapsed());
+
+        let started = Instant::now();
+        self.draw_sprites(world, view, &view_frame.size, view_frame.buffer, pitch);
+        profiler.record(Phase::Sprites, started.elapsed());
 
-    // We can pass the self raycasting function straight into a write lock closure. Incredible.
-    texture
-      .with_lock(None, |buffer, pitch| {
-        self.raycast(world, window_size, buffer, pitch);
-      })
+        // Anything 2D goes on top of the 3D view.
+        draw_hud(&mut view_frame, world, view, &self.textures);
+        self.automap.draw(&mut view_frame, world, view);
+      }
+
+      // Three players leave a corner nobody's looking through.
+      if views.len() == 3 {
+        frame.fill_rect(render_size / 2, render_size - render_size / 2, [0, 0, 0]);
+      }
+    }
+    if let Some(menu) = menu {
+      menu.draw(&mut frame);
+    }
+    profiler.draw_overlay(&mut frame);
+
+    self.framebuffer = framebuffer;
+
+    let started = Instant::now();
+    self
+      .frame
+      .as_mut()
+      .unwrap()
+      .update(None, &self.framebuffer, pitch)
+      .map_err(|e| panic!("{}", e))
       .unwrap();
+    profiler.record(Phase::Upload, started.elapsed());
 
-    window.draw(&texture);
+    let started = Instant::now();
+    window.draw(self.frame.as_ref().unwrap());
+    profiler.record(Phase::Present, started.elapsed());
   }
 }
 
@@ -0,0 +1,628 @@
+use std::{
+  collections::HashMap,
+  fmt, fs, io,
+  path::{Path, PathBuf},
+  time::{SystemTime, UNIX_EPOCH},
+};
+
+use glam::DVec2;
+use serde::{Deserialize, Serialize};
+
+use super::{
+  entity::{AiState, Bullet, MOB_HEALTH},
+  level::{check_map, on_floor, Level},
+  settings::Settings,
+  texture::{find_texture, TEXTURE_NAMES},
+  weapon::{AmmoType, Weapons},
+  world::{KeyColor, World},
+};
+
+///
+/// Bump this whenever the save format changes in a way old saves can't just be read as,
+/// and add a migration for the old version to MIGRATIONS.
+///
+pub const SAVE_VERSION: u32 = 4;
+
+///
+/// Upgrades a save one version. MIGRATIONS[0] takes a version 1 save to version 2, and so on.
+///
+/// Adding a field with a default doesn't need one of these, serde fills it in.
+///
+type Migration = fn(&mut toml::Table) -> Result<(), SaveError>;
+const MIGRATIONS: [Migration; 3] = [add_level, split_ammo, list_bullets];
+
+///
+/// Version 1 was from before levels, when there was only the hangar.
+///
+fn add_level(table: &mut toml::Table) -> Result<(), SaveError> {
+  table.insert("level".to_string(), toml::Value::from("hangar"));
+  Ok(())
+}
+
+///
+/// Version 2 had one pile of ammo for everything. It was all bullets back then.
+///
+fn split_ammo(table: &mut toml::Table) -> Result<(), SaveError> {
+  let Some(player) = table
+    .get_mut("player")
+    .and_then(|player| player.as_table_mut())
+  else {
+    return Ok(());
+  };
+  if let Some(ammo) = player.get("ammo").and_then(|ammo| ammo.as_integer()) {
+    let mut split = toml::Table::new();
+    split.insert(
+      AmmoType::Bullets.name().to_string(),
+      toml::Value::Integer(ammo),
+    );
+    player.insert("ammo".to_string(), toml::Value::Table(split));
+  }
+  Ok(())
+}
+
+///
+/// Version 3 kept bullets in a table by name. Entities don't have names, so now it's a list.
+///
+fn list_bullets(table: &mut toml::Table) -> Result<(), SaveError> {
+  if let Some(toml::Value::Table(bullets)) = table.remove("bullets") {
+    let bullets = bullets.into_iter().map(|(_, bullet)| bullet).collect();
+    table.insert("bullets".to_string(), toml::Value::Array(bullets));
+  }
+  Ok(())
+}
+
+/// Slot 0 is the quicksave, the rest are picked from the menu.
+pub const QUICKSAVE_SLOT: usize = 0;
+pub const SAVE_SLOTS: usize = 3;
+
+///
+/// Something went wrong reading or writing a save.
+///
+#[derive(Debug)]
+pub enum SaveError {
+  Io(PathBuf, io::Error),
+  Parse(PathBuf, toml::de::Error),
+  Serialize(toml::ser::Error),
+  TooNew(u32),
+  Invalid(String),
+}
+
+impl fmt::Display for SaveError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      SaveError::Io(path, e) => write!(f, "could not access {}: {}", path.display(), e),
+      SaveError::Parse(path, e) => write!(f, "could not parse {}: {}", path.display(), e),
+      SaveError::Serialize(e) => write!(f, "could not serialize save: {}", e),
+      SaveError::TooNew(version) => write!(
+        f,
+        "save is version {} but this build only understands up to {}",
+        version, SAVE_VERSION
+      ),
+      SaveError::Invalid(reason) => write!(f, "invalid save: {}", reason),
+    }
+  }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct PlayerSave {
+  pub alive: bool,
+  pub position: [f64; 2],
+  pub direction: [f64; 2],
+  pub pitch: f64,
+  pub z: f64,
+  pub z_velocity: f64,
+  pub crouching: bool,
+  pub health: i32,
+  pub armor: i32,
+  pub ammo: HashMap<AmmoType, i32>,
+  pub keys: Vec<KeyColor>,
+  pub weapons: Vec<String>,
+  pub weapon: String,
+}
+
+impl Default for PlayerSave {
+  fn default() -> Self {
+    PlayerSave {
+      alive: true,
+      position: [22.0, 12.0],
+      direction: [-1.0, 0.0],
+      pitch: 0.0,
+      z: 0.0,
+      z_velocity: 0.0,
+      crouching: false,
+      health: 100,
+      armor: 0,
+      ammo: HashMap::from([(AmmoType::Bullets, 50)]),
+      keys: Vec::new(),
+      weapons: vec!["pistol".to_string()],
+      weapon: "pistol".to_string(),
+    }
+  }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MobSave {
+  pub alive: bool,
+  pub position: [f64; 2],
+  pub yaw: f64,
+  /// Saved by name so adding textures doesn't scramble old saves.
+  pub sprite: String,
+  /// Older saves don't have this, so mobs start those at full health.
+  #[serde(default = "default_mob_health")]
+  pub health: i32,
+  #[serde(default)]
+  pub ai: AiState,
+}
+
+fn default_mob_health() -> i32 {
+  MOB_HEALTH
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BulletSave {
+  pub position: [f64; 2],
+  pub direction: [f64; 2],
+  /// Missing means hitscan.
+  #[serde(default)]
+  pub speed: Option<f64>,
+  #[serde(default)]
+  pub damage: i32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MapSave {
+  pub data: Vec<Vec<i32>>,
+  pub revealed: Vec<Vec<bool>>,
+}
+
+///
+/// Everything needed to put a World back together.
+///
+/// This is its own set of structs so the game can change shape without breaking saves.
+///
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SaveGame {
+  pub version: u32,
+  /// Unix time in seconds.
+  #[serde(default)]
+  pub saved_at: u64,
+  /// The id of the level the map came from.
+  pub level: String,
+  #[serde(default)]
+  pub level_time: f64,
+  #[serde(default)]
+  pub player: PlayerSave,
+  #[serde(default)]
+  pub plane: [f64; 2],
+  #[serde(default)]
+  pub mobs: Vec<MobSave>,
+  #[serde(default)]
+  pub bullets: Vec<BulletSave>,
+  pub map: MapSave,
+  /// Which of the level's triggers have gone off, in the order the level lists them.
+  #[serde(default)]
+  pub triggers_fired: Vec<bool>,
+  /// Same for the level's items.
+  #[serde(default)]
+  pub items_collected: Vec<bool>,
+}
+
+fn to_array(vector: DVec2) -> [f64; 2] {
+  [vector.x, vector.y]
+}
+
+fn to_vector(array: [f64; 2]) -> DVec2 {
+  DVec2::new(array[0], array[1])
+}
+
+impl SaveGame {
+  pub fn from_world(world: &World) -> Self {
+    let player = world.player();
+    let entities = &world.entities;
+
+    SaveGame {
+      version: SAVE_VERSION,
+      saved_at: SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs()),
+      level: world.level_id.clone(),
+      level_time: world.level_time,
+      player: PlayerSave {
+        alive: player.alive,
+        position: to_array(player.position),
+        direction: to_array(player.direction),
+        pitch: player.pitch,
+        z: player.z,
+        z_velocity: player.z_velocity,
+        crouching: player.crouching,
+        health: player.health,
+        armor: player.armor,
+        ammo: player.ammo.clone(),
+        keys: player.keys.clone(),
+        weapons: player.weapons.clone(),
+        weapon: player.weapon.clone(),
+      },
+      plane: to_array(player.plane),
+      mobs: entities
+        .mobs()
+        .filter_map(|id| {
+          let transform = entities.transforms.get(id)?;
+          Some(MobSave {
+            alive: entities.is_alive(id),
+            position: to_array(transform.position),
+            yaw: transform.yaw,
+            sprite: TEXTURE_NAMES[entities.sprites.get(id)?.texture].to_string(),
+            health: entities.healths.get(id)?.current,
+            ai: entities.ais.get(id)?.state,
+          })
+        })
+        .collect(),
+      bullets: entities
+        .bullets
+        .iter()
+        .filter_map(|(id, bullet)| {
+          Some(BulletSave {
+            position: to_array(entities.position(id)?),
+            direction: to_array(bullet.direction),
+            speed: bullet.speed,
+            damage: bullet.damage,
+          })
+        })
+        .collect(),
+      map: MapSave {
+        data: world.map.data.clone(),
+        revealed: world.map.revealed.clone(),
+      },
+      triggers_fired: world.triggers.iter().map(|trigger| trigger.fired).collect(),
+      items_collected: (0..world.placed_items)
+        .map(|index| {
+          !entities
+            .pickups
+            .iter()
+            .any(|(_, pickup)| pickup.placed == Some(index))
+        })
+        .collect(),
+    }
+  }
+
+  ///
+  /// Builds a fresh World out of the level the save was made in, then pours the save into it.
+  ///
+  pub fn to_world(
+    &self,
+    level: &Level,
+    settings: &Settings,
+    weapons: &Weapons,
+  ) -> Result<World, SaveError> {
+    self.check(level)?;
+    let mut world = World::new(level, settings, weapons);
+    world.level_time = self.level_time;
+
+    let saved = &self.player;
+    let player = world.player_mut();
+    player.alive = saved.alive;
+    player.position = to_vector(saved.position);
+    player.direction = to_vector(saved.direction).normalize_or_zero();
+    player.pitch = saved.pitch;
+    player.z = saved.z;
+    player.z_velocity = saved.z_velocity;
+    player.crouching = saved.crouching;
+    player.health = saved.health;
+    player.armor = saved.armor;
+    player.ammo.clone_from(&saved.ammo);
+    player.keys.clone_from(&saved.keys);
+    player.weapons.clone_from(&saved.weapons);
+    player.weapon.clone_from(&saved.weapon);
+
+    if player.direction == DVec2::ZERO {
+      return Err(SaveError::Invalid("player has no direction".to_string()));
+    }
+
+    // The level's mobs get swapped out for the saved ones, items stay put until the
+    // collected ones get picked out below.
+    let entities = &mut world.entities;
+    for id in entities.mobs().collect::<Vec<_>>() {
+      entities.despawn(id);
+    }
+    for saved in &self.mobs {
+      let sprite = find_texture(&saved.sprite)
+        .ok_or_else(|| SaveError::Invalid(format!("unknown mob sprite {}", saved.sprite)))?;
+      let id = entities.spawn_mob(to_vector(saved.position));
+      if let Some(transform) = entities.transforms.get_mut(id) {
+        transform.yaw = saved.yaw;
+      }
+      if let Some(health) = entities.healths.get_mut(id) {
+        health.current = saved.health;
+      }
+      if let Some(ai) = entities.ais.get_mut(id) {
+        ai.state = saved.ai;
+      }
+      if !saved.alive {
+        entities.kill(id);
+      }
+      if let Some(mob_sprite) = entities.sprites.get_mut(id) {
+        mob_sprite.texture = sprite;
+      }
+    }
+
+    for saved in &self.bullets {
+      entities.spawn_bullet(
+        to_vector(saved.position),
+        Bullet {
+          direction: to_vector(saved.direction),
+          speed: saved.speed,
+          damage: saved.damage,
+          owner: None,
+        },
+      );
+    }
+
+    let map = &mut world.map;
+    let (width, height) = (map.data.len(), map.data[0].len());
+    map.data = self.map.data.clone();
+    // Older saves might not have the fog of war, that just means nothing has been seen.
+    for (x, row) in self.map.revealed.iter().enumerate().take(width) {
+      for (y, seen) in row.iter().enumerate().take(height) {
+        map.revealed[x][y] = *seen;
+      }
+    }
+
+    // Extra or missing entries just mean the level changed since, so match up what's there.
+    for (trigger, fired) in world.triggers.iter_mut().zip(&self.triggers_fired) {
+      trigger.fired = *fired;
+    }
+    let collected: Vec<_> = world
+      .entities
+      .pickups
+      .iter()
+      .filter(|(_, pickup)| {
+        pickup
+          .placed
+          .and_then(|index| self.items_collected.get(index))
+          .copied()
+          .unwrap_or(false)
+      })
+      .map(|(id, _)| id)
+      .collect();
+    for id in collected {
+      world.entities.despawn(id);
+    }
+    // The script's variables aren't saved, but at least it shouldn't set the level up twice.
+    if let Some(script) = world.script.as_mut() {
+      script.started = true;
+    }
+
+    // The plane comes back too, but the FOV in the settings wins.
+    world.player_mut().plane = to_vector(self.plane);
+    world.set_fov(settings.video.fov);
+    world.sync_body(0);
+
+    Ok(world)
+  }
+
+  ///
+  /// Holds a save to the same rules as a level file, so a broken or hand edited one gets
+  /// turned away here instead of crashing the game once it's playing.
+  ///
+  fn check(&self, level: &Level) -> Result<(), SaveError> {
+    let invalid = |reason: String| Err(SaveError::Invalid(reason));
+    let (width, height) = (level.map.len(), level.map[0].len());
+    if self.map.data.len() != width || self.map.data.iter().any(|row| row.len() != height) {
+      return invalid(format!(
+        "map must be {}x{} to match {}",
+        width, height, level.id
+      ));
+    }
+    check_map(&self.map.data).map_err(SaveError::Invalid)?;
+
+    let floor = |position: [f64; 2]| on_floor(&self.map.data, to_vector(position));
+    if !floor(self.player.position) {
+      return invalid("player is inside a wall".to_string());
+    }
+    if let Some(mob) = self.mobs.iter().find(|mob| !floor(mob.position)) {
+      return invalid(format!(
+        "mob at {}, {} is inside a wall",
+        mob.position[0], mob.position[1]
+      ));
+    }
+    if let Some(bullet) = self
+      .bullets
+      .iter()
+      .find(|bullet| !floor(bullet.position) || !to_vector(bullet.direction).is_finite())
+    {
+      return invalid(format!(
+        "bullet at {}, {} is inside a wall or going nowhere",
+        bullet.position[0], bullet.position[1]
+      ));
+    }
+    Ok(())
+  }
+
+  ///
+  /// Parses a save, upgrading it from older versions on the way.
+  ///
+  pub fn parse(raw: &str, path: &Path) -> Result<Self, SaveError> {
+    let mut table: toml::Table =
+      toml::from_str(raw).map_err(|e| SaveError::Parse(path.to_path_buf(), e))?;
+
+    let version = table
+      .get("version")
+      .and_then(|version| version.as_integer())
+      .ok_or_else(|| SaveError::Invalid("missing version".to_string()))? as u32;
+
+    if version > SAVE_VERSION {
+      return Err(SaveError::TooNew(version));
+    }
+    if version == 0 {
+      return Err(SaveError::Invalid("version 0 does not exist".to_string()));
+    }
+
+    for migration in &MIGRATIONS[(version - 1) as usize..] {
+      migration(&mut table)?;
+    }
+    table.insert(
+      "version".to_string(),
+      toml::Value::Integer(SAVE_VERSION as i64),
+    );
+
+    table
+      .try_into()
+      .map_err(|e| SaveError::Parse(path.to_path_buf(), e))
+  }
+
+  pub fn slot_path(slot: usize) -> Option<PathBuf> {
+    let name = if slot == QUICKSAVE_SLOT {
+      "quicksave.toml".to_string()
+    } else {
+      format!("slot{}.toml", slot)
+    };
+    Settings::default_path().and_then(|path| path.parent().map(|dir| dir.join("saves").join(name)))
+  }
+
+  pub fn read(slot: usize) -> Result<Self, SaveError> {
+    let path = SaveGame::slot_path(slot)
+      .ok_or_else(|| SaveError::Invalid("no config directory to load from".to_string()))?;
+    let raw = fs::read_to_string(&path).map_err(|e| SaveError::Io(path.clone(), e))?;
+    SaveGame::parse(&raw, &path)
+  }
+
+  pub fn write(&self, slot: usize) -> Result<PathBuf, SaveError> {
+    let path = SaveGame::slot_path(slot)
+      .ok_or_else(|| SaveError::Invalid("no config directory to save to".to_string()))?;
+
+    if let Some(dir) = path.parent() {
+      fs::create_dir_all(dir).map_err(|e| SaveError::Io(dir.to_path_buf(), e))?;
+    }
+
+    let raw = toml::to_string(self).map_err(SaveError::Serialize)?;
+    fs::write(&path, raw).map_err(|e| SaveError::Io(path.clone(), e))?;
+    Ok(path)
+  }
+
+  ///
+  /// A short description of what's in a slot, for the menus. None if the slot is empty.
+  ///
+  pub fn describe_slot(slot: usize) -> Option<String> {
+    let save = SaveGame::read(slot).ok()?;
+    let now = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .map_or(0, |time| time.as_secs());
+    let minutes = now.saturating_sub(save.saved_at) / 60;
+
+    let age = match minutes {
+      0 => "just now".to_string(),
+      1..=59 => format!("{} min ago", minutes),
+      60..=1439 => format!("{} h ago", minutes / 60),
+      _ => format!("{} days ago", minutes / 1440),
+    };
+    Some(format!(
+      "{}, HP {}, {}",
+      save.level, save.player.health, age
+    ))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::boom::world::test_world;
+
+  ///
+  /// A save from before levels, split ammo and entity lists, as the game used to write them.
+  ///
+  const VERSION_1: &str = r#"
+version = 1
+saved_at = 1700000000
+level_time = 12.5
+plane = [0.0, 0.66]
+
+[player]
+position = [1.5, 1.5]
+direction = [-1.0, 0.0]
+health = 80
+ammo = 37
+
+[map]
+data = [[1, 1, 1], [1, 0, 1], [1, 1, 1]]
+revealed = [[true, true, true], [true, true, true], [true, true, true]]
+
+[bullets.first]
+position = [1.5, 1.5]
+direction = [1.0, 0.0]
+"#;
+
+  fn hangar_world() -> (Level, World) {
+    (Level::load("hangar").unwrap(), test_world("hangar"))
+  }
+
+  fn load(save: &SaveGame, level: &Level) -> Result<World, SaveError> {
+    let weapons = Weapons::load(&Weapons::default_path()).unwrap();
+    save.to_world(level, &Settings::default(), &weapons)
+  }
+
+  #[test]
+  fn version_1_migrates_to_current() {
+    let save = SaveGame::parse(VERSION_1, Path::new("v1.toml")).unwrap();
+
+    assert_eq!(save.version, SAVE_VERSION);
+    assert_eq!(save.level, "hangar");
+    assert_eq!(save.level_time, 12.5);
+    assert_eq!(save.player.health, 80);
+    assert_eq!(save.player.ammo, HashMap::from([(AmmoType::Bullets, 37)]));
+    assert_eq!(save.bullets.len(), 1);
+    assert_eq!(save.bullets[0].direction, [1.0, 0.0]);
+  }
+
+  #[test]
+  fn too_new_and_zero_versions_are_refused() {
+    let newer = format!("version = {}\n", SAVE_VERSION + 1);
+    assert!(matches!(
+      SaveGame::parse(&newer, Path::new("new.toml")),
+      Err(SaveError::TooNew(_))
+    ));
+    assert!(matches!(
+      SaveGame::parse("version = 0\n", Path::new("zero.toml")),
+      Err(SaveError::Invalid(_))
+    ));
+  }
+
+  #[test]
+  fn saves_round_trip() {
+    let (level, world) = hangar_world();
+    let save = SaveGame::from_world(&world);
+    let raw = toml::to_string(&save).unwrap();
+    let parsed = SaveGame::parse(&raw, Path::new("slot.toml")).unwrap();
+
+    let loaded = load(&parsed, &level).unwrap();
+    assert_eq!(loaded.player().position, world.player().position);
+    assert_eq!(loaded.map.data, world.map.data);
+  }
+
+  #[test]
+  fn open_edges_are_invalid() {
+    let (level, world) = hangar_world();
+    let mut save = SaveGame::from_world(&world);
+    save.map.data[0][5] = 0;
+    assert!(matches!(load(&save, &level), Err(SaveError::Invalid(_))));
+  }
+
+  #[test]
+  fn things_inside_walls_are_invalid() {
+    let (level, world) = hangar_world();
+
+    let mut save = SaveGame::from_world(&world);
+    save.player.position = [0.5, 0.5];
+    assert!(matches!(load(&save, &level), Err(SaveError::Invalid(_))));
+
+    let mut save = SaveGame::from_world(&world);
+    save.player.position = [-40.0, f64::NAN];
+    assert!(matches!(load(&save, &level), Err(SaveError::Invalid(_))));
+
+    let mut save = SaveGame::from_world(&world);
+    if let Some(mob) = save.mobs.first_mut() {
+      mob.position = [1000.0, 3.5];
+      assert!(matches!(load(&save, &level), Err(SaveError::Invalid(_))));
+    }
+  }
+}
@@ -0,0 +1,490 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use glam::{DVec2, IVec2};
+use rhai::{
+  module_resolvers::DummyModuleResolver, Array, CallFnOptions, Dynamic, Engine, EvalAltResult,
+  Scope, AST,
+};
+
+use super::{
+  entity::{Bullet, EntityId},
+  level::on_floor,
+  weapon::AmmoType,
+  world::World,
+};
+
+// Bullets from scripts are slow projectiles, so there's a chance to see them coming.
+const BULLET_SPEED: f64 = 8.0;
+const BULLET_DAMAGE: i32 = 10;
+
+///
+/// Everything a script can see and touch, copied out of the World before a hook runs
+/// and copied back in afterwards.
+///
+/// Scripts never get the real World, so there's nothing they can break that we can't check.
+///
+#[derive(Default)]
+struct Shared {
+  player_position: DVec2,
+  player_direction: DVec2,
+  health: i32,
+  armor: i32,
+  /// Scripts only see the ammo for the weapon the player is holding.
+  ammo: i32,
+  ammo_type: Option<AmmoType>,
+  /// Position and whether it's alive. Anything past the end of mob_ids was spawned by the script.
+  /// Killing a mob sticks, setting a dead one back to alive does nothing.
+  mobs: Vec<(DVec2, bool)>,
+  mob_ids: Vec<EntityId>,
+  map: Vec<Vec<i32>>,
+  level_time: f64,
+  bullets: Vec<(DVec2, DVec2)>,
+  messages: Vec<String>,
+  end_level: bool,
+  /// Script functions can't see each other's variables, so anything that needs to stick
+  /// around between hooks lives in here.
+  vars: HashMap<String, Dynamic>,
+}
+
+impl Shared {
+  fn load(&mut self, world: &World) {
+    let player = world.player();
+    self.player_position = player.position;
+    self.player_direction = player.direction;
+    self.health = player.health;
+    self.armor = player.armor;
+    self.ammo_type = world.weapons.get(&player.weapon).map(|weapon| weapon.ammo);
+    self.ammo = self.ammo_type.map_or(0, |ammo| player.ammo_of(ammo));
+    let entities = &world.entities;
+    self.mob_ids = entities
+      .mobs()
+      .filter(|id| entities.transforms.contains(*id))
+      .collect();
+    self.mobs = self
+      .mob_ids
+      .iter()
+      .filter_map(|id| Some((entities.position(*id)?, entities.is_alive(*id))))
+      .collect();
+    self.map.clone_from(&world.map.data);
+    self.level_time = world.level_time;
+  }
+
+  fn store(&mut self, world: &mut World) {
+    let player = world.player_mut();
+    player.position = self.player_position;
+    player.health = self.health;
+    player.armor = self.armor;
+    if let Some(ammo) = self.ammo_type {
+      player.ammo.insert(ammo, self.ammo.max(0));
+    }
+
+    let entities = &mut world.entities;
+    for (index, (position, alive)) in self.mobs.iter().enumerate() {
+      let id = match self.mob_ids.get(index) {
+        Some(id) => *id,
+        None => entities.spawn_mob(*position),
+      };
+      entities.move_to(id, *position);
+      if !*alive && entities.is_alive(id) {
+        entities.kill(id);
+      }
+    }
+
+    world.map.data.clone_from(&self.map);
+
+    for (position, direction) in self.bullets.drain(..) {
+      world.entities.spawn_bullet(
+        position,
+        Bullet {
+          direction,
+          speed: Some(BULLET_SPEED),
+          damage: BULLET_DAMAGE,
+          owner: None,
+        },
+      );
+    }
+    for message in self.messages.drain(..) {
+      world.show_message(&message);
+    }
+    if self.end_level {
+      world.exited = true;
+      self.end_level = false;
+    }
+  }
+
+  fn check_cell(&self, x: i64, y: i64) -> Result<IVec2, Box<EvalAltResult>> {
+    let width = self.map.len() as i64;
+    let height = self.map.first().map_or(0, |row| row.len()) as i64;
+    if x < 0 || y < 0 || x >= width || y >= height {
+      return Err(format!("cell {}, {} is off the map", x, y).into());
+    }
+    Ok(IVec2::new(x as i32, y as i32))
+  }
+
+  ///
+  /// Anything a script moves has to end up on a floor cell, or the raycaster walks through
+  /// the edge walls and off the end of the map.
+  ///
+  fn check_position(&self, position: DVec2) -> Result<DVec2, Box<EvalAltResult>> {
+    if !position.is_finite() || !on_floor(&self.map, position) {
+      return Err(format!("{}, {} isn't on the floor", position.x, position.y).into());
+    }
+    Ok(position)
+  }
+}
+
+type SharedRef = Rc<RefCell<Shared>>;
+
+/// `player()` in a script. In multiplayer that's whoever joined first.
+#[derive(Clone)]
+struct PlayerRef(SharedRef);
+
+/// Each entry of `mobs()` in a script.
+#[derive(Clone)]
+struct MobRef(SharedRef, usize);
+
+/// `map()` in a script.
+#[derive(Clone)]
+struct MapRef(SharedRef);
+
+// Stops a runaway loop from freezing the game. Plenty for anything reasonable in one hook.
+const MAX_OPERATIONS: u64 = 200_000;
+
+///
+/// A level's script and the sandboxed engine that runs it.
+///
+/// Levels hook in by defining any of these functions:
+///
+/// on_start()       when the level starts.
+/// on_tick(delta)   every tick, delta in seconds.
+/// on_use(x, y)     when the player uses a wall.
+///
+/// Triggers can also call any function with no arguments through a run_script action.
+///
+/// Scripts get no filesystem, no imports and no eval. A script that errors gets stopped and
+/// the error shows up in game.
+///
+pub struct Script {
+  name: String,
+  engine: Engine,
+  ast: AST,
+  scope: Scope<'static>,
+  shared: SharedRef,
+  /// Whether on_start has been run. Loading a save sets this so it doesn't run twice.
+  pub started: bool,
+  broken: bool,
+}
+
+impl Script {
+  pub fn compile(name: &str, source: &str) -> Result<Self, String> {
+    let shared = SharedRef::default();
+    let engine = Script::build_engine(&shared);
+
+    let ast = engine
+      .compile(source)
+      .map_err(|e| format!("script error in {}: {}", name, e))?;
+
+    let mut script = Script {
+      name: name.to_string(),
+      engine,
+      ast,
+      scope: Scope::new(),
+      shared,
+      started: false,
+      broken: false,
+    };
+
+    // Top level code runs once, up front. It can only see the script's own variables.
+    if let Err(e) = script
+      .engine
+      .run_ast_with_scope(&mut script.scope, &script.ast)
+    {
+      return Err(format!("script error in {}: {}", name, e));
+    }
+
+    Ok(script)
+  }
+
+  fn build_engine(shared: &SharedRef) -> Engine {
+    let mut engine = Engine::new();
+
+    // The sandbox. Rhai has no file access of its own, this closes the rest.
+    engine.set_module_resolver(DummyModuleResolver::new());
+    engine.disable_symbol("eval");
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.set_max_call_levels(32);
+    engine.set_max_expr_depths(64, 32);
+    engine.set_max_string_size(4096);
+    engine.set_max_array_size(4096);
+    engine.set_max_map_size(1024);
+
+    let messages = shared.clone();
+    engine.on_print(move |text| messages.borrow_mut().messages.push(text.to_string()));
+    engine.on_debug(|text, _, position| eprintln!("boom: script debug at {}: {}", position, text));
+
+    engine
+      .register_type_with_name::<PlayerRef>("Player")
+      .register_get("x", |p: &mut PlayerRef| p.0.borrow().player_position.x)
+      .register_get("y", |p: &mut PlayerRef| p.0.borrow().player_position.y)
+      .register_set(
+        "x",
+        |p: &mut PlayerRef, x: f64| -> Result<(), Box<EvalAltResult>> {
+          let mut shared = p.0.borrow_mut();
+          let position = DVec2::new(x, shared.player_position.y);
+          shared.player_position = shared.check_position(position)?;
+          Ok(())
+        },
+      )
+      .register_set(
+        "y",
+        |p: &mut PlayerRef, y: f64| -> Result<(), Box<EvalAltResult>> {
+          let mut shared = p.0.borrow_mut();
+          let position = DVec2::new(shared.player_position.x, y);
+          shared.player_position = shared.check_position(position)?;
+          Ok(())
+        },
+      )
+      .register_get("dir_x", |p: &mut PlayerRef| p.0.borrow().player_direction.x)
+      .register_get("dir_y", |p: &mut PlayerRef| p.0.borrow().player_direction.y)
+      .register_get_set(
+        "health",
+        |p: &mut PlayerRef| p.0.borrow().health as i64,
+        |p: &mut PlayerRef, health: i64| p.0.borrow_mut().health = health as i32,
+      )
+      .register_get_set(
+        "armor",
+        |p: &mut PlayerRef| p.0.borrow().armor as i64,
+        |p: &mut PlayerRef, armor: i64| p.0.borrow_mut().armor = armor as i32,
+      )
+      .register_get_set(
+        "ammo",
+        |p: &mut PlayerRef| p.0.borrow().ammo as i64,
+        |p: &mut PlayerRef, ammo: i64| p.0.borrow_mut().ammo = ammo as i32,
+      );
+
+    engine
+      .register_type_with_name::<MobRef>("Mob")
+      .register_get("x", |m: &mut MobRef| m.0.borrow().mobs[m.1].0.x)
+      .register_get("y", |m: &mut MobRef| m.0.borrow().mobs[m.1].0.y)
+      .register_set(
+        "x",
+        |m: &mut MobRef, x: f64| -> Result<(), Box<EvalAltResult>> {
+          let mut shared = m.0.borrow_mut();
+          let position = DVec2::new(x, shared.mobs[m.1].0.y);
+          shared.mobs[m.1].0 = shared.check_position(position)?;
+          Ok(())
+        },
+      )
+      .register_set(
+        "y",
+        |m: &mut MobRef, y: f64| -> Result<(), Box<EvalAltResult>> {
+          let mut shared = m.0.borrow_mut();
+          let position = DVec2::new(shared.mobs[m.1].0.x, y);
+          shared.mobs[m.1].0 = shared.check_position(position)?;
+          Ok(())
+        },
+      )
+      .register_get_set(
+        "alive",
+        |m: &mut MobRef| m.0.borrow().mobs[m.1].1,
+        |m: &mut MobRef, alive: bool| m.0.borrow_mut().mobs[m.1].1 = alive,
+      );
+
+    engine
+      .register_type_with_name::<MapRef>("LevelMap")
+      .register_get("width", |m: &mut MapRef| m.0.borrow().map.len() as i64)
+      .register_get("height", |m: &mut MapRef| {
+        m.0.borrow().map.first().map_or(0, |row| row.len()) as i64
+      })
+      .register_fn(
+        "wall",
+        |m: &mut MapRef, x: i64, y: i64| -> Result<i64, Box<EvalAltResult>> {
+          let shared = m.0.borrow();
+          let cell = shared.check_cell(x, y)?;
+          Ok(shared.map[cell.x as usize][cell.y as usize] as i64)
+        },
+      )
+      .register_fn(
+        "set_wall",
+        |m: &mut MapRef, x: i64, y: i64, wall: i64| -> Result<(), Box<EvalAltResult>> {
+          let mut shared = m.0.borrow_mut();
+          let cell = shared.check_cell(x, y)?;
+          let wall = match i32::try_from(wall) {
+            Ok(wall) if wall >= 0 => wall,
+            _ => return Err(format!("{} isn't a wall", wall).into()),
+          };
+          let width = shared.map.len() as i32;
+          let height = shared.map[0].len() as i32;
+          let edge = cell.x == 0 || cell.y == 0 || cell.x == width - 1 || cell.y == height - 1;
+          if edge && wall <= 0 {
+            return Err(format!("can't open the map edge at {}, {}", x, y).into());
+          }
+          shared.map[cell.x as usize][cell.y as usize] = wall;
+          Ok(())
+        },
+      );
+
+    let handle = shared.clone();
+    engine.register_fn("player", move || PlayerRef(handle.clone()));
+
+    let handle = shared.clone();
+    engine.register_fn("mobs", move || -> Array {
+      (0..handle.borrow().mobs.len())
+        .map(|index| Dynamic::from(MobRef(handle.clone(), index)))
+        .collect()
+    });
+
+    let handle = shared.clone();
+    engine.register_fn("map", move || MapRef(handle.clone()));
+
+    let handle = shared.clone();
+    engine.register_fn(
+      "spawn_mob",
+      move |x: f64, y: f64| -> Result<(), Box<EvalAltResult>> {
+        let mut shared = handle.borrow_mut();
+        let position = shared.check_position(DVec2::new(x, y))?;
+        shared.mobs.push((position, true));
+        Ok(())
+      },
+    );
+
+    let handle = shared.clone();
+    engine.register_fn(
+      "spawn_bullet",
+      move |x: f64, y: f64, dir_x: f64, dir_y: f64| -> Result<(), Box<EvalAltResult>> {
+        let mut shared = handle.borrow_mut();
+        let position = shared.check_position(DVec2::new(x, y))?;
+        let direction = DVec2::new(dir_x, dir_y).normalize_or_zero();
+        shared.bullets.push((position, direction));
+        Ok(())
+      },
+    );
+
+    let handle = shared.clone();
+    engine.register_fn("message", move |text: &str| {
+      handle.borrow_mut().messages.push(text.to_string());
+    });
+
+    let handle = shared.clone();
+    engine.register_fn("end_level", move || handle.borrow_mut().end_level = true);
+
+    let handle = shared.clone();
+    engine.register_fn("level_time", move || handle.borrow().level_time);
+
+    let handle = shared.clone();
+    engine.register_fn("set_var", move |name: &str, value: Dynamic| {
+      handle.borrow_mut().vars.insert(name.to_string(), value);
+    });
+
+    let handle = shared.clone();
+    engine.register_fn("get_var", move |name: &str| {
+      handle
+        .borrow()
+        .vars
+        .get(name)
+        .cloned()
+        .unwrap_or(Dynamic::UNIT)
+    });
+
+    engine
+  }
+
+  fn has_function(&self, name: &str, arguments: usize) -> bool {
+    self
+      .ast
+      .iter_functions()
+      .any(|function| function.name == name && function.params.len() == arguments)
+  }
+
+  ///
+  /// Calls a function in the script if it has one, with the world copied in and back out around it.
+  ///
+  /// Errors stop the script for good and get shown on the HUD.
+  ///
+  pub fn call(&mut self, world: &mut World, function: &str, arguments: Vec<Dynamic>) {
+    if self.broken || !self.has_function(function, arguments.len()) {
+      return;
+    }
+
+    self.shared.borrow_mut().load(world);
+
+    let options = CallFnOptions::new().eval_ast(false).rewind_scope(false);
+    let result = self.engine.call_fn_with_options::<Dynamic>(
+      options,
+      &mut self.scope,
+      &self.ast,
+      function,
+      arguments,
+    );
+
+    self.shared.borrow_mut().store(world);
+
+    if let Err(e) = result {
+      self.broken = true;
+      let error = format!("script error in {} {}: {}", self.name, function, e);
+      eprintln!("boom: {}", error);
+      world.show_message(&error);
+      world.show_message("script stopped");
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::boom::world::test_world;
+
+  ///
+  /// Runs one line of script against the hangar, returns the world and whether it errored.
+  ///
+  fn run(line: &str) -> (World, bool) {
+    let mut world = test_world("hangar");
+    let source = format!("fn test() {{ {} }}", line);
+    let mut script = Script::compile("test", &source).unwrap();
+    script.call(&mut world, "test", vec![]);
+    let errored = world
+      .messages
+      .iter()
+      .any(|message| message.text.starts_with("script error"));
+    (world, errored)
+  }
+
+  #[test]
+  fn set_wall_changes_the_map() {
+    let (world, errored) = run("map().set_wall(1, 1, 3);");
+    assert!(!errored);
+    assert_eq!(world.map.data[1][1], 3);
+  }
+
+  #[test]
+  fn set_wall_keeps_the_edge_closed() {
+    for line in [
+      "map().set_wall(0, 0, 0);",
+      "map().set_wall(0, 0, -1);",
+      "map().set_wall(0, 0, 4294967296);",
+      "map().set_wall(1, 1, -2);",
+    ] {
+      let before = test_world("hangar").map.data;
+      let (world, errored) = run(line);
+      assert!(errored, "{} should have errored", line);
+      assert_eq!(world.map.data, before, "{} changed the map", line);
+    }
+  }
+
+  #[test]
+  fn moving_the_player_keeps_them_on_the_floor() {
+    let before = test_world("hangar").player().position;
+    for line in [
+      "let p = player(); p.x = 0.5;",
+      "let p = player(); p.y = -3.0;",
+      "let p = player(); p.x = parse_float(\"NaN\");",
+      "let p = player(); p.y = parse_float(\"inf\");",
+    ] {
+      let (world, errored) = run(line);
+      assert!(errored, "{} should have errored", line);
+      assert_eq!(world.player().position, before, "{} moved the player", line);
+    }
+    let (_, errored) = run("let p = player(); p.x = p.x;");
+    assert!(!errored);
+  }
+}
@@ -0,0 +1,445 @@
+use std::{fmt, fs, io, path::PathBuf};
+
+use glam::IVec2;
+use serde::{Deserialize, Serialize};
+
+///
+/// How big the frame the renderer draws is, compared to the window.
+///
+/// SDL stretches it up to the window afterwards.
+///
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RenderResolution {
+  /// One rendered pixel per window pixel.
+  Native,
+  /// A fraction of the window size, like 0.5 or 0.75.
+  Scale(f64),
+  /// Always this size no matter the window. 320x200 for that retro feel.
+  Fixed { width: i32, height: i32 },
+  /// The window size divided by this, so every rendered pixel is a perfect square block.
+  Integer(i32),
+}
+
+impl RenderResolution {
+  ///
+  /// The presets you can flip through in game.
+  ///
+  pub const PRESETS: [RenderResolution; 5] = [
+    RenderResolution::Native,
+    RenderResolution::Scale(0.75),
+    RenderResolution::Scale(0.5),
+    RenderResolution::Integer(2),
+    RenderResolution::Fixed {
+      width: 320,
+      height: 200,
+    },
+  ];
+
+  ///
+  /// The size of the frame to render for a window of this size.
+  ///
+  pub fn render_size(&self, window_size: IVec2) -> IVec2 {
+    let size = match *self {
+      RenderResolution::Native => window_size,
+      RenderResolution::Scale(scale) => (window_size.as_dvec2() * scale).as_ivec2(),
+      RenderResolution::Fixed { width, height } => IVec2::new(width, height),
+      RenderResolution::Integer(factor) => window_size / factor,
+    };
+    size.max(IVec2::ONE)
+  }
+
+  ///
+  /// The preset this many steps away, wrapping around. Anything custom goes back to the start.
+  ///
+  pub fn cycle_preset(&self, step: i32) -> RenderResolution {
+    let count = RenderResolution::PRESETS.len() as i32;
+    let index = RenderResolution::PRESETS
+      .iter()
+      .position(|preset| preset == self)
+      .map_or(0, |index| (index as i32 + step).rem_euclid(count));
+    RenderResolution::PRESETS[index as usize]
+  }
+
+  ///
+  /// Something short and readable for the options menu.
+  ///
+  pub fn label(&self) -> String {
+    match *self {
+      RenderResolution::Native => "native".to_string(),
+      RenderResolution::Scale(scale) => format!("{}%", (scale * 100.0).round()),
+      RenderResolution::Fixed { width, height } => format!("{}x{}", width, height),
+      RenderResolution::Integer(factor) => format!("1/{} pixels", factor),
+    }
+  }
+}
+
+///
+/// How SDL stretches the rendered frame up to the window.
+///
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum UpscaleFilter {
+  Nearest,
+  Linear,
+}
+
+impl UpscaleFilter {
+  pub fn toggled(&self) -> UpscaleFilter {
+    match self {
+      UpscaleFilter::Nearest => UpscaleFilter::Linear,
+      UpscaleFilter::Linear => UpscaleFilter::Nearest,
+    }
+  }
+
+  ///
+  /// The value SDL wants for SDL_RENDER_SCALE_QUALITY.
+  ///
+  pub fn sdl_hint(&self) -> &'static str {
+    match self {
+      UpscaleFilter::Nearest => "nearest",
+      UpscaleFilter::Linear => "linear",
+    }
+  }
+}
+
+///
+/// Everything to do with what ends up on the screen.
+///
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct VideoSettings {
+  pub window_width: i32,
+  pub window_height: i32,
+  pub target_fps: f64,
+  /// Horizontal field of view in degrees.
+  pub fov: f64,
+  pub render_resolution: RenderResolution,
+  pub upscale_filter: UpscaleFilter,
+}
+
+impl Default for VideoSettings {
+  fn default() -> Self {
+    VideoSettings {
+      window_width: 512,
+      window_height: 512,
+      target_fps: 60.0,
+      fov: 66.0,
+      render_resolution: RenderResolution::Native,
+      upscale_filter: UpscaleFilter::Nearest,
+    }
+  }
+}
+
+///
+/// Everything to do with your hands.
+///
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct InputSettings {
+  pub mouse_sensitivity: f64,
+  /// Radians per second with the right stick pushed all the way over.
+  pub gamepad_turn_speed: f64,
+  /// How far a stick has to move before it counts, 0 to 1. Old sticks drift.
+  pub gamepad_dead_zone: f64,
+}
+
+impl Default for InputSettings {
+  fn default() -> Self {
+    InputSettings {
+      mouse_sensitivity: 10.0,
+      gamepad_turn_speed: 3.0,
+      gamepad_dead_zone: 0.2,
+    }
+  }
+}
+
+///
+/// Everything to do with how the game plays.
+///
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct GameplaySettings {
+  /// Map cells per second.
+  pub move_speed: f64,
+  /// How good deathmatch bots are, from 0 to 1. Slower to react, slower to turn and
+  /// worse at aiming the lower it goes.
+  pub bot_skill: f64,
+}
+
+impl Default for GameplaySettings {
+  fn default() -> Self {
+    GameplaySettings {
+      move_speed: 5.0,
+      bot_skill: 0.5,
+    }
+  }
+}
+
+///
+/// Everything to do with your ears.
+///
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct AudioSettings {
+  /// How loud sound effects are, from 0 to 1.
+  pub sfx_volume: f64,
+  /// Same for the music.
+  pub music_volume: f64,
+}
+
+impl Default for AudioSettings {
+  fn default() -> Self {
+    AudioSettings {
+      sfx_volume: 0.8,
+      music_volume: 0.6,
+    }
+  }
+}
+
+///
+/// Something went wrong reading or writing the settings file.
+///
+#[derive(Debug)]
+pub enum SettingsError {
+  Io(PathBuf, io::Error),
+  Parse(PathBuf, toml::de::Error),
+  Serialize(toml::ser::Error),
+  Invalid(String),
+}
+
+impl fmt::Display for SettingsError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      SettingsError::Io(path, e) => write!(f, "could not access {}: {}", path.display(), e),
+      SettingsError::Parse(path, e) => write!(f, "could not parse {}: {}", path.display(), e),
+      SettingsError::Serialize(e) => write!(f, "could not serialize settings: {}", e),
+      SettingsError::Invalid(reason) => write!(f, "invalid setting: {}", reason),
+    }
+  }
+}
+
+///
+/// The settings file. Lives in the user's config directory as boom/settings.toml.
+///
+/// Anything missing from the file just falls back to the default.
+///
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct Settings {
+  pub video: VideoSettings,
+  pub input: InputSettings,
+  pub gameplay: GameplaySettings,
+  pub audio: AudioSettings,
+  /// Where this got loaded from. None means we don't write it back.
+  #[serde(skip)]
+  path: Option<PathBuf>,
+}
+
+impl Settings {
+  ///
+  /// Where the settings file should live on this machine.
+  ///
+  pub fn default_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("boom").join("settings.toml"))
+  }
+
+  ///
+  /// Loads the settings file, creating it with defaults if it doesn't exist yet.
+  ///
+  /// A broken file is reported and left alone, the game just runs on defaults.
+  ///
+  pub fn load() -> Self {
+    let Some(path) = Settings::default_path() else {
+      eprintln!("boom: no config directory found, settings will not be saved");
+      return Settings::default();
+    };
+
+    if !path.exists() {
+      let settings = Settings {
+        path: Some(path),
+        ..Default::default()
+      };
+      settings.save();
+      return settings;
+    }
+
+    match Settings::load_from(path.clone()) {
+      Ok(settings) => settings,
+      Err(e) => {
+        eprintln!("boom: {}", e);
+        eprintln!(
+          "boom: using default settings, {} will not be overwritten",
+          path.display()
+        );
+        Settings::default()
+      }
+    }
+  }
+
+  ///
+  /// Loads and validates a specific settings file.
+  ///
+  pub fn load_from(path: PathBuf) -> Result<Self, SettingsError> {
+    let raw = fs::read_to_string(&path).map_err(|e| SettingsError::Io(path.clone(), e))?;
+    let mut settings: Settings =
+      toml::from_str(&raw).map_err(|e| SettingsError::Parse(path.clone(), e))?;
+    settings.validate()?;
+    settings.path = Some(path);
+    Ok(settings)
+  }
+
+  ///
+  /// Makes sure nothing in here is going to make the game explode.
+  ///
+  pub fn validate(&self) -> Result<(), SettingsError> {
+    let check = |ok: bool, reason: String| {
+      if ok {
+        Ok(())
+      } else {
+        Err(SettingsError::Invalid(reason))
+      }
+    };
+
+    let video = &self.video;
+    check(
+      (64..=16384).contains(&video.window_width),
+      format!(
+        "video.window_width must be between 64 and 16384, got {}",
+        video.window_width
+      ),
+    )?;
+    check(
+      (64..=16384).contains(&video.window_height),
+      format!(
+        "video.window_height must be between 64 and 16384, got {}",
+        video.window_height
+      ),
+    )?;
+    check(
+      (1.0..=1000.0).contains(&video.target_fps),
+      format!(
+        "video.target_fps must be between 1 and 1000, got {}",
+        video.target_fps
+      ),
+    )?;
+    check(
+      (30.0..=150.0).contains(&video.fov),
+      format!(
+        "video.fov must be between 30 and 150 degrees, got {}",
+        video.fov
+      ),
+    )?;
+    match video.render_resolution {
+      RenderResolution::Native => (),
+      RenderResolution::Scale(scale) => check(
+        scale > 0.0 && scale <= 1.0,
+        format!(
+          "video.render_resolution scale must be above 0 and at most 1, got {}",
+          scale
+        ),
+      )?,
+      RenderResolution::Fixed { width, height } => check(
+        (16..=4096).contains(&width) && (16..=4096).contains(&height),
+        format!(
+          "video.render_resolution fixed size must be between 16 and 4096, got {}x{}",
+          width, height
+        ),
+      )?,
+      RenderResolution::Integer(factor) => check(
+        (1..=16).contains(&factor),
+        format!(
+          "video.render_resolution integer factor must be between 1 and 16, got {}",
+          factor
+        ),
+      )?,
+    }
+
+    let sensitivity = self.input.mouse_sensitivity;
+    check(
+      sensitivity > 0.0 && sensitivity <= 100.0,
+      format!(
+        "input.mouse_sensitivity must be above 0 and at most 100, got {}",
+        sensitivity
+      ),
+    )?;
+
+    let turn_speed = self.input.gamepad_turn_speed;
+    check(
+      turn_speed > 0.0 && turn_speed <= 20.0,
+      format!(
+        "input.gamepad_turn_speed must be above 0 and at most 20, got {}",
+        turn_speed
+      ),
+    )?;
+
+    let dead_zone = self.input.gamepad_dead_zone;
+    check(
+      (0.0..1.0).contains(&dead_zone),
+      format!(
+        "input.gamepad_dead_zone must be at least 0 and below 1, got {}",
+        dead_zone
+      ),
+    )?;
+
+    let move_speed = self.gameplay.move_speed;
+    check(
+      move_speed > 0.0 && move_speed <= 50.0,
+      format!(
+        "gameplay.move_speed must be above 0 and at most 50, got {}",
+        move_speed
+      ),
+    )?;
+    let bot_skill = self.gameplay.bot_skill;
+    check(
+      (0.0..=1.0).contains(&bot_skill),
+      format!(
+        "gameplay.bot_skill must be between 0 and 1, got {}",
+        bot_skill
+      ),
+    )?;
+
+    let sfx_volume = self.audio.sfx_volume;
+    check(
+      (0.0..=1.0).contains(&sfx_volume),
+      format!(
+        "audio.sfx_volume must be between 0 and 1, got {}",
+        sfx_volume
+      ),
+    )?;
+    let music_volume = self.audio.music_volume;
+    check(
+      (0.0..=1.0).contains(&music_volume),
+      format!(
+        "audio.music_volume must be between 0 and 1, got {}",
+        music_volume
+      ),
+    )?;
+
+    Ok(())
+  }
+
+  ///
+  /// Writes the settings back to where they came from.
+  ///
+  /// Failing to save isn't worth crashing over, so it just complains.
+  ///
+  pub fn save(&self) {
+    if let Err(e) = self.try_save() {
+      eprintln!("boom: {}", e);
+    }
+  }
+
+  fn try_save(&self) -> Result<(), SettingsError> {
+    let Some(path) = &self.path else {
+      return Ok(());
+    };
+
+    if let Some(dir) = path.parent() {
+      fs::create_dir_all(dir).map_err(|e| SettingsError::Io(dir.to_path_buf(), e))?;
+    }
+
+    let raw = toml::to_string_pretty(self).map_err(SettingsError::Serialize)?;
+    fs::write(path, raw).map_err(|e| SettingsError::Io(path.clone(), e))
+  }
+}
@@ -0,0 +1,50 @@
+use std::path::{Path, PathBuf};
+
+use glam::DVec2;
+
+///
+/// Every sound in the sounds folder. The position in here is the sound's id.
+///
+/// Each one is loaded from sounds/<name>.wav, or sounds/<name>.ogg if there's no wav.
+///
+pub const SOUND_NAMES: [&str; 7] = [
+  "door", "launcher", "mob_die", "mob_hurt", "pickup", "pistol", "shotgun",
+];
+
+///
+/// Where music files live, next to the sounds folder.
+///
+pub const MUSIC_DIR: &str = "music";
+
+///
+/// Gets the id of a sound from its name. Panics if it doesn't exist because that's a typo.
+///
+pub fn sound_id(name: &str) -> usize {
+  find_sound(name).unwrap_or_else(|| panic!("sound {} does not exist", name))
+}
+
+///
+/// Same as sound_id, but for names that come from files and might be wrong.
+///
+pub fn find_sound(name: &str) -> Option<usize> {
+  SOUND_NAMES
+    .iter()
+    .position(|sound_name| *sound_name == name)
+}
+
+///
+/// Something in the world made a noise. World collects these during a tick and
+/// Audio turns them into voices.
+///
+#[derive(Clone, Copy, Debug)]
+pub struct SoundEvent {
+  pub sound: usize,
+  pub position: DVec2,
+}
+
+///
+/// Where a level's music file is, from the name in the level file.
+///
+pub fn music_path(name: &str) -> PathBuf {
+  Path::new(MUSIC_DIR).join(name)
+}
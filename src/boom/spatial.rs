@@ -0,0 +1,423 @@
+use std::collections::{HashMap, VecDeque};
+
+use glam::{DVec2, IVec2};
+
+use super::entity::EntityId;
+
+// A ray's 3x3 neighbourhoods stop overlapping after 4 steps, so that's 36 cells.
+const RAY_MEMORY: usize = 36;
+
+///
+/// Buckets entities by the map cell they're standing in, so "what's near here" only looks
+/// at a few cells instead of every entity in the level.
+///
+/// Cells line up with the Map's, one bucket per map cell. Anything off the map goes in one
+/// extra bucket at the end that every query looks through, there shouldn't be much in it.
+/// Queries assume nothing is fatter than a cell, so they only ever look one cell further
+/// out than they need to.
+///
+pub struct SpatialGrid {
+  size: IVec2,
+  cells: Vec<Vec<(EntityId, DVec2)>>,
+  /// Which cell each entity is in, so it can be found again when it moves.
+  placed: HashMap<EntityId, usize>,
+}
+
+impl Default for SpatialGrid {
+  fn default() -> Self {
+    SpatialGrid::new(IVec2::ONE)
+  }
+}
+
+impl SpatialGrid {
+  pub fn new(size: IVec2) -> Self {
+    let size = size.max(IVec2::ONE);
+    SpatialGrid {
+      size,
+      cells: vec![Vec::new(); (size.x * size.y) as usize + 1],
+      placed: HashMap::new(),
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    self.placed.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.placed.is_empty()
+  }
+
+  fn cell_of(&self, position: DVec2) -> IVec2 {
+    position
+      .floor()
+      .as_ivec2()
+      .clamp(IVec2::ZERO, self.size - IVec2::ONE)
+  }
+
+  fn index(&self, cell: IVec2) -> usize {
+    (cell.x * self.size.y + cell.y) as usize
+  }
+
+  /// The bucket for everything off the grid.
+  fn outside(&self) -> &[(EntityId, DVec2)] {
+    &self.cells[self.cells.len() - 1]
+  }
+
+  fn bucket_of(&self, position: DVec2) -> usize {
+    let cell = position.floor().as_ivec2();
+    if cell.cmplt(IVec2::ZERO).any() || cell.cmpge(self.size).any() {
+      self.cells.len() - 1
+    } else {
+      self.index(cell)
+    }
+  }
+
+  ///
+  /// Puts an entity in the grid, or moves it if it's already there.
+  ///
+  pub fn insert(&mut self, id: EntityId, position: DVec2) {
+    let index = self.bucket_of(position);
+
+    match self.placed.get(&id).copied() {
+      Some(old) if old == index => {
+        if let Some(entry) = self.cells[index].iter_mut().find(|entry| entry.0 == id) {
+          entry.1 = position;
+        }
+        return;
+      }
+      Some(old) => self.cells[old].retain(|entry| entry.0 != id),
+      None => (),
+    }
+
+    self.cells[index].push((id, position));
+    self.placed.insert(id, index);
+  }
+
+  pub fn remove(&mut self, id: EntityId) {
+    if let Some(index) = self.placed.remove(&id) {
+      self.cells[index].retain(|entry| entry.0 != id);
+    }
+  }
+
+  ///
+  /// Everything within radius of center, in no particular order.
+  ///
+  pub fn query_radius(&self, center: DVec2, radius: f64) -> Vec<(EntityId, DVec2)> {
+    let min = self.cell_of(center - radius);
+    let max = self.cell_of(center + radius);
+    let mut found = Vec::new();
+    let mut look_in = |bucket: &[(EntityId, DVec2)]| {
+      for (id, position) in bucket {
+        if position.distance_squared(center) <= radius * radius {
+          found.push((*id, *position));
+        }
+      }
+    };
+
+    look_in(self.outside());
+    for x in min.x..=max.x {
+      for y in min.y..=max.y {
+        look_in(&self.cells[self.index(IVec2::new(x, y))]);
+      }
+    }
+    found
+  }
+
+  ///
+  /// Walks a ray through the grid and returns the closest thing it hits, and how far away.
+  ///
+  /// hit gets an entity and its position and says how far along the ray it gets hit, if it
+  /// does. That's where the shape of the thing comes in, the grid only knows points.
+  ///
+  pub fn query_ray(
+    &self,
+    from: DVec2,
+    direction: DVec2,
+    max_distance: f64,
+    hit: impl Fn(EntityId, DVec2) -> Option<f64>,
+  ) -> Option<(EntityId, f64)> {
+    // A ray that goes nowhere would never get any further along.
+    if !from.is_finite() || !direction.is_finite() || direction == DVec2::ZERO {
+      return None;
+    }
+
+    let mut cell = from.floor().as_ivec2();
+    let step = direction.signum().as_ivec2();
+    let delta = (DVec2::ONE / direction).abs();
+    let mut side_distance = DVec2::new(
+      if direction.x < 0.0 {
+        (from.x - cell.x as f64) * delta.x
+      } else {
+        (cell.x as f64 + 1.0 - from.x) * delta.x
+      },
+      if direction.y < 0.0 {
+        (from.y - cell.y as f64) * delta.y
+      } else {
+        (cell.y as f64 + 1.0 - from.y) * delta.y
+      },
+    );
+
+    let mut best: Option<(EntityId, f64)> = None;
+    let check = |bucket: &[(EntityId, DVec2)], best: &mut Option<(EntityId, f64)>| {
+      for (id, position) in bucket {
+        if let Some(along) = hit(*id, *position).filter(|along| *along <= max_distance) {
+          if best.map_or(true, |(_, closest)| along < closest) {
+            *best = Some((*id, along));
+          }
+        }
+      }
+    };
+    check(self.outside(), &mut best);
+
+    // Neighbourhoods overlap for a few steps, this remembers enough of them to not look twice.
+    let mut checked = VecDeque::with_capacity(RAY_MEMORY);
+    let mut distance = 0.0;
+
+    loop {
+      // Something fat in the next cell over can still stick into the ray, so look around it.
+      for x in cell.x - 1..=cell.x + 1 {
+        for y in cell.y - 1..=cell.y + 1 {
+          let neighbour = IVec2::new(x, y);
+          if neighbour.cmplt(IVec2::ZERO).any() || neighbour.cmpge(self.size).any() {
+            continue;
+          }
+          let index = self.index(neighbour);
+          if checked.contains(&index) {
+            continue;
+          }
+          if checked.len() == RAY_MEMORY {
+            checked.pop_front();
+          }
+          checked.push_back(index);
+          check(&self.cells[index], &mut best);
+        }
+      }
+
+      // Nothing further along can beat what's already been hit. A ray that starts off the
+      // grid keeps going as long as it's heading back onto it.
+      let done = best.is_some_and(|(_, closest)| closest < distance - 3.0);
+      let leaving = (cell.x < -1 && step.x <= 0)
+        || (cell.y < -1 && step.y <= 0)
+        || (cell.x > self.size.x && step.x >= 0)
+        || (cell.y > self.size.y && step.y >= 0);
+      if done || distance > max_distance + 1.0 || leaving {
+        return best;
+      }
+
+      distance = if side_distance.x < side_distance.y {
+        cell.x += step.x;
+        side_distance.x += delta.x;
+        side_distance.x - delta.x
+      } else {
+        cell.y += step.y;
+        side_distance.y += delta.y;
+        side_distance.y - delta.y
+      };
+    }
+  }
+
+  ///
+  /// The closest entity that passes the filter, within max_distance.
+  ///
+  /// Looks in rings of cells around the start and stops once the next ring can't have
+  /// anything closer than what's already been found.
+  ///
+  pub fn nearest(
+    &self,
+    position: DVec2,
+    max_distance: f64,
+    filter: impl Fn(EntityId) -> bool,
+  ) -> Option<(EntityId, f64)> {
+    let center = self.cell_of(position);
+    let max_ring = (max_distance.ceil() as i32 + 1).min(self.size.x.max(self.size.y));
+    let mut best: Option<(EntityId, f64)> = None;
+    let look_in = |bucket: &[(EntityId, DVec2)], best: &mut Option<(EntityId, f64)>| {
+      for (id, other) in bucket {
+        let distance = position.distance(*other);
+        if distance > max_distance || !filter(*id) {
+          continue;
+        }
+        if best.map_or(true, |(_, closest)| distance < closest) {
+          *best = Some((*id, distance));
+        }
+      }
+    };
+    look_in(self.outside(), &mut best);
+
+    for ring in 0..=max_ring {
+      // Everything in this ring is at least ring - 1 cells away.
+      if best.is_some_and(|(_, closest)| closest < (ring - 1) as f64) {
+        break;
+      }
+
+      for x in center.x - ring..=center.x + ring {
+        for y in center.y - ring..=center.y + ring {
+          let on_ring = (x - center.x).abs() == ring || (y - center.y).abs() == ring;
+          let cell = IVec2::new(x, y);
+          if !on_ring || cell.cmplt(IVec2::ZERO).any() || cell.cmpge(self.size).any() {
+            continue;
+          }
+          look_in(&self.cells[self.index(cell)], &mut best);
+        }
+      }
+    }
+    best
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use rand::{rngs::StdRng, Rng, SeedableRng};
+
+  use super::*;
+
+  const SIZE: f64 = 32.0;
+
+  ///
+  /// A grid and the same entities in a plain list to check it against. Some of them are
+  /// off the grid, which shouldn't happen in game but mustn't lose them either.
+  ///
+  fn scatter(rng: &mut StdRng, count: u32) -> (SpatialGrid, Vec<(EntityId, DVec2)>) {
+    let mut grid = SpatialGrid::new(IVec2::splat(SIZE as i32));
+    let entities: Vec<(EntityId, DVec2)> = (0..count)
+      .map(|index| {
+        let id = EntityId {
+          index,
+          generation: 0,
+        };
+        let position = DVec2::new(
+          rng.gen_range(-6.0..SIZE + 6.0),
+          rng.gen_range(-6.0..SIZE + 6.0),
+        );
+        (id, position)
+      })
+      .collect();
+    for (id, position) in &entities {
+      grid.insert(*id, *position);
+    }
+    (grid, entities)
+  }
+
+  fn random_point(rng: &mut StdRng) -> DVec2 {
+    DVec2::new(
+      rng.gen_range(-4.0..SIZE + 4.0),
+      rng.gen_range(-4.0..SIZE + 4.0),
+    )
+  }
+
+  /// A circle 0.3 across around every entity, like the bullets use.
+  fn hit(from: DVec2, direction: DVec2, position: DVec2) -> Option<f64> {
+    let along = (position - from).dot(direction);
+    let miss = (position - from).length_squared() - along * along;
+    (along >= 0.0 && miss <= 0.09).then_some(along)
+  }
+
+  #[test]
+  fn radius_matches_brute_force() {
+    let mut rng = StdRng::seed_from_u64(1);
+    let (grid, entities) = scatter(&mut rng, 600);
+
+    for _ in 0..500 {
+      let center = random_point(&mut rng);
+      let radius = rng.gen_range(0.0..8.0);
+      let mut found: Vec<EntityId> = grid
+        .query_radius(center, radius)
+        .into_iter()
+        .map(|(id, _)| id)
+        .collect();
+      let mut expected: Vec<EntityId> = entities
+        .iter()
+        .filter(|(_, position)| position.distance_squared(center) <= radius * radius)
+        .map(|(id, _)| *id)
+        .collect();
+      found.sort_by_key(|id| id.index);
+      expected.sort_by_key(|id| id.index);
+      assert_eq!(found, expected, "around {} by {}", center, radius);
+    }
+  }
+
+  #[test]
+  fn ray_matches_brute_force() {
+    let mut rng = StdRng::seed_from_u64(2);
+    let (grid, entities) = scatter(&mut rng, 600);
+
+    for _ in 0..500 {
+      let from = random_point(&mut rng);
+      let direction = DVec2::from_angle(rng.gen_range(0.0..std::f64::consts::TAU));
+      let max_distance = rng.gen_range(0.0..48.0);
+      let found = grid.query_ray(from, direction, max_distance, |_, position| {
+        hit(from, direction, position)
+      });
+      let expected = entities
+        .iter()
+        .filter_map(|(_, position)| hit(from, direction, *position))
+        .filter(|along| *along <= max_distance)
+        .min_by(|a, b| a.total_cmp(b));
+      assert_eq!(
+        found.map(|(_, along)| along),
+        expected,
+        "from {} towards {}",
+        from,
+        direction
+      );
+    }
+  }
+
+  #[test]
+  fn nearest_matches_brute_force() {
+    let mut rng = StdRng::seed_from_u64(3);
+    let (grid, entities) = scatter(&mut rng, 300);
+
+    for _ in 0..500 {
+      let position = random_point(&mut rng);
+      let max_distance = rng.gen_range(0.0..20.0);
+      // Odd ones only, so the filter gets a say too.
+      let found = grid.nearest(position, max_distance, |id| id.index % 2 == 1);
+      let expected = entities
+        .iter()
+        .filter(|(id, _)| id.index % 2 == 1)
+        .map(|(_, other)| position.distance(*other))
+        .filter(|distance| *distance <= max_distance)
+        .min_by(|a, b| a.total_cmp(b));
+      assert_eq!(
+        found.map(|(_, distance)| distance),
+        expected,
+        "around {} within {}",
+        position,
+        max_distance
+      );
+    }
+  }
+
+  #[test]
+  fn moving_and_removing_keeps_one_copy() {
+    let mut grid = SpatialGrid::new(IVec2::splat(8));
+    let id = EntityId {
+      index: 0,
+      generation: 0,
+    };
+    for position in [
+      DVec2::new(1.5, 1.5),
+      DVec2::new(1.7, 1.2),
+      DVec2::new(-3.0, 4.0),
+      DVec2::new(6.5, 2.5),
+    ] {
+      grid.insert(id, position);
+      assert_eq!(grid.len(), 1);
+      assert_eq!(grid.query_radius(position, 0.1), vec![(id, position)]);
+    }
+    grid.remove(id);
+    assert!(grid.is_empty());
+    assert!(grid.query_radius(DVec2::new(6.5, 2.5), 20.0).is_empty());
+  }
+
+  #[test]
+  fn rays_that_go_nowhere_stop() {
+    let grid = SpatialGrid::new(IVec2::splat(8));
+    let anything = |_, _| Some(0.0);
+    let from = DVec2::new(2.5, 2.5);
+    assert_eq!(grid.query_ray(from, DVec2::ZERO, 10.0, anything), None);
+    assert_eq!(grid.query_ray(from, DVec2::NAN, 10.0, anything), None);
+    assert_eq!(grid.query_ray(DVec2::NAN, DVec2::X, 10.0, anything), None);
+  }
+}
@@ -0,0 +1,126 @@
+use std::fs::File;
+
+///
+/// Every texture in the textures folder. The position in here is the texture's id.
+///
+pub const TEXTURE_NAMES: [&str; 30] = [
+  "bluebrick",
+  "bone",
+  "brick",
+  "bulletHole",
+  "cobble",
+  "colorcobble",
+  "dm",
+  "dm_dead",
+  "item_ammo",
+  "item_health",
+  "item_launcher",
+  "item_shells",
+  "item_shotgun",
+  "key_blue",
+  "key_red",
+  "key_yellow",
+  "launcher",
+  "launcher_fire",
+  "lava",
+  "light",
+  "mossy",
+  "oerkki",
+  "oerkki_dead",
+  "pillar",
+  "pistol",
+  "pistol_fire",
+  "purple",
+  "shotgun",
+  "shotgun_fire",
+  "wood",
+];
+
+///
+/// Gets the id of a texture from its name. Panics if it doesn't exist because that's a typo.
+///
+pub fn texture_id(name: &str) -> usize {
+  find_texture(name).unwrap_or_else(|| panic!("texture {} does not exist", name))
+}
+
+///
+/// Same as texture_id, but for names that come from files and might be wrong.
+///
+pub fn find_texture(name: &str) -> Option<usize> {
+  TEXTURE_NAMES
+    .iter()
+    .position(|texture_name| *texture_name == name)
+}
+
+///
+/// Raw RGBA pixels of a texture, in rows.
+///
+pub struct TextureData {
+  pub width: usize,
+  pub height: usize,
+  pub pixels: Vec<u8>,
+}
+
+impl TextureData {
+  ///
+  /// Loads a png and squishes whatever format it's in into RGBA.
+  ///
+  pub fn load(path: &str) -> Self {
+    let file = File::open(path).unwrap_or_else(|e| panic!("could not open {}: {}", path, e));
+
+    let mut decoder = png::Decoder::new(file);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder
+      .read_info()
+      .unwrap_or_else(|e| panic!("could not read {}: {}", path, e));
+
+    let mut raw = vec![0; reader.output_buffer_size()];
+    let info = reader
+      .next_frame(&mut raw)
+      .unwrap_or_else(|e| panic!("could not decode {}: {}", path, e));
+    let raw = &raw[..info.buffer_size()];
+
+    let pixels = match info.color_type {
+      png::ColorType::Rgba => raw.to_vec(),
+      png::ColorType::Rgb => raw
+        .chunks_exact(3)
+        .flat_map(|p| [p[0], p[1], p[2], 255])
+        .collect(),
+      png::ColorType::GrayscaleAlpha => raw
+        .chunks_exact(2)
+        .flat_map(|p| [p[0], p[0], p[0], p[1]])
+        .collect(),
+      png::ColorType::Grayscale => raw.iter().flat_map(|p| [*p, *p, *p, 255]).collect(),
+      png::ColorType::Indexed => panic!("{} is still indexed after expanding", path),
+    };
+
+    TextureData {
+      width: info.width as usize,
+      height: info.height as usize,
+      pixels,
+    }
+  }
+
+  ///
+  /// Grabs one RGBA pixel. Wraps around, so you can't fall off the edge.
+  ///
+  pub fn pixel(&self, x: usize, y: usize) -> [u8; 4] {
+    let index = ((y % self.height) * self.width + (x % self.width)) * 4;
+    [
+      self.pixels[index],
+      self.pixels[index + 1],
+      self.pixels[index + 2],
+      self.pixels[index + 3],
+    ]
+  }
+}
+
+///
+/// Loads everything in TEXTURE_NAMES, in order.
+///
+pub fn load_textures() -> Vec<TextureData> {
+  TEXTURE_NAMES
+    .iter()
+    .map(|name| TextureData::load(format!("textures/{}.png", name).as_str()))
+    .collect()
+}
@@ -0,0 +1,141 @@
+use glam::IVec2;
+use serde::{Deserialize, Serialize};
+
+///
+/// What makes a trigger go off.
+///
+/// In a level file these look like `when = { enter_cell = [3, 4] }` or `when = "all_mobs_dead"`.
+///
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Condition {
+  /// The player walks into this cell.
+  EnterCell(IVec2),
+  /// The player presses use while looking at this wall, close enough to touch it.
+  UseWall(IVec2),
+  /// Every mob in the level is dead.
+  AllMobsDead,
+  /// This many seconds into the level.
+  Timer(f64),
+}
+
+///
+/// What a trigger does once it goes off.
+///
+/// In a level file these look like `{ open_door = [3, 4] }` or just `"end_level"`.
+///
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+  /// Clears the wall out of a cell so it can be walked through.
+  OpenDoor(IVec2),
+  /// Puts a new mob in the middle of a cell.
+  SpawnMob(IVec2),
+  /// Swaps the wall in a cell for another one. 0 removes it, like opening a door.
+  ChangeWall { cell: IVec2, wall: i32 },
+  /// Pops some text up on the HUD.
+  ShowMessage(String),
+  /// Same as walking onto an exit.
+  EndLevel,
+  /// Calls a function with no arguments in the level's script.
+  RunScript(String),
+}
+
+fn default_once() -> bool {
+  true
+}
+
+///
+/// A condition and the actions it sets off, placed on the map by a level designer.
+///
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Trigger {
+  pub when: Condition,
+  pub actions: Vec<Action>,
+  /// Only go off the first time. Triggers that repeat go off every time the condition
+  /// becomes true again, not every tick it stays true.
+  #[serde(default = "default_once")]
+  pub once: bool,
+  /// Has this gone off yet. Save games keep track of this.
+  #[serde(skip)]
+  pub fired: bool,
+  /// Whether the condition held last tick.
+  #[serde(skip)]
+  pub active: bool,
+}
+
+impl Trigger {
+  ///
+  /// Every cell this trigger looks at or changes, so the level can check they're on the map.
+  ///
+  pub fn cells(&self) -> Vec<IVec2> {
+    let mut cells = match self.when {
+      Condition::EnterCell(cell) | Condition::UseWall(cell) => vec![cell],
+      Condition::AllMobsDead | Condition::Timer(_) => vec![],
+    };
+    for action in &self.actions {
+      match action {
+        Action::OpenDoor(cell) | Action::SpawnMob(cell) => cells.push(*cell),
+        Action::ChangeWall { cell, .. } => cells.push(*cell),
+        Action::ShowMessage(_) | Action::EndLevel | Action::RunScript(_) => (),
+      }
+    }
+    cells
+  }
+
+  ///
+  /// Cells this trigger can turn into anything but a wall. These can't be on the edge of
+  /// the map.
+  ///
+  pub fn opened_cells(&self) -> Vec<IVec2> {
+    self
+      .actions
+      .iter()
+      .filter_map(|action| match action {
+        Action::OpenDoor(cell) => Some(*cell),
+        Action::ChangeWall { cell, wall } if *wall <= 0 => Some(*cell),
+        _ => None,
+      })
+      .collect()
+  }
+
+  ///
+  /// Feeds in whether the condition holds this tick. Returns true when the actions should run.
+  ///
+  pub fn update(&mut self, holds: bool) -> bool {
+    let rising = holds && !self.active;
+    self.active = holds;
+
+    if !rising || (self.once && self.fired) {
+      return false;
+    }
+    self.fired = true;
+    true
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn anything_but_a_wall_counts_as_opened() {
+    let trigger: Trigger = toml::from_str(
+      r#"
+      when = "all_mobs_dead"
+      actions = [
+        { open_door = [1, 2] },
+        { change_wall = { cell = [3, 4], wall = 0 } },
+        { change_wall = { cell = [5, 6], wall = -1 } },
+        { change_wall = { cell = [7, 8], wall = 3 } },
+      ]
+      "#,
+    )
+    .unwrap();
+
+    assert_eq!(
+      trigger.opened_cells(),
+      vec![IVec2::new(1, 2), IVec2::new(3, 4), IVec2::new(5, 6)]
+    );
+  }
+}
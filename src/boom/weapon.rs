@@ -0,0 +1,333 @@
+use std::{
+  collections::HashMap,
+  fmt, fs, io,
+  path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{sound::find_sound, texture::find_texture};
+
+///
+/// What a weapon eats.
+///
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum AmmoType {
+  Bullets,
+  Shells,
+}
+
+impl AmmoType {
+  pub const ALL: [AmmoType; 2] = [AmmoType::Bullets, AmmoType::Shells];
+
+  pub fn name(&self) -> &'static str {
+    match self {
+      AmmoType::Bullets => "bullets",
+      AmmoType::Shells => "shells",
+    }
+  }
+
+  ///
+  /// The most of this the player can carry.
+  ///
+  pub fn max(&self) -> i32 {
+    match self {
+      AmmoType::Bullets => 200,
+      AmmoType::Shells => 50,
+    }
+  }
+}
+
+fn default_pellets() -> u32 {
+  1
+}
+
+fn default_ammo_per_shot() -> i32 {
+  1
+}
+
+fn default_noise() -> u32 {
+  16
+}
+
+///
+/// One weapon out of weapons.toml. See the top of that file for what everything means.
+///
+#[derive(Deserialize, Clone, Debug)]
+pub struct WeaponDef {
+  pub name: String,
+  /// The number key that picks it.
+  pub slot: u32,
+  pub fire_rate: f64,
+  #[serde(default)]
+  pub spread: f64,
+  #[serde(default = "default_pellets")]
+  pub pellets: u32,
+  pub damage: i32,
+  /// None is hitscan.
+  #[serde(default)]
+  pub projectile_speed: Option<f64>,
+  pub ammo: AmmoType,
+  #[serde(default = "default_ammo_per_shot")]
+  pub ammo_per_shot: i32,
+  /// How much ammo comes with it when you pick it up.
+  #[serde(default)]
+  pub pickup_ammo: i32,
+  pub frames: Vec<String>,
+  pub frame_time: f64,
+  /// The frames turned into texture ids once the file is loaded.
+  #[serde(skip)]
+  pub frame_textures: Vec<usize>,
+  /// Played on every shot.
+  #[serde(default)]
+  pub sound: Option<String>,
+  /// How many cells away mobs can hear it. Sound goes around walls, not through them.
+  #[serde(default = "default_noise")]
+  pub noise: u32,
+  #[serde(skip)]
+  pub sound_id: Option<usize>,
+}
+
+impl WeaponDef {
+  ///
+  /// Which texture to show, given how long ago the last shot was.
+  ///
+  pub fn frame(&self, since_shot: f64) -> usize {
+    let firing_frames = self.frame_textures.len() - 1;
+    let frame = (since_shot / self.frame_time) as usize;
+    if frame < firing_frames {
+      self.frame_textures[frame + 1]
+    } else {
+      self.frame_textures[0]
+    }
+  }
+}
+
+///
+/// Network snapshots pack which weapons a player has into 32 bits, one per weapon.
+///
+pub const MAX_WEAPONS: usize = 32;
+// Every pellet is a bullet of its own, so a shot can't be allowed to be too many of them.
+const MAX_PELLETS: u32 = 64;
+
+///
+/// Something went wrong reading weapons.toml.
+///
+#[derive(Debug)]
+pub enum WeaponError {
+  Io(PathBuf, io::Error),
+  Parse(PathBuf, toml::de::Error),
+  Invalid(String),
+}
+
+impl fmt::Display for WeaponError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      WeaponError::Io(path, e) => write!(f, "could not access {}: {}", path.display(), e),
+      WeaponError::Parse(path, e) => write!(f, "could not parse {}: {}", path.display(), e),
+      WeaponError::Invalid(reason) => write!(f, "invalid weapon: {}", reason),
+    }
+  }
+}
+
+///
+/// Every weapon definition, by id.
+///
+#[derive(Clone, Debug, Default)]
+pub struct Weapons {
+  pub defs: HashMap<String, WeaponDef>,
+}
+
+impl Weapons {
+  pub fn default_path() -> PathBuf {
+    PathBuf::from("weapons.toml")
+  }
+
+  pub fn load(path: &Path) -> Result<Self, WeaponError> {
+    let raw = fs::read_to_string(path).map_err(|e| WeaponError::Io(path.to_path_buf(), e))?;
+    Weapons::parse(&raw, path)
+  }
+
+  ///
+  /// Reads and checks weapon definitions. The path is only for errors.
+  ///
+  pub fn parse(raw: &str, path: &Path) -> Result<Self, WeaponError> {
+    let mut defs: HashMap<String, WeaponDef> =
+      toml::from_str(raw).map_err(|e| WeaponError::Parse(path.to_path_buf(), e))?;
+
+    if defs.len() > MAX_WEAPONS {
+      return Err(WeaponError::Invalid(format!(
+        "there can't be more than {} weapons, there are {}",
+        MAX_WEAPONS,
+        defs.len()
+      )));
+    }
+
+    for (id, def) in defs.iter_mut() {
+      let invalid = |reason: &str| WeaponError::Invalid(format!("{}: {}", id, reason));
+
+      if !(1..=9).contains(&def.slot) {
+        return Err(invalid("slot must be from 1 to 9, one for each number key"));
+      }
+      if !(def.fire_rate > 0.0 && def.fire_rate.is_finite()) {
+        return Err(invalid("fire_rate must be above 0"));
+      }
+      if !(def.spread >= 0.0 && def.spread.is_finite()) {
+        return Err(invalid("spread must be 0 or more"));
+      }
+      if !(1..=MAX_PELLETS).contains(&def.pellets) {
+        return Err(invalid(&format!(
+          "pellets must be from 1 to {}",
+          MAX_PELLETS
+        )));
+      }
+      if def.ammo_per_shot < 0 {
+        return Err(invalid("ammo_per_shot can't be negative"));
+      }
+      if def
+        .projectile_speed
+        .is_some_and(|speed| !(speed > 0.0 && speed.is_finite()))
+      {
+        return Err(invalid(
+          "projectile_speed must be above 0, leave it out for hitscan",
+        ));
+      }
+      if !(def.frame_time > 0.0 && def.frame_time.is_finite()) {
+        return Err(invalid("frame_time must be above 0"));
+      }
+      if def.frames.is_empty() {
+        return Err(invalid("needs at least one frame"));
+      }
+
+      def.frame_textures = def
+        .frames
+        .iter()
+        .map(|frame| {
+          find_texture(frame).ok_or_else(|| invalid(&format!("no texture called {}", frame)))
+        })
+        .collect::<Result<Vec<usize>, WeaponError>>()?;
+
+      def.sound_id = def
+        .sound
+        .as_ref()
+        .map(|sound| {
+          find_sound(sound).ok_or_else(|| invalid(&format!("no sound called {}", sound)))
+        })
+        .transpose()?;
+    }
+
+    Ok(Weapons { defs })
+  }
+
+  pub fn get(&self, id: &str) -> Option<&WeaponDef> {
+    self.defs.get(id)
+  }
+
+  ///
+  /// The weapons out of these ids that actually exist, sorted by slot then id.
+  ///
+  pub fn sorted<'a>(&self, ids: &'a [String]) -> Vec<&'a String> {
+    let mut sorted: Vec<&String> = ids
+      .iter()
+      .filter(|id| self.defs.contains_key(*id))
+      .collect();
+    sorted.sort_by_key(|id| (self.defs[*id].slot, *id));
+    sorted
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn weapon(id: &str, slot: u32, spread: &str) -> String {
+    format!(
+      r#"
+[{}]
+name = "Test"
+slot = {}
+fire_rate = 2.0
+spread = {}
+damage = 10
+ammo = "bullets"
+frames = ["pistol", "pistol_fire"]
+frame_time = 0.1
+"#,
+      id, slot, spread
+    )
+  }
+
+  fn parse(raw: &str) -> Result<Weapons, WeaponError> {
+    Weapons::parse(raw, Path::new("test.toml"))
+  }
+
+  #[test]
+  fn shipped_weapons_load() {
+    Weapons::load(&Weapons::default_path()).unwrap();
+  }
+
+  #[test]
+  fn good_weapon_parses() {
+    let weapons = parse(&weapon("test", 2, "1.5")).unwrap();
+    assert_eq!(weapons.defs["test"].slot, 2);
+  }
+
+  #[test]
+  fn bad_spread_is_invalid() {
+    for spread in ["-1.0", "nan", "inf"] {
+      assert!(
+        matches!(
+          parse(&weapon("test", 2, spread)),
+          Err(WeaponError::Invalid(_))
+        ),
+        "spread {} got through",
+        spread
+      );
+    }
+  }
+
+  #[test]
+  fn bad_numbers_are_invalid() {
+    let good = weapon("test", 2, "0.0");
+    let mut bad = Vec::new();
+    for value in ["0.0", "-1.0", "nan", "inf"] {
+      bad.push(good.replace("fire_rate = 2.0", &format!("fire_rate = {}", value)));
+      bad.push(good.replace("frame_time = 0.1", &format!("frame_time = {}", value)));
+      bad.push(format!("{}projectile_speed = {}\n", good, value));
+    }
+    for pellets in ["0", "65", "4294967295"] {
+      bad.push(format!("{}pellets = {}\n", good, pellets));
+    }
+
+    for raw in bad {
+      assert!(
+        matches!(parse(&raw), Err(WeaponError::Invalid(_))),
+        "{} got through",
+        raw
+      );
+    }
+    assert!(parse(&format!("{}pellets = 64\nprojectile_speed = 20.0\n", good)).is_ok());
+  }
+
+  #[test]
+  fn slot_must_be_a_number_key() {
+    for slot in [0, 10] {
+      assert!(matches!(
+        parse(&weapon("test", slot, "0.0")),
+        Err(WeaponError::Invalid(_))
+      ));
+    }
+  }
+
+  #[test]
+  fn too_many_weapons_is_invalid() {
+    let fits: String = (0..MAX_WEAPONS)
+      .map(|index| weapon(&format!("w{}", index), 1, "0.0"))
+      .collect();
+    assert!(parse(&fits).is_ok());
+
+    let too_many = format!("{}{}", fits, weapon("one_more", 1, "0.0"));
+    assert!(matches!(parse(&too_many), Err(WeaponError::Invalid(_))));
+  }
+}
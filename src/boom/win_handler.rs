@@ -1,13 +1,63 @@
+use std::collections::HashSet;
+
 use glam::{DVec2, IVec2};
 use sdl2::{
+  controller::{Axis, Button, GameController},
   event::{self},
   keyboard::Keycode,
+  mouse::{MouseButton, MouseWheelDirection},
   rect::Rect,
-  render::{Canvas, Texture},
-  video::Window,
-  Sdl, VideoSubsystem,
+  render::{Canvas, Texture, TextureCreator},
+  video::{Window, WindowContext},
+  GameControllerSubsystem, Sdl, VideoSubsystem,
 };
 
+use super::settings::Settings;
+
+///
+/// A controller that's plugged in, and which buttons got pressed on it during the last poll.
+///
+pub struct Gamepad {
+  controller: GameController,
+  buttons_pressed: Vec<Button>,
+}
+
+impl Gamepad {
+  ///
+  /// SDL's instance id. It stays the same for as long as the pad stays plugged in.
+  ///
+  pub fn id(&self) -> u32 {
+    self.controller.instance_id()
+  }
+
+  pub fn name(&self) -> String {
+    self.controller.name()
+  }
+
+  ///
+  /// Where a stick or trigger is, -1 to 1. Anything inside the dead zone is 0 and the rest
+  /// gets stretched back out so the stick still reaches 1.
+  ///
+  pub fn axis(&self, axis: Axis, dead_zone: f64) -> f64 {
+    let value = (self.controller.axis(axis) as f64 / i16::MAX as f64).clamp(-1.0, 1.0);
+    if value.abs() < dead_zone {
+      return 0.0;
+    }
+    value.signum() * (value.abs() - dead_zone) / (1.0 - dead_zone)
+  }
+
+  pub fn button_down(&self, button: Button) -> bool {
+    self.controller.button(button)
+  }
+
+  ///
+  /// Was this button pressed down during the last poll?
+  ///
+  pub fn button_pressed(&self, button: Button) -> bool {
+    self.buttons_pressed.contains(&button)
+  }
+}
+
 ///
 /// Win encapsulates the Window components to clean up the
 /// external implementation and allow more flexible execution
@@ -16,33 +66,53 @@ use sdl2::{
 pub struct WinHandler {
   sdl_context: Option<Sdl>,
   video_subsystem: Option<VideoSubsystem>,
+  controller_subsystem: Option<GameControllerSubsystem>,
   pub canvas: Option<Canvas<Window>>,
+  pub texture_creator: Option<&'static TextureCreator<WindowContext>>,
   pub quit_received: bool,
   pub window_size: IVec2,
   mouse_captured: bool,
   pub mouse_delta: DVec2,
-  mouse_sensitivity: f64,
+  pub mouse_sensitivity: f64,
+  pub keys_pressed: Vec<Keycode>,
+  keys_down: HashSet<Keycode>,
   pub forward_down: bool,
   pub backward_down: bool,
   pub left_down: bool,
   pub right_down: bool,
+  pub jump_down: bool,
+  pub crouch_down: bool,
+  pub fire_down: bool,
+  /// Wheel clicks since the last poll. Up is positive.
+  pub mouse_wheel: i32,
+  /// Every controller plugged in, in the order they showed up.
+  pub gamepads: Vec<Gamepad>,
 }
 
 impl WinHandler {
-  pub fn new() -> Self {
+  pub fn new(settings: &Settings) -> Self {
     let mut new_window = WinHandler {
       sdl_context: None,
       video_subsystem: None,
+      controller_subsystem: None,
       canvas: None,
+      texture_creator: None,
       quit_received: false,
-      window_size: IVec2::new(512, 512),
+      window_size: IVec2::new(settings.video.window_width, settings.video.window_height),
       mouse_captured: false,
       mouse_delta: DVec2::new(0.0, 0.0),
-      mouse_sensitivity: 10.0,
+      mouse_sensitivity: settings.input.mouse_sensitivity,
+      keys_pressed: vec![],
+      keys_down: HashSet::new(),
       forward_down: false,
       backward_down: false,
       left_down: false,
       right_down: false,
+      jump_down: false,
+      crouch_down: false,
+      fire_down: false,
+      mouse_wheel: 0,
+      gamepads: Vec::new(),
     };
 
     // I'm doing this a bit differently than I usually do.
@@ -87,6 +157,12 @@ impl WinHandler {
         .unwrap(),
     );
 
+    // Leaked on purpose. There's only ever one and it lives as long as the game does,
+    // so textures made from it can be kept around without lifetime gymnastics.
+    new_window.texture_creator = Some(Box::leak(Box::new(
+      new_window.canvas.as_ref().unwrap().texture_creator(),
+    )));
+
     new_window
       .sdl_context
       .as_ref()
@@ -94,9 +170,51 @@ impl WinHandler {
       .mouse()
       .capture(true);
 
+    // No controllers is fine, they're only needed for split screen. Ones that are already
+    // plugged in show up as added events on the first poll.
+    new_window.controller_subsystem = match new_window.sdl().game_controller() {
+      Ok(subsystem) => Some(subsystem),
+      Err(e) => {
+        eprintln!("boom: no gamepad support: {}", e);
+        None
+      }
+    };
+
     new_window
   }
 
+  ///
+  /// The controller with this instance id, if it's still plugged in.
+  ///
+  pub fn gamepad(&self, id: u32) -> Option<&Gamepad> {
+    self.gamepads.iter().find(|gamepad| gamepad.id() == id)
+  }
+
+  fn add_gamepad(&mut self, joystick_index: u32) {
+    let Some(subsystem) = &self.controller_subsystem else {
+      return;
+    };
+    match subsystem.open(joystick_index) {
+      Ok(controller) => {
+        if self.gamepad(controller.instance_id()).is_none() {
+          println!("gamepad connected | {} |", controller.name());
+          self.gamepads.push(Gamepad {
+            controller,
+            buttons_pressed: Vec::new(),
+          });
+        }
+      }
+      Err(e) => eprintln!("boom: could not open gamepad {}: {}", joystick_index, e),
+    }
+  }
+
+  ///
+  /// The SDL context, for the subsystems that aren't about the window.
+  ///
+  pub fn sdl(&self) -> &Sdl {
+    self.sdl_context.as_ref().unwrap()
+  }
+
   fn toggle_mouse_capture(&mut self) {
     self.mouse_captured = !self.mouse_captured;
 
@@ -115,6 +233,15 @@ impl WinHandler {
       .set_relative_mouse_mode(self.mouse_captured);
   }
 
+  ///
+  /// Grabs or lets go of the mouse. Menus want it free, playing wants it captured.
+  ///
+  pub fn set_mouse_capture(&mut self, captured: bool) {
+    if self.mouse_captured != captured {
+      self.toggle_mouse_capture();
+    }
+  }
+
   ///
   /// Changes the game window title to whatever you want.
   ///
@@ -129,31 +256,64 @@ impl WinHandler {
   }
 
   ///
-  /// Draws a texture straight into the canvas.
+  /// The biggest rectangle with the frame's aspect ratio that fits in the window, centered.
+  ///
+  /// Whatever is left over becomes black bars.
+  ///
+  pub fn letterbox(&self, frame_size: IVec2) -> Rect {
+    let window = self.window_size.as_dvec2();
+    let frame = frame_size.as_dvec2();
+    let scale = (window.x / frame.x).min(window.y / frame.y);
+    let size = (frame * scale).round().as_ivec2().max(IVec2::ONE);
+    let offset = (self.window_size - size) / 2;
+
+    Rect::new(offset.x, offset.y, size.x as u32, size.y as u32)
+  }
+
+  ///
+  /// Draws a texture straight into the canvas, stretched up to fit the window.
   ///
   /// Also shows it immediately.
   ///
   pub fn draw(&mut self, texture: &Texture) {
+    let query = texture.query();
+    let destination = self.letterbox(IVec2::new(query.width as i32, query.height as i32));
+
     self
       .canvas
       .as_mut()
       .unwrap()
-      .copy(
-        texture,
-        None,
-        Rect::new(0, 0, self.window_size.x as u32, self.window_size.y as u32),
-      )
+      .copy(texture, None, destination)
       .unwrap();
 
     self.canvas.as_mut().unwrap().present();
   }
 
+  ///
+  /// Was this key pressed down during the last poll?
+  ///
+  pub fn key_pressed(&self, key: Keycode) -> bool {
+    self.keys_pressed.contains(&key)
+  }
+
+  ///
+  /// Is this key being held down right now?
+  ///
+  pub fn key_down(&self, key: Keycode) -> bool {
+    self.keys_down.contains(&key)
+  }
+
   ///
   /// Consider this glfw's glfwPollEvents but not.
   ///
   pub fn poll(&mut self) {
     self.mouse_delta.x = 0.0;
     self.mouse_delta.y = 0.0;
+    self.mouse_wheel = 0;
+    self.keys_pressed.clear();
+    for gamepad in &mut self.gamepads {
+      gamepad.buttons_pressed.clear();
+    }
     // self.forward_down = false;
     // self.backward_down = false;
     // self.left_down = false;
@@ -172,7 +332,7 @@ impl WinHandler {
           window_id,
           win_event,
         } => match win_event {
-          event::WindowEvent::Resized(x, y) => {
+          event::WindowEvent::Resized(x, y) | event::WindowEvent::SizeChanged(x, y) => {
             println!("window resized | {} | {} |", x, y);
             self.window_size.x = x;
             self.window_size.y = y;
@@ -197,6 +357,23 @@ impl WinHandler {
           }
         }
 
+        event::Event::MouseButtonDown {
+          mouse_btn: MouseButton::Left,
+          ..
+        } if self.mouse_captured => self.fire_down = true,
+
+        event::Event::MouseButtonUp {
+          mouse_btn: MouseButton::Left,
+          ..
+        } => self.fire_down = false,
+
+        event::Event::MouseWheel { y, direction, .. } if self.mouse_captured => {
+          self.mouse_wheel += match direction {
+            MouseWheelDirection::Flipped => -y,
+            _ => y,
+          };
+        }
+
         event::Event::KeyDown {
           timestamp,
           window_id,
@@ -205,16 +382,23 @@ impl WinHandler {
           keymod,
           repeat,
         } => match keycode {
-          Some(key) => match key {
-            Keycode::E => self.toggle_mouse_capture(),
-            Keycode::W => self.forward_down = true,
-            Keycode::S => self.backward_down = true,
-            Keycode::A => self.left_down = true,
-            Keycode::D => self.right_down = true,
-
-            Keycode::Escape => self.quit_received = true,
-            _ => (),
-          },
+          Some(key) => {
+            if !repeat {
+              self.keys_pressed.push(key);
+            }
+            self.keys_down.insert(key);
+            match key {
+              Keycode::F1 => self.toggle_mouse_capture(),
+              Keycode::W => self.forward_down = true,
+              Keycode::S => self.backward_down = true,
+              Keycode::A => self.left_down = true,
+              Keycode::D => self.right_down = true,
+              Keycode::Space => self.jump_down = true,
+              Keycode::LCtrl | Keycode::C => self.crouch_down = true,
+
+              _ => (),
+            }
+          }
           None => (),
         },
 
@@ -226,15 +410,36 @@ impl WinHandler {
           keymod,
           repeat,
         } => match keycode {
-          Some(key) => match key {
-            Keycode::W => self.forward_down = false,
-            Keycode::S => self.backward_down = false,
-            Keycode::A => self.left_down = false,
-            Keycode::D => self.right_down = false,
-            _ => (),
-          },
+          Some(key) => {
+            self.keys_down.remove(&key);
+            match key {
+              Keycode::W => self.forward_down = false,
+              Keycode::S => self.backward_down = false,
+              Keycode::A => self.left_down = false,
+              Keycode::D => self.right_down = false,
+              Keycode::Space => self.jump_down = false,
+              Keycode::LCtrl | Keycode::C => self.crouch_down = false,
+              _ => (),
+            }
+          }
           None => (),
         },
+
+        event::Event::ControllerDeviceAdded { which, .. } => self.add_gamepad(which),
+
+        event::Event::ControllerDeviceRemoved { which, .. } => {
+          self.gamepads.retain(|gamepad| gamepad.id() != which);
+        }
+
+        event::Event::ControllerButtonDown { which, button, .. } => {
+          if let Some(gamepad) = self
+            .gamepads
+            .iter_mut()
+            .find(|gamepad| gamepad.id() == which)
+          {
+            gamepad.buttons_pressed.push(button);
+          }
+        }
         _ => (),
       }
     }
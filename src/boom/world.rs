@@ -1,163 +1,503 @@
-use std::collections::HashMap;
+use std::{
+  cmp::Reverse,
+  collections::{BinaryHeap, HashMap},
+  path::PathBuf,
+};
 
 use glam::DVec2;
 use glam::IVec2;
+use rand::Rng;
+use rhai::Dynamic;
+use serde::{Deserialize, Serialize};
 
-use super::win_handler::WinHandler;
+use super::{
+  entity::{AiState, Bullet, Collider, Entities, EntityId, PLAYER_RADIUS},
+  level::Level,
+  player_input::PlayerInput,
+  script::Script,
+  settings::Settings,
+  sound::{sound_id, SoundEvent},
+  texture::{find_texture, texture_id},
+  trigger::{Action, Condition, Trigger},
+  weapon::{AmmoType, Weapons},
+};
+
+// Eye heights are in wall heights. 0.5 is looking at the middle of the wall.
+const STAND_EYE_HEIGHT: f64 = 0.5;
+const CROUCH_EYE_HEIGHT: f64 = 0.3;
+const JUMP_SPEED: f64 = 2.5;
+const GRAVITY: f64 = 9.0;
+// Pitch is in screen heights, this keeps the horizon on the screen.
+const MAX_PITCH: f64 = 0.4;
+// How far away a wall can be and still get used, in cells.
+const USE_DISTANCE: f64 = 1.5;
+// How close the player has to get to an item to grab it, in cells.
+const PICKUP_DISTANCE: f64 = 0.5;
+pub const MAX_HEALTH: i32 = 100;
+const MESSAGE_TIME: f64 = 4.0;
+// Mobs stop walking once they're this close to the player, in cells.
+const MOB_REACH: f64 = 0.8;
+// Sound loses this many steps getting through a closed door.
+const DOOR_NOISE_COST: u32 = 4;
+// Hitscan bullets stop at the first wall anyway, this just keeps the ray finite.
+const HITSCAN_RANGE: f64 = 64.0;
+// Seconds a fragged player lies there before coming back.
+const RESPAWN_TIME: f64 = 3.0;
+// Seconds before an item that got picked up in deathmatch shows up again.
+const ITEM_RESPAWN_TIME: f64 = 20.0;
 
 ///
 /// The player. It's you!
 ///
+/// In multiplayer there's one of these for everybody, and the others are who you're shooting at.
+///
 pub struct Player {
-  alive: bool,
+  /// Stays the same for as long as they're in the game, respawning doesn't change it.
+  pub id: u32,
+  pub name: String,
+  /// The body everybody else sees and shoots at. It follows the player around.
+  pub entity: EntityId,
+  pub alive: bool,
   pub position: DVec2,
   pub direction: DVec2,
+  /// The camera plane, perpendicular to direction. How long it is sets the FOV.
+  pub plane: DVec2,
+  pub pitch: f64,
+  pub z: f64,
+  pub z_velocity: f64,
+  pub crouching: bool,
+  pub health: i32,
+  pub armor: i32,
+  pub ammo: HashMap<AmmoType, i32>,
+  /// Where the weapon is in its bob cycle, in radians.
+  pub bob_phase: f64,
+  /// How much the weapon is bobbing. 0 is standing still, 1 is running.
+  pub bob_amount: f64,
+  /// Keys picked up in this level.
+  pub keys: Vec<KeyColor>,
+  /// Ids of the weapons the player is carrying.
+  pub weapons: Vec<String>,
+  /// The id of the weapon in the player's hands.
+  pub weapon: String,
+  /// Seconds until the weapon can fire again.
+  pub weapon_cooldown: f64,
+  /// Seconds since the last shot, for the firing animation.
+  pub since_shot: f64,
+  /// Deathmatch score. Goes down for killing yourself.
+  pub frags: i32,
+  pub deaths: i32,
+  /// Seconds until a fragged player comes back.
+  pub respawn_time: f64,
 }
 
 impl Player {
-  pub fn new() -> Self {
-    Player {
+  pub fn new(id: u32, name: &str, entity: EntityId) -> Self {
+    let mut player = Player {
+      id,
+      name: name.to_string(),
+      entity,
       alive: true,
       position: DVec2::new(22.0, 12.0),
       direction: DVec2::new(-1.0, 0.0),
+      plane: DVec2::new(0.0, 0.66),
+      pitch: 0.0,
+      z: 0.0,
+      z_velocity: 0.0,
+      crouching: false,
+      health: 0,
+      armor: 0,
+      ammo: HashMap::new(),
+      bob_phase: 0.0,
+      bob_amount: 0.0,
+      keys: Vec::new(),
+      weapons: Vec::new(),
+      weapon: String::new(),
+      weapon_cooldown: 0.0,
+      since_shot: f64::INFINITY,
+      frags: 0,
+      deaths: 0,
+      respawn_time: 0.0,
+    };
+    player.apply_loadout(Loadout::starting());
+    player
+  }
+
+  pub fn ammo_of(&self, ammo: AmmoType) -> i32 {
+    self.ammo.get(&ammo).copied().unwrap_or(0)
+  }
+
+  ///
+  /// Adds ammo up to the most that can be carried. Returns false if there was no room.
+  ///
+  pub fn give_ammo(&mut self, ammo: AmmoType, amount: i32) -> bool {
+    let current = self.ammo_of(ammo);
+    if current >= ammo.max() {
+      return false;
+    }
+    self.ammo.insert(ammo, (current + amount).min(ammo.max()));
+    true
+  }
+
+  ///
+  /// Swings the weapon around while walking, and lets it settle when stopped.
+  ///
+  fn update_bob(&mut self, delta: f64, moving: bool) {
+    let target = if moving { 1.0 } else { 0.0 };
+    self.bob_amount += (target - self.bob_amount) * (delta * 8.0).min(1.0);
+
+    if moving {
+      self.bob_phase = (self.bob_phase + delta * 10.0) % std::f64::consts::TAU;
+    }
+  }
+
+  ///
+  /// How high off the floor the camera is, in wall heights.
+  ///
+  pub fn eye_height(&self) -> f64 {
+    let base = if self.crouching {
+      CROUCH_EYE_HEIGHT
+    } else {
+      STAND_EYE_HEIGHT
+    };
+    base + self.z
+  }
+
+  pub fn on_ground(&self) -> bool {
+    self.z <= 0.0
+  }
+
+  ///
+  /// Where the horizon sits on the screen. Looking up and down just slides it around.
+  ///
+  pub fn horizon(&self, window_size: &IVec2) -> i32 {
+    window_size.y / 2 + (self.pitch * window_size.y as f64) as i32
+  }
+
+  ///
+  /// Points the player somewhere else, taking the camera plane along.
+  ///
+  pub fn face(&mut self, direction: DVec2) {
+    let length = self.plane.length();
+    self.direction = direction;
+    self.plane = DVec2::new(direction.y, -direction.x) * length;
+  }
+
+  pub fn loadout(&self) -> Loadout {
+    Loadout {
+      health: self.health,
+      armor: self.armor,
+      ammo: self.ammo.clone(),
+      weapons: self.weapons.clone(),
+      weapon: self.weapon.clone(),
     }
   }
+
+  pub fn apply_loadout(&mut self, loadout: Loadout) {
+    self.health = loadout.health;
+    self.armor = loadout.armor;
+    self.ammo = loadout.ammo;
+    self.weapons = loadout.weapons;
+    self.weapon = loadout.weapon;
+  }
 }
 
 ///
-/// Enemies in the game. If you can call them that.
+/// The parts of the player that come along to the next level. Keys stay behind.
 ///
-pub struct Mob {
-  alive: bool,
-  position: DVec2,
-  yaw: f64,
-  sprite: usize,
+#[derive(Clone, Debug)]
+pub struct Loadout {
+  pub health: i32,
+  pub armor: i32,
+  pub ammo: HashMap<AmmoType, i32>,
+  pub weapons: Vec<String>,
+  pub weapon: String,
 }
-impl Mob {
-  pub fn new(position: DVec2) -> Self {
-    Mob {
-      alive: true,
-      position,
-      yaw: 0.0,
-      sprite: 0,
+
+impl Loadout {
+  ///
+  /// What everybody starts the game with.
+  ///
+  pub fn starting() -> Self {
+    Loadout {
+      health: MAX_HEALTH,
+      armor: 0,
+      ammo: HashMap::from([(AmmoType::Bullets, 50)]),
+      weapons: vec!["pistol".to_string()],
+      weapon: "pistol".to_string(),
     }
   }
 }
 
 ///
-/// The bullet the player can shoot. Pew pew.
+/// How the player did on a level, for the intermission screen.
 ///
-pub struct Bullet {
-  position: DVec2,
-  direction: DVec2,
+#[derive(Clone, Copy, Debug)]
+pub struct LevelStats {
+  pub kills: usize,
+  pub total_mobs: usize,
+  /// Seconds spent in the level.
+  pub time: f64,
 }
 
-impl Bullet {
-  pub fn new(position: DVec2, direction: DVec2) -> Self {
-    Bullet {
-      position,
-      direction,
+///
+/// Keys open the locked doors of the same color.
+///
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyColor {
+  Red,
+  Blue,
+  Yellow,
+}
+
+impl KeyColor {
+  pub fn name(&self) -> &'static str {
+    match self {
+      KeyColor::Red => "red",
+      KeyColor::Blue => "blue",
+      KeyColor::Yellow => "yellow",
+    }
+  }
+
+  pub fn color(&self) -> [u8; 3] {
+    match self {
+      KeyColor::Red => [220, 40, 40],
+      KeyColor::Blue => [60, 90, 230],
+      KeyColor::Yellow => [235, 210, 50],
     }
   }
 }
 
+///
+/// What an item does when you pick it up.
+///
+/// In a level file these look like `{ health = 25 }`, `{ ammo = ["shells", 8] }`,
+/// `{ key = "red" }` or `{ weapon = "shotgun" }`.
+///
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemKind {
+  Health(i32),
+  Ammo(AmmoType, i32),
+  Key(KeyColor),
+  /// Weapons are drawn with the item_<id> texture.
+  Weapon(String),
+}
+
+impl ItemKind {
+  ///
+  /// The texture to draw this item with, None if it doesn't exist.
+  ///
+  pub fn sprite(&self) -> Option<usize> {
+    match self {
+      ItemKind::Health(_) => find_texture("item_health"),
+      ItemKind::Ammo(AmmoType::Bullets, _) => find_texture("item_ammo"),
+      ItemKind::Ammo(AmmoType::Shells, _) => find_texture("item_shells"),
+      ItemKind::Key(color) => find_texture(format!("key_{}", color.name()).as_str()),
+      ItemKind::Weapon(id) => find_texture(format!("item_{}", id).as_str()),
+    }
+  }
+}
+
+///
+/// A wall that opens when you use it. Locked ones need the matching key.
+///
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct Door {
+  pub cell: IVec2,
+  #[serde(default)]
+  pub key: Option<KeyColor>,
+}
+
+///
+/// How far along a ray it first touches a circle, if it does at all.
+///
+fn ray_hits_circle(from: DVec2, direction: DVec2, center: DVec2, radius: f64) -> Option<f64> {
+  let to_center = center - from;
+  let along = to_center.dot(direction);
+  let miss = to_center.length_squared() - along * along;
+  if along < 0.0 || miss > radius * radius {
+    return None;
+  }
+  Some((along - (radius * radius - miss).sqrt()).max(0.0))
+}
+
 ///
 /// Raw map data.
 ///
 pub struct Map {
   pub min: IVec2,
   pub max: IVec2,
-  pub data: [[i32; 24]; 24],
+  /// Indexed as data[x][y].
+  pub data: Vec<Vec<i32>>,
+  /// Cells the player has seen, for the automap's fog of war.
+  pub revealed: Vec<Vec<bool>>,
+  /// Walking onto one of these finishes the level.
+  pub exits: Vec<IVec2>,
+  pub doors: Vec<Door>,
 }
 
 impl Map {
-  pub fn new() -> Self {
+  pub fn new(data: Vec<Vec<i32>>, exits: Vec<IVec2>, doors: Vec<Door>) -> Self {
+    let size = IVec2::new(
+      data.len() as i32,
+      data.first().map_or(0, |row| row.len()) as i32,
+    );
     Map {
       min: IVec2::new(0, 0),
-      max: IVec2::new(24, 24),
-      data: [
-        [
-          8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 4, 4, 6, 4, 4, 6, 4, 6, 4, 4, 4, 6, 4,
-        ],
-        [
-          8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 8, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 4,
-        ],
-        [
-          8, 0, 3, 3, 0, 0, 0, 0, 0, 8, 8, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 6,
-        ],
-        [
-          8, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 6,
-        ],
-        [
-          8, 0, 3, 3, 0, 0, 0, 0, 0, 8, 8, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 4,
-        ],
-        [
-          8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 8, 4, 0, 0, 0, 0, 0, 6, 6, 6, 0, 6, 4, 6,
-        ],
-        [
-          8, 8, 8, 8, 0, 8, 8, 8, 8, 8, 8, 4, 4, 4, 4, 4, 4, 6, 0, 0, 0, 0, 0, 6,
-        ],
-        [
-          7, 7, 7, 7, 0, 7, 7, 7, 7, 0, 8, 0, 8, 0, 8, 0, 8, 4, 0, 4, 0, 6, 0, 6,
-        ],
-        [
-          7, 7, 0, 0, 0, 0, 0, 0, 7, 8, 0, 8, 0, 8, 0, 8, 8, 6, 0, 0, 0, 0, 0, 6,
-        ],
-        [
-          7, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 8, 6, 0, 0, 0, 0, 0, 4,
-        ],
-        [
-          7, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 8, 6, 0, 6, 0, 6, 0, 6,
-        ],
-        [
-          7, 7, 0, 0, 0, 0, 0, 0, 7, 8, 0, 8, 0, 8, 0, 8, 8, 6, 4, 6, 0, 6, 6, 6,
-        ],
-        [
-          7, 7, 7, 7, 0, 7, 7, 7, 7, 8, 8, 4, 0, 6, 8, 4, 8, 3, 3, 3, 0, 3, 3, 3,
-        ],
-        [
-          2, 2, 2, 2, 0, 2, 2, 2, 2, 4, 6, 4, 0, 0, 6, 0, 6, 3, 0, 0, 0, 0, 0, 3,
-        ],
-        [
-          2, 2, 0, 0, 0, 0, 0, 2, 2, 4, 0, 0, 0, 0, 0, 0, 4, 3, 0, 0, 0, 0, 0, 3,
-        ],
-        [
-          2, 0, 0, 0, 0, 0, 0, 0, 2, 4, 0, 0, 0, 0, 0, 0, 4, 3, 0, 0, 0, 0, 0, 3,
-        ],
-        [
-          1, 0, 0, 0, 0, 0, 0, 0, 1, 4, 4, 4, 4, 4, 6, 0, 6, 3, 3, 0, 0, 0, 3, 3,
-        ],
-        [
-          2, 0, 0, 0, 0, 0, 0, 0, 2, 2, 2, 1, 2, 2, 2, 6, 6, 0, 0, 5, 0, 5, 0, 5,
-        ],
-        [
-          2, 2, 0, 0, 0, 0, 0, 2, 2, 2, 0, 0, 0, 2, 2, 0, 5, 0, 5, 0, 0, 0, 5, 5,
-        ],
-        [
-          2, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 2, 5, 0, 5, 0, 5, 0, 5, 0, 5,
-        ],
-        [
-          1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 5,
-        ],
-        [
-          2, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 2, 5, 0, 5, 0, 5, 0, 5, 0, 5,
-        ],
-        [
-          2, 2, 0, 0, 0, 0, 0, 2, 2, 2, 0, 0, 0, 2, 2, 0, 5, 0, 5, 0, 0, 0, 5, 5,
-        ],
-        [
-          2, 2, 2, 2, 1, 2, 2, 2, 2, 2, 2, 1, 2, 2, 2, 5, 5, 5, 5, 5, 5, 5, 5, 5,
-        ],
-      ],
+      max: size,
+      revealed: vec![vec![false; size.y as usize]; size.x as usize],
+      data,
+      exits,
+      doors,
     }
   }
 
+  ///
+  /// Walks a ray through the grid and returns the first wall cell it runs into,
+  /// if there is one closer than max_distance.
+  ///
+  pub fn first_wall(&self, from: DVec2, direction: DVec2, max_distance: f64) -> Option<IVec2> {
+    self
+      .cast(from, direction, max_distance)
+      .map(|(cell, _)| cell)
+  }
+
+  ///
+  /// Same as first_wall, but also says how far along the ray the wall starts.
+  ///
+  pub fn cast(&self, from: DVec2, direction: DVec2, max_distance: f64) -> Option<(IVec2, f64)> {
+    // A ray that doesn't go anywhere would never leave the map.
+    if !from.is_finite() || !direction.is_finite() || direction == DVec2::ZERO {
+      return None;
+    }
+    let mut cell = from.floor().as_ivec2();
+    let step = direction.signum().as_ivec2();
+    let delta = (DVec2::ONE / direction).abs();
+    let mut side_distance = DVec2::new(
+      if direction.x < 0.0 {
+        (from.x - cell.x as f64) * delta.x
+      } else {
+        (cell.x as f64 + 1.0 - from.x) * delta.x
+      },
+      if direction.y < 0.0 {
+        (from.y - cell.y as f64) * delta.y
+      } else {
+        (cell.y as f64 + 1.0 - from.y) * delta.y
+      },
+    );
+
+    loop {
+      let distance = if side_distance.x < side_distance.y {
+        cell.x += step.x;
+        side_distance.x += delta.x;
+        side_distance.x - delta.x
+      } else {
+        cell.y += step.y;
+        side_distance.y += delta.y;
+        side_distance.y - delta.y
+      };
+
+      if distance > max_distance || cell.cmplt(self.min).any() || cell.cmpge(self.max).any() {
+        return None;
+      }
+      if self.data[cell.x as usize][cell.y as usize] != 0 {
+        return Some((cell, distance));
+      }
+    }
+  }
+
+  ///
+  /// Is there a wall here? Everything off the map counts as wall.
+  ///
+  pub fn is_wall(&self, position: DVec2) -> bool {
+    let cell = position.floor().as_ivec2();
+    cell.cmplt(self.min).any()
+      || cell.cmpge(self.max).any()
+      || self.data[cell.x as usize][cell.y as usize] != 0
+  }
+
+  ///
+  /// Can you see one point from the other without a wall in the way?
+  ///
+  pub fn line_of_sight(&self, from: DVec2, to: DVec2) -> bool {
+    let distance = from.distance(to);
+    distance == 0.0 || self.cast(from, (to - from) / distance, distance).is_none()
+  }
+
+  ///
+  /// How many steps it takes to walk from one cell to every other one within max_steps,
+  /// going around walls. Indexed like data, None is out of reach.
+  ///
+  /// Closed doors let things through but cost door_cost steps. Anything else solid doesn't.
+  ///
+  pub fn flood_fill(&self, from: IVec2, max_steps: u32, door_cost: u32) -> Vec<Vec<Option<u32>>> {
+    let size = self.max - self.min;
+    let mut steps = vec![vec![None; size.y as usize]; size.x as usize];
+    let on_map = |cell: IVec2| cell.cmpge(self.min).all() && cell.cmplt(self.max).all();
+    if !on_map(from) {
+      return steps;
+    }
+
+    // Doors make the steps uneven, so it's cheapest first rather than a plain queue.
+    let mut frontier = BinaryHeap::from([Reverse((0, from.x, from.y))]);
+    steps[from.x as usize][from.y as usize] = Some(0);
+
+    while let Some(Reverse((cost, x, y))) = frontier.pop() {
+      if steps[x as usize][y as usize].is_some_and(|best| best < cost) {
+        continue;
+      }
+
+      for offset in [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y] {
+        let next = IVec2::new(x, y) + offset;
+        if !on_map(next) {
+          continue;
+        }
+        let step = if self.data[next.x as usize][next.y as usize] == 0 {
+          1
+        } else if self.doors.iter().any(|door| door.cell == next) {
+          door_cost
+        } else {
+          continue;
+        };
+
+        let next_cost = cost + step;
+        let known = &mut steps[next.x as usize][next.y as usize];
+        if next_cost <= max_steps && known.map_or(true, |best| next_cost < best) {
+          *known = Some(next_cost);
+          frontier.push(Reverse((next_cost, next.x, next.y)));
+        }
+      }
+    }
+    steps
+  }
+
   pub fn testing() {
     let x = vec![[1, 23, 4]];
     // .to_owned();
   }
 }
 
+///
+/// Text on the HUD that goes away after a few seconds.
+///
+pub struct Message {
+  pub text: String,
+  pub time_left: f64,
+  /// The id of the only player who gets to see it. None is everybody.
+  pub to: Option<u32>,
+}
+
+///
+/// An item that got picked up in deathmatch, waiting to come back.
+///
+pub struct ItemRespawn {
+  pub time_left: f64,
+  pub position: DVec2,
+  pub kind: ItemKind,
+  pub placed: Option<usize>,
+}
+
 ///
 /// World is the master container for all worldly things.
 /// Like the player, enemies, map data, bullets.
@@ -165,114 +505,1020 @@ impl Map {
 /// Neat.
 ///
 pub struct World {
-  pub player: Player,
-  pub mobs: Vec<Mob>,
-  pub bullet: HashMap<String, Bullet>,
+  /// Everybody playing. The first one is whoever is sitting at this screen, or the only one
+  /// there is in single player.
+  pub players: Vec<Player>,
+  /// How many players from the front of players are sitting at this screen. More than one
+  /// is split screen, each of them gets their own slice of it.
+  pub local_players: usize,
+  /// Mobs, items, bullets and whatever else gets added. See entity.rs.
+  pub entities: Entities,
+  /// How many items the level placed, the first ones spawned get Pickup::placed set.
+  pub placed_items: usize,
   pub map: Map,
-  pub plane: DVec2,
+  /// Horizontal FOV in degrees, every player's camera plane gets stretched to match.
+  pub fov: f64,
+  pub move_speed: f64,
+  /// The id of the level this world was built from.
+  pub level_id: String,
+  pub level_name: String,
+  /// Seconds spent in this level so far.
+  pub level_time: f64,
+  /// Set once the player walks onto an exit. Deathmatch never ends that way.
+  pub exited: bool,
+  /// Players shoot each other instead of mobs, and come back after they die.
+  pub deathmatch: bool,
+  /// Where players (re)appear, facing spawn_direction.
+  pub spawns: Vec<DVec2>,
+  pub spawn_direction: DVec2,
+  next_player_id: u32,
+  /// Walls players pressed use on this tick, for the triggers.
+  pub used_walls: Vec<IVec2>,
+  pub item_respawns: Vec<ItemRespawn>,
+  pub triggers: Vec<Trigger>,
+  /// Newest last.
+  pub messages: Vec<Message>,
+  pub script: Option<Script>,
+  pub weapons: Weapons,
+  /// Noises made this tick, for Audio to pick up.
+  pub sounds: Vec<SoundEvent>,
+  /// The level's music, if it has any.
+  pub music: Option<PathBuf>,
 }
 
 impl World {
-  pub fn new() -> Self {
-    World {
-      player: Player::new(),
-      mobs: vec![],
-      bullet: HashMap::new(),
-      map: Map::new(),
-      plane: DVec2::new(0.0, 0.66),
+  ///
+  /// A single player world, with the player standing at the level's start.
+  ///
+  pub fn new(level: &Level, settings: &Settings, weapons: &Weapons) -> Self {
+    let mut world = World::build(level, settings, weapons, false);
+    world.add_player("player");
+    world
+  }
+
+  ///
+  /// A deathmatch world. Nobody's in it yet and the mobs stay home, see add_player.
+  ///
+  pub fn new_deathmatch(level: &Level, settings: &Settings, weapons: &Weapons) -> Self {
+    World::build(level, settings, weapons, true)
+  }
+
+  fn build(level: &Level, settings: &Settings, weapons: &Weapons, deathmatch: bool) -> Self {
+    let size = IVec2::new(
+      level.map.len() as i32,
+      level.map.first().map_or(0, |row| row.len()) as i32,
+    );
+    let mut entities = Entities::new(size);
+    if !deathmatch {
+      for position in &level.mobs {
+        entities.spawn_mob(*position);
+      }
+    }
+    for (index, (position, kind)) in level.items.iter().enumerate() {
+      entities.spawn_item(*position, kind.clone(), Some(index));
+    }
+
+    let mut world = World {
+      players: Vec::new(),
+      local_players: 1,
+      entities,
+      placed_items: level.items.len(),
+      map: Map::new(level.map.clone(), level.exits.clone(), level.doors.clone()),
+      fov: settings.video.fov,
+      move_speed: settings.gameplay.move_speed,
+      level_id: level.id.clone(),
+      level_name: level.name.clone(),
+      level_time: 0.0,
+      exited: false,
+      deathmatch,
+      spawns: level.spawns.clone(),
+      spawn_direction: level.direction,
+      next_player_id: 1,
+      used_walls: Vec::new(),
+      item_respawns: Vec::new(),
+      triggers: level.triggers.clone(),
+      messages: Vec::new(),
+      script: None,
+      weapons: weapons.clone(),
+      sounds: Vec::new(),
+      music: level.music.clone(),
+    };
+
+    if let Some(source) = &level.script {
+      match Script::compile(&source.name, &source.source) {
+        Ok(script) => world.script = Some(script),
+        Err(e) => {
+          eprintln!("boom: {}", e);
+          world.show_message(&e);
+        }
+      }
+    }
+
+    world
+  }
+
+  pub fn stats(&self) -> LevelStats {
+    LevelStats {
+      kills: self
+        .entities
+        .mobs()
+        .filter(|id| !self.entities.is_alive(*id))
+        .count(),
+      total_mobs: self.entities.ais.len(),
+      time: self.level_time,
+    }
+  }
+
+  ///
+  /// The player at this screen. There's always at least one outside of a dedicated server.
+  ///
+  pub fn player(&self) -> &Player {
+    &self.players[0]
+  }
+
+  pub fn player_mut(&mut self) -> &mut Player {
+    &mut self.players[0]
+  }
+
+  ///
+  /// The players sitting at this screen, at least one as long as anybody's playing.
+  ///
+  pub fn local_player_count(&self) -> usize {
+    self.local_players.clamp(1, self.players.len().max(1))
+  }
+
+  pub fn player_index(&self, id: u32) -> Option<usize> {
+    self.players.iter().position(|player| player.id == id)
+  }
+
+  ///
+  /// Brings a new player into the game at a spawn point. Returns their index in players.
+  ///
+  pub fn add_player(&mut self, name: &str) -> usize {
+    let entity = self.entities.spawn_player(DVec2::ZERO);
+    let player = Player::new(self.next_player_id, name, entity);
+    self.next_player_id += 1;
+    self.players.push(player);
+
+    let index = self.players.len() - 1;
+    self.respawn(index);
+    index
+  }
+
+  ///
+  /// Takes a player out of the game, body and all.
+  ///
+  pub fn remove_player(&mut self, id: u32) {
+    if let Some(index) = self.player_index(id) {
+      let player = self.players.remove(index);
+      self.entities.despawn(player.entity);
     }
   }
 
-  fn do_player_controls(&mut self, delta: f64, window: &WinHandler) {
-    let move_speed = delta * 5.0;
+  ///
+  /// The spawn point furthest from everybody who's alive, so nobody appears right in
+  /// front of a gun.
+  ///
+  fn spawn_point(&self) -> DVec2 {
+    let distance_to_nearest = |spawn: &DVec2| {
+      self
+        .players
+        .iter()
+        .filter(|player| player.alive)
+        .map(|player| player.position.distance(*spawn))
+        .fold(f64::INFINITY, f64::min)
+    };
+
+    self
+      .spawns
+      .iter()
+      .copied()
+      .max_by(|a, b| distance_to_nearest(a).total_cmp(&distance_to_nearest(b)))
+      .unwrap_or_default()
+  }
+
+  ///
+  /// Puts a player back at a spawn point with a fresh loadout.
+  ///
+  fn respawn(&mut self, index: usize) {
+    // They're not alive yet, so they don't push themselves away from their own corpse.
+    self.players[index].alive = false;
+    let position = self.spawn_point();
+    let fov = self.fov;
+
+    let player = &mut self.players[index];
+    player.alive = true;
+    player.position = position;
+    player.face(self.spawn_direction);
+    player.pitch = 0.0;
+    player.z = 0.0;
+    player.z_velocity = 0.0;
+    player.crouching = false;
+    player.keys.clear();
+    player.weapon_cooldown = 0.0;
+    player.since_shot = f64::INFINITY;
+    player.apply_loadout(Loadout::starting());
+    World::stretch_plane(player, fov);
+    self.sync_body(index);
+  }
+
+  ///
+  /// Keeps a player's body where the player is, and knocks it over when they die.
+  ///
+  pub fn sync_body(&mut self, index: usize) {
+    let player = &self.players[index];
+    let (id, alive) = (player.entity, player.alive);
+    let yaw = player.direction.y.atan2(player.direction.x);
+
+    self.entities.move_to(id, player.position);
+    if let Some(transform) = self.entities.transforms.get_mut(id) {
+      transform.yaw = yaw;
+    }
+    if let Some(sprite) = self.entities.sprites.get_mut(id) {
+      sprite.texture = texture_id(if alive { "dm" } else { "dm_dead" });
+    }
+    if alive {
+      self.entities.colliders.insert(
+        id,
+        Collider {
+          radius: PLAYER_RADIUS,
+        },
+      );
+    } else {
+      self.entities.colliders.remove(id);
+    }
+  }
+
+  fn stretch_plane(player: &mut Player, fov: f64) {
+    let length = (fov.to_radians() / 2.0).tan();
+    player.plane = DVec2::new(player.direction.y, -player.direction.x) * length;
+  }
+
+  ///
+  /// Stretches the camera planes to match a horizontal FOV in degrees.
+  ///
+  /// The planes stay perpendicular to wherever each player is looking.
+  ///
+  pub fn set_fov(&mut self, fov: f64) {
+    self.fov = fov;
+    for player in &mut self.players {
+      World::stretch_plane(player, fov);
+    }
+  }
+
+  ///
+  /// How many pixels tall something one unit tall and one unit away is.
+  ///
+  /// Pixels are square, so this comes from the width and the FOV. Otherwise resizing
+  /// the window squashes and stretches everything.
+  ///
+  pub fn projection_scale(&self, window_size: &IVec2) -> f64 {
+    (window_size.x as f64 / 2.0) / (self.fov.to_radians() / 2.0).tan()
+  }
+
+  fn do_player_controls(&mut self, index: usize, input: &PlayerInput, delta: f64) {
+    let map = &self.map;
+    let player = &mut self.players[index];
+    let mut move_speed = delta * self.move_speed;
+    if player.crouching {
+      move_speed /= 2.0;
+    }
     let mut moving = false;
 
-    if window.forward_down {
-      if self.map.data
-        [(self.player.position.x + self.player.direction.x * move_speed).floor() as usize]
-        [(self.player.position.y).floor() as usize]
-        == 0
-      {
-        self.player.position.x += self.player.direction.x * move_speed;
+    // Each axis moves on its own, so running into a wall at an angle slides along it.
+    let mut walk = |step: DVec2| {
+      if !map.is_wall(player.position + DVec2::new(step.x, 0.0)) {
+        player.position.x += step.x;
       }
-      if self.map.data[(self.player.position.x).floor() as usize]
-        [(self.player.position.y + self.player.direction.y * move_speed).floor() as usize]
-        == 0
-      {
-        self.player.position.y += self.player.direction.y * move_speed;
+      if !map.is_wall(player.position + DVec2::new(0.0, step.y)) {
+        player.position.y += step.y;
+      }
+      moving = true;
+    };
+
+    if input.forward {
+      walk(player.direction * move_speed);
+    }
+    if input.backward {
+      walk(-player.direction * move_speed);
+    }
+    if input.right {
+      walk(player.plane * move_speed);
+    }
+    if input.left {
+      walk(-player.plane * move_speed);
+    }
+
+    let rot_speed = input.turn;
+    let old_dir_x = player.direction.x;
+    player.direction.x =
+      player.direction.x * (-rot_speed).cos() - player.direction.y * (-rot_speed).sin();
+    player.direction.y = old_dir_x * (-rot_speed).sin() + player.direction.y * (-rot_speed).cos();
+    let old_plane_x = player.plane.x;
+    player.plane.x = player.plane.x * (-rot_speed).cos() - player.plane.y * (-rot_speed).sin();
+    player.plane.y = old_plane_x * (-rot_speed).sin() + player.plane.y * (-rot_speed).cos();
+
+    // Mouse up looks up, which pushes the horizon down the screen.
+    player.pitch = (player.pitch - input.look * 0.5).clamp(-MAX_PITCH, MAX_PITCH);
+
+    player.crouching = input.crouch;
+
+    if input.jump && player.on_ground() && !player.crouching {
+      player.z_velocity = JUMP_SPEED;
+    }
+
+    let on_ground = player.on_ground();
+    player.update_bob(delta, moving && on_ground);
+  }
+
+  ///
+  /// Number keys pick the weapon in that slot, pressing it again goes to the next one in
+  /// the same slot. The mouse wheel goes through everything the player has.
+  ///
+  fn do_weapon_switching(&mut self, index: usize, input: &PlayerInput) {
+    let player = &mut self.players[index];
+    let owned = self.weapons.sorted(&player.weapons);
+    if owned.is_empty() {
+      return;
+    }
+    let current = owned.iter().position(|id| **id == player.weapon);
+
+    let mut chosen = None;
+    if input.slot != 0 {
+      let in_slot: Vec<&String> = owned
+        .iter()
+        .copied()
+        .filter(|id| self.weapons.defs[*id].slot == input.slot as u32)
+        .collect();
+      let next = in_slot
+        .iter()
+        .position(|id| **id == player.weapon)
+        .map_or(0, |index| (index + 1) % in_slot.len());
+      chosen = in_slot.get(next).copied();
+    }
+
+    if input.wheel != 0 {
+      // Scrolling up goes back a weapon, like it does in pretty much every shooter.
+      let count = owned.len() as i32;
+      let index = current.map_or(0, |index| index as i32) - input.wheel.rem_euclid(count);
+      chosen = Some(owned[index.rem_euclid(count) as usize]);
+    }
+
+    if let Some(id) = chosen {
+      if *id != player.weapon {
+        player.weapon = id.clone();
+        player.since_shot = f64::INFINITY;
       }
-      moving = true
     }
+  }
 
-    if window.backward_down {
-      if self.map.data
-        [(self.player.position.x - self.player.direction.x * move_speed).floor() as usize]
-        [(self.player.position.y).floor() as usize]
-        == 0
-      {
-        self.player.position.x -= self.player.direction.x * move_speed;
+  ///
+  /// Shoots the current weapon while fire is held, as fast as the weapon allows.
+  ///
+  fn do_weapon_firing(&mut self, index: usize, input: &PlayerInput, delta: f64) {
+    let player = &mut self.players[index];
+    player.weapon_cooldown = (player.weapon_cooldown - delta).max(0.0);
+    player.since_shot += delta;
+
+    let Some(weapon) = self.weapons.get(&player.weapon) else {
+      return;
+    };
+    if !input.fire || player.weapon_cooldown > 0.0 || !player.alive {
+      return;
+    }
+    if player.ammo_of(weapon.ammo) < weapon.ammo_per_shot {
+      return;
+    }
+
+    player.ammo.insert(
+      weapon.ammo,
+      player.ammo_of(weapon.ammo) - weapon.ammo_per_shot,
+    );
+    player.weapon_cooldown = 1.0 / weapon.fire_rate;
+    player.since_shot = 0.0;
+    let noise = weapon.noise;
+    if let Some(sound) = weapon.sound_id {
+      self.sounds.push(SoundEvent {
+        sound,
+        position: player.position,
+      });
+    }
+
+    let mut rng = rand::thread_rng();
+    let bullets: Vec<Bullet> = (0..weapon.pellets)
+      .map(|_| {
+        let angle = rng.gen_range(-weapon.spread..=weapon.spread).to_radians();
+        Bullet {
+          direction: DVec2::from_angle(angle).rotate(player.direction),
+          speed: weapon.projectile_speed,
+          damage: weapon.damage,
+          owner: Some(player.entity),
+        }
+      })
+      .collect();
+
+    let position = player.position;
+    for bullet in bullets {
+      self.entities.spawn_bullet(position, bullet);
+    }
+    self.make_noise(position, noise);
+  }
+
+  ///
+  /// Wakes up every mob close enough to hear a noise. Close enough is counted in steps
+  /// through the map, so a mob right behind a thick wall can sleep through it while one
+  /// down the end of an open corridor can't.
+  ///
+  pub fn make_noise(&mut self, position: DVec2, loudness: u32) {
+    let entities = &mut self.entities;
+    // Walking around walls is never shorter than a straight line, so this catches everyone.
+    let nearby = entities.near(&entities.ais, position, loudness as f64 + 2.0);
+    if nearby.is_empty() {
+      return;
+    }
+
+    let steps = self
+      .map
+      .flood_fill(position.floor().as_ivec2(), loudness, DOOR_NOISE_COST);
+    for (id, _) in nearby {
+      let Some(cell) = entities
+        .position(id)
+        .map(|position| position.floor().as_ivec2())
+      else {
+        continue;
+      };
+      let heard = steps
+        .get(cell.x as usize)
+        .and_then(|column| column.get(cell.y as usize))
+        .is_some_and(Option::is_some);
+      if !heard {
+        continue;
+      }
+      if let Some(ai) = entities.ais.get_mut(id) {
+        ai.state = AiState::Alert;
       }
-      if self.map.data[(self.player.position.x).floor() as usize]
-        [(self.player.position.y - self.player.direction.y * move_speed).floor() as usize]
-        == 0
+    }
+  }
+
+  ///
+  /// Moves projectiles along and resolves hitscan bullets. Anything that hits a wall or a
+  /// collider goes away, and whatever it hit gets hurt.
+  ///
+  fn do_bullets(&mut self, delta: f64) {
+    let entities = &mut self.entities;
+    let mut spent = Vec::new();
+    let mut hits = Vec::new();
+    let mut moves = Vec::new();
+
+    for (id, bullet) in entities.bullets.iter() {
+      let Some(from) = entities.position(id) else {
+        continue;
+      };
+      let reach = match bullet.speed {
+        Some(speed) => speed * delta,
+        None => HITSCAN_RANGE,
+      };
+      let wall = self
+        .map
+        .cast(from, bullet.direction, reach)
+        .map(|(_, distance)| distance);
+      let reach = wall.unwrap_or(reach);
+
+      let colliders = &entities.colliders;
+      let hit = entities
+        .grid
+        .query_ray(from, bullet.direction, reach, |target, center| {
+          if Some(target) == bullet.owner {
+            return None;
+          }
+          let collider = colliders.get(target)?;
+          ray_hits_circle(from, bullet.direction, center, collider.radius)
+        });
+
+      if let Some((target, _)) = hit {
+        hits.push((target, bullet.damage, bullet.owner));
+        spent.push(id);
+      } else if wall.is_some() || bullet.speed.is_none() || self.map.is_wall(from) {
+        spent.push(id);
+      } else {
+        moves.push((id, from + bullet.direction * reach));
+      }
+    }
+
+    for (id, position) in moves {
+      entities.move_to(id, position);
+    }
+    for (target, damage, owner) in hits {
+      // Players get hurt through their Player, their body is just something to hit.
+      if let Some(index) = self
+        .players
+        .iter()
+        .position(|player| player.entity == target)
       {
-        self.player.position.y -= self.player.direction.y * move_speed;
+        let attacker = self
+          .players
+          .iter()
+          .position(|player| Some(player.entity) == owner);
+        self.hurt_player(index, damage, attacker);
+        continue;
       }
-      moving = true;
+
+      let entities = &mut self.entities;
+      let was_alive = entities.is_alive(target);
+      entities.hurt(target, damage);
+      if let (true, Some(position)) = (was_alive, entities.position(target)) {
+        let sound = if entities.is_alive(target) {
+          "mob_hurt"
+        } else {
+          "mob_die"
+        };
+        self.sounds.push(SoundEvent {
+          sound: sound_id(sound),
+          position,
+        });
+      }
+    }
+    for id in spent {
+      self.entities.despawn(id);
+    }
+  }
+
+  ///
+  /// Takes health off a player. In deathmatch whoever got the kill gets the frag, and
+  /// anybody who managed to kill themselves loses one.
+  ///
+  pub fn hurt_player(&mut self, index: usize, damage: i32, attacker: Option<usize>) {
+    let player = &mut self.players[index];
+    if !player.alive {
+      return;
+    }
+    player.health -= damage;
+    let position = player.position;
+    if player.health > 0 {
+      self.play_sound("mob_hurt", position);
+      return;
+    }
+
+    player.alive = false;
+    player.deaths += 1;
+    player.respawn_time = RESPAWN_TIME;
+    let victim = player.name.clone();
+    self.play_sound("mob_die", position);
+    self.sync_body(index);
+
+    if !self.deathmatch {
+      return;
     }
+    match attacker.filter(|attacker| *attacker != index) {
+      Some(attacker) => {
+        self.players[attacker].frags += 1;
+        let message = format!("{} fragged {}.", self.players[attacker].name, victim);
+        self.show_message(&message);
+      }
+      None => {
+        self.players[index].frags -= 1;
+        self.show_message(format!("{} died.", victim).as_str());
+      }
+    }
+  }
 
-    if window.right_down {
-      if self.map.data[(self.player.position.x + self.plane.x * move_speed).floor() as usize]
-        [(self.player.position.y).floor() as usize]
-        == 0
+  ///
+  /// Awake mobs walk straight at the player and stop when they're close enough to touch.
+  /// They shuffle out of each other's way instead of piling up in one spot.
+  ///
+  fn do_ai(&mut self, delta: f64) {
+    let entities = &mut self.entities;
+    let living: Vec<DVec2> = self
+      .players
+      .iter()
+      .filter(|player| player.alive)
+      .map(|player| player.position)
+      .collect();
+
+    let awake: Vec<(EntityId, f64)> = entities
+      .ais
+      .iter()
+      .filter(|(id, ai)| ai.state == AiState::Alert && entities.is_alive(*id))
+      .map(|(id, ai)| (id, ai.speed))
+      .collect();
+
+    for (id, speed) in awake {
+      let Some(transform) = entities.transforms.get_mut(id) else {
+        continue;
+      };
+      let mut position = transform.position;
+      // Whoever's closest, they don't play favourites.
+      let Some(target) = living
+        .iter()
+        .copied()
+        .min_by(|a, b| a.distance(position).total_cmp(&b.distance(position)))
+      else {
+        continue;
+      };
+      let to_player = target - position;
+      transform.yaw = to_player.y.atan2(to_player.x);
+
+      let mut step = DVec2::ZERO;
+      if to_player.length() > MOB_REACH {
+        step = to_player.normalize() * speed * delta;
+      }
+
+      let radius = entities
+        .colliders
+        .get(id)
+        .map_or(0.0, |collider| collider.radius);
+      let colliders = &entities.colliders;
+      let crowding = entities.grid.nearest(position, radius * 2.0, |other| {
+        other != id && colliders.contains(other)
+      });
+      if let Some((other, distance)) = crowding {
+        let other_radius = colliders.get(other).map_or(0.0, |collider| collider.radius);
+        let away = (position - entities.position(other).unwrap_or(position)).normalize_or_zero();
+        step += away * (radius + other_radius - distance).max(0.0) * 0.5;
+      }
+
+      // One axis at a time, so they slide along walls instead of sticking to them.
+      if !self
+        .map
+        .is_wall(DVec2::new(position.x + step.x, position.y))
       {
-        self.player.position.x += self.plane.x * move_speed;
+        position.x += step.x;
       }
-      if self.map.data[(self.player.position.x).floor() as usize]
-        [(self.player.position.y + self.plane.y * move_speed).floor() as usize]
-        == 0
+      if !self
+        .map
+        .is_wall(DVec2::new(position.x, position.y + step.y))
       {
-        self.player.position.y += self.plane.y * move_speed;
+        position.y += step.y;
       }
-      moving = true;
+      entities.move_to(id, position);
     }
+  }
 
-    if window.left_down {
-      if self.map.data[(self.player.position.x - self.plane.x * move_speed).round() as usize]
-        [(self.player.position.y).floor() as usize]
-        == 0
-      {
-        self.player.position.x -= self.plane.x * move_speed;
+  ///
+  /// Counts down lifetimes and gets rid of whatever ran out.
+  ///
+  fn do_lifetimes(&mut self, delta: f64) {
+    let mut expired = Vec::new();
+    for (id, lifetime) in self.entities.lifetimes.iter_mut() {
+      lifetime.time_left -= delta;
+      if lifetime.time_left <= 0.0 {
+        expired.push(id);
       }
-      if self.map.data[(self.player.position.x).floor() as usize]
-        [(self.player.position.y - self.plane.y * move_speed).floor() as usize]
-        == 0
+    }
+    for id in expired {
+      self.entities.despawn(id);
+    }
+  }
+
+  ///
+  /// Runs a function in the level's script, if there is a script.
+  ///
+  fn call_script(&mut self, function: &str, arguments: Vec<Dynamic>) {
+    // Scripts are written around the player, an empty server has nobody to give them.
+    if self.players.is_empty() {
+      return;
+    }
+    if let Some(mut script) = self.script.take() {
+      script.call(self, function, arguments);
+      self.script = Some(script);
+    }
+  }
+
+  pub fn play_sound(&mut self, name: &str, position: DVec2) {
+    self.sounds.push(SoundEvent {
+      sound: sound_id(name),
+      position,
+    });
+  }
+
+  ///
+  /// Clears a door out of the way.
+  ///
+  fn open_door(&mut self, cell: IVec2) {
+    self.map.data[cell.x as usize][cell.y as usize] = 0;
+    self.play_sound("door", cell.as_dvec2() + 0.5);
+  }
+
+  pub fn show_message(&mut self, text: &str) {
+    self.messages.push(Message {
+      text: text.to_string(),
+      time_left: MESSAGE_TIME,
+      to: None,
+    });
+  }
+
+  ///
+  /// A message only one player gets to see.
+  ///
+  pub fn tell(&mut self, player_id: u32, text: &str) {
+    self.messages.push(Message {
+      text: text.to_string(),
+      time_left: MESSAGE_TIME,
+      to: Some(player_id),
+    });
+  }
+
+  ///
+  /// Pressing use on a wall opens it if it's a door, then lets the script know.
+  ///
+  fn use_wall(&mut self, index: usize, cell: IVec2) {
+    if let Some(door) = self
+      .map
+      .doors
+      .iter()
+      .find(|door| door.cell == cell)
+      .copied()
+    {
+      let player = &self.players[index];
+      match door.key {
+        Some(key) if !player.keys.contains(&key) => {
+          let id = player.id;
+          self.tell(id, format!("You need the {} key.", key.name()).as_str());
+        }
+        _ => self.open_door(cell),
+      }
+    }
+    self.used_walls.push(cell);
+
+    self.call_script(
+      "on_use",
+      vec![Dynamic::from(cell.x as i64), Dynamic::from(cell.y as i64)],
+    );
+  }
+
+  ///
+  /// Grabs anything the player is standing on. Health is left alone when you don't need it.
+  ///
+  fn do_pickups(&mut self, index: usize) {
+    let player = &mut self.players[index];
+    if !player.alive {
+      return;
+    }
+    let entities = &self.entities;
+    let mut picked_up = Vec::new();
+    let mut messages = Vec::new();
+
+    for (id, _) in entities.near(&entities.pickups, player.position, PICKUP_DISTANCE) {
+      let Some(pickup) = entities.pickups.get(id) else {
+        continue;
+      };
+
+      let message = match &pickup.kind {
+        ItemKind::Health(amount) => {
+          if player.health >= MAX_HEALTH {
+            continue;
+          }
+          player.health = (player.health + amount).min(MAX_HEALTH);
+          format!("Picked up {} health.", amount)
+        }
+        ItemKind::Ammo(ammo, amount) => {
+          if !player.give_ammo(*ammo, *amount) {
+            continue;
+          }
+          format!("Picked up {} {}.", amount, ammo.name())
+        }
+        ItemKind::Key(key) => {
+          if !player.keys.contains(key) {
+            player.keys.push(*key);
+          }
+          format!("Picked up the {} key.", key.name())
+        }
+        ItemKind::Weapon(id) => {
+          let name = match self.weapons.get(id) {
+            Some(weapon) => {
+              player.give_ammo(weapon.ammo, weapon.pickup_ammo);
+              weapon.name.clone()
+            }
+            None => id.clone(),
+          };
+          if !player.weapons.contains(id) {
+            player.weapons.push(id.clone());
+            player.weapon = id.clone();
+            player.since_shot = f64::INFINITY;
+          }
+          format!("You got the {}!", name)
+        }
+      };
+
+      picked_up.push(id);
+      messages.push(message);
+    }
+
+    let (position, player_id) = (player.position, player.id);
+    if !picked_up.is_empty() {
+      self.play_sound("pickup", position);
+    }
+    for id in picked_up {
+      // Deathmatch would run dry pretty quick if things didn't come back.
+      if let (true, Some(pickup)) = (self.deathmatch, self.entities.pickups.get(id)) {
+        if let Some(item_position) = self.entities.position(id) {
+          self.item_respawns.push(ItemRespawn {
+            time_left: ITEM_RESPAWN_TIME,
+            position: item_position,
+            kind: pickup.kind.clone(),
+            placed: pickup.placed,
+          });
+        }
+      }
+      self.entities.despawn(id);
+    }
+    for message in messages {
+      self.tell(player_id, &message);
+    }
+  }
+
+  ///
+  /// Brings fragged players and picked up items back once their time is up.
+  ///
+  fn do_respawns(&mut self, delta: f64) {
+    if !self.deathmatch {
+      return;
+    }
+
+    for index in 0..self.players.len() {
+      let player = &mut self.players[index];
+      if player.alive {
+        continue;
+      }
+      player.respawn_time -= delta;
+      if player.respawn_time <= 0.0 {
+        self.respawn(index);
+      }
+    }
+
+    for respawn in &mut self.item_respawns {
+      respawn.time_left -= delta;
+    }
+    let (ready, waiting) = std::mem::take(&mut self.item_respawns)
+      .into_iter()
+      .partition(|respawn| respawn.time_left <= 0.0);
+    self.item_respawns = waiting;
+    for respawn in ready {
+      self
+        .entities
+        .spawn_item(respawn.position, respawn.kind, respawn.placed);
+    }
+  }
+
+  ///
+  /// Checks every trigger and runs the actions of the ones that went off.
+  ///
+  fn do_triggers(&mut self) {
+    let player_cells: Vec<IVec2> = self
+      .players
+      .iter()
+      .filter(|player| player.alive)
+      .map(|player| player.position.floor().as_ivec2())
+      .collect();
+    let all_dead = self.entities.mobs().all(|id| !self.entities.is_alive(id));
+
+    let mut actions = Vec::new();
+    for trigger in &mut self.triggers {
+      let holds = match trigger.when {
+        Condition::EnterCell(cell) => player_cells.contains(&cell),
+        Condition::UseWall(cell) => self.used_walls.contains(&cell),
+        Condition::AllMobsDead => all_dead,
+        Condition::Timer(seconds) => self.level_time >= seconds,
+      };
+      if trigger.update(holds) {
+        actions.extend(trigger.actions.iter().cloned());
+      }
+    }
+
+    for action in actions {
+      self.do_action(action);
+    }
+  }
+
+  fn do_action(&mut self, action: Action) {
+    match action {
+      Action::OpenDoor(cell) => self.open_door(cell),
+      Action::SpawnMob(cell) => {
+        self.entities.spawn_mob(cell.as_dvec2() + 0.5);
+      }
+      Action::ChangeWall { cell, wall } => self.map.data[cell.x as usize][cell.y as usize] = wall,
+      Action::ShowMessage(text) => self.show_message(&text),
+      Action::EndLevel => self.exited = true,
+      Action::RunScript(function) => self.call_script(&function, vec![]),
+    }
+  }
+
+  fn do_script(&mut self, delta: f64) {
+    let starting = self.script.as_ref().is_some_and(|script| !script.started);
+    if starting {
+      if let Some(script) = self.script.as_mut() {
+        script.started = true;
+      }
+      self.call_script("on_start", vec![]);
+    }
+    self.call_script("on_tick", vec![Dynamic::from(delta)]);
+  }
+
+  ///
+  /// Counts messages down and drops the ones that have been up long enough.
+  ///
+  pub fn age_messages(&mut self, delta: f64) {
+    for message in &mut self.messages {
+      message.time_left -= delta;
+    }
+    self.messages.retain(|message| message.time_left > 0.0);
+  }
+
+  ///
+  /// Pulls the player back down after a jump.
+  ///
+  fn do_player_gravity(&mut self, index: usize, delta: f64) {
+    let player = &mut self.players[index];
+    if player.on_ground() && player.z_velocity <= 0.0 {
+      return;
+    }
+
+    player.z_velocity -= GRAVITY * delta;
+    player.z += player.z_velocity * delta;
+
+    if player.z <= 0.0 {
+      player.z = 0.0;
+      player.z_velocity = 0.0;
+    }
+  }
+
+  ///
+  /// Just the moving, looking and weapon switching part of a player's input. None of it
+  /// touches anything but the player, so a network client can run it ahead of the server
+  /// and get the same answer.
+  ///
+  pub fn predict(&mut self, index: usize, input: &PlayerInput, delta: f64) {
+    if !self.players[index].alive {
+      return;
+    }
+    self.do_player_controls(index, input, delta);
+    self.do_player_gravity(index, delta);
+    self.do_weapon_switching(index, input);
+  }
+
+  ///
+  /// Everything one player does with one tick's worth of input.
+  ///
+  pub fn run_input(&mut self, index: usize, input: &PlayerInput, delta: f64) {
+    self.predict(index, input, delta);
+    self.do_weapon_firing(index, input, delta);
+    self.do_pickups(index);
+
+    let player = &self.players[index];
+    if input.use_wall && player.alive {
+      if let Some(cell) = self
+        .map
+        .first_wall(player.position, player.direction, USE_DISTANCE)
       {
-        self.player.position.y -= self.plane.y * move_speed;
+        self.use_wall(index, cell);
       }
-      moving = true;
     }
+    self.sync_body(index);
+  }
+
+  ///
+  /// Everything that happens whether anybody presses anything or not.
+  ///
+  pub fn update(&mut self, delta: f64) {
+    self.do_bullets(delta);
+    self.do_ai(delta);
+    self.do_lifetimes(delta);
+    self.do_respawns(delta);
 
-    // println!("{}", window.mouse_delta);
+    self.level_time += delta;
+    let on_exit = self
+      .players
+      .iter()
+      .any(|player| player.alive && self.map.exits.contains(&player.position.floor().as_ivec2()));
+    if on_exit && !self.deathmatch {
+      self.exited = true;
+    }
 
-    let mouse_delta = window.mouse_delta;
-    let rot_speed = mouse_delta.x;
-    let old_dir_x = self.player.direction.x;
-    self.player.direction.x =
-      self.player.direction.x * (-rot_speed).cos() - self.player.direction.y * (-rot_speed).sin();
-    self.player.direction.y =
-      old_dir_x * (-rot_speed).sin() + self.player.direction.y * (-rot_speed).cos();
-    let old_plane_x = self.plane.x.clone();
-    self.plane.x = self.plane.x * (-rot_speed).cos() - self.plane.y * (-rot_speed).sin();
-    self.plane.y = old_plane_x * (-rot_speed).sin() + self.plane.y * (-rot_speed).cos();
+    self.do_triggers();
+    self.used_walls.clear();
+    self.do_script(delta);
+    self.age_messages(delta);
   }
 
   ///
-  /// What the world will do on each tick.
+  /// What the world will do on each tick. Inputs go to the players in the same order.
   ///
-  pub fn on_tick(&mut self, delta: f64, window: &WinHandler) {
+  pub fn on_tick(&mut self, delta: f64, inputs: &[PlayerInput]) {
     // println!("tick tock {}", delta)
 
-    self.do_player_controls(delta, window);
+    for (index, input) in inputs.iter().enumerate().take(self.players.len()) {
+      self.run_input(index, input, delta);
+    }
+    self.update(delta);
   }
 }
+
+///
+/// A single player world on one of the shipped levels, with the shipped weapons.
+///
+#[cfg(test)]
+pub(crate) fn test_world(level: &str) -> World {
+  let level = Level::load(level).unwrap();
+  let weapons = Weapons::load(&Weapons::default_path()).unwrap();
+  World::new(&level, &Settings::default(), &weapons)
+}
@@ -0,0 +1,8 @@
+// The game lives in here so both binaries can get at it. main.rs is the game itself,
+// bin/boom_server.rs is the dedicated server. The spatial grid is out here for
+// benches/spatial.rs.
+mod boom;
+
+#[cfg(feature = "client")]
+pub use boom::Boom;
+pub use boom::{dedicated, entity::EntityId, spatial::SpatialGrid};
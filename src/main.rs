@@ -2,8 +2,6 @@ use std::{cell::RefCell, rc::Rc};
 
 use boom::Boom;
 
-mod boom;
-
 fn main() {
   // Move Boom into the heap. Then run it.
   Rc::new(RefCell::new(Boom::new()))